@@ -1 +1 @@
-(export "std/arith" "std/bool" "std/list")
+(export "std/arith" "std/bool" "std/control" "std/list")
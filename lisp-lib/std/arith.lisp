@@ -1,11 +1,3 @@
-(def >= (lambda (a b) (eq (- b a) 0)))
-(def <= (lambda (a b) (eq (- a b) 0)))
-
-(def < (lambda (a b) (if (eq (- b a) 0) false true)))
-(def > (lambda (a b) (if (eq (- a b) 0) false true)))
-
-(export < <= >= >)
-
 (assert (eq (< 1 2) true))
 (assert (eq (< 2 1) false))
 (assert (eq (< 0 0) false))
@@ -24,6 +16,7 @@
 
 
 
+;; n! for a natural number `n`.
 (defrec factorial (n) (if (eq n 0) 1 (* n (factorial (- n 1)))))
 
 (export factorial)
@@ -37,13 +30,14 @@
 
 
 
+;; Whether `n` has no divisor from `k` up to its square root.
 (defrec _is_prime_sub (n k)
-  (if (> (* k k) n)
-    true
-    (if (eq (% n k) 0)
-      false
-      (_is_prime_sub n (+ k 1)))))
+  (cond
+    ((> (* k k) n) true)
+    ((eq (% n k) 0) false)
+    (else (_is_prime_sub n (+ k 1)))))
 
+;; Whether the integer `n` is a prime number.
 (def is_prime (lambda (n) (if (< n 2) false (_is_prime_sub n 2))))
 
 (export is_prime)
@@ -1,9 +1,12 @@
+;; Boolean operators on `true` and `false`.
+;; `and2` and `or2` are the special forms `(and ..)` and `(or ..)` of 2 args as functions.
+;; Unlike the special forms, they evaluate all args, but can be passed as values.
 (def nand (lambda (a b) (if a (if b false true) true)))
 (def not (lambda (a) (if a false true)))
-(def and (lambda (a b) (if a (if b true false) false)))
-(def or (lambda (a b) (if a true (if b true false))))
+(def and2 (lambda (a b) (if a (if b true false) false)))
+(def or2 (lambda (a b) (if a true (if b true false))))
 (def nor (lambda (a b) (if a false (if b false true))))
 (def xor (lambda (a b) (if a (if b false true) (if b true false))))
 (def nxor (lambda (a b) (if a (if b true false) (if b false true))))
 
-(export nand not and or nor xor nxor)
+(export nand not and2 or2 nor xor nxor)
@@ -0,0 +1,10 @@
+;; Conditional macros with one branch, nil if the branch is not taken.
+(defmacro when (c body) (list (quote if) c body nil))
+(defmacro unless (c body) (list (quote if) c nil body))
+
+(export when unless)
+
+(assert (eq (when true 1) 1))
+(assert (eq (when false 1) nil))
+(assert (eq (unless false 1) 1))
+(assert (eq (unless true 1) nil))
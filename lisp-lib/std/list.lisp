@@ -1,5 +1,6 @@
+;; Number of elements of the list `xs`.
 (defrec len (xs)
-  (if (eq xs nil)
+  (if (nil? xs)
     0
     (+ (len (cdr xs)) 1)))
 
@@ -14,6 +15,7 @@
 
 
 
+;; The `i`-th element of the list `xs`, 0-indexed.
 (defrec index (i xs)
   (if (eq i 0)
     (car xs)
@@ -29,18 +31,33 @@ index
 
 
 
+;; The list `xs` followed by the value `v`.
 (defrec append (xs v)
-  (if (eq xs nil)
-    (list v)
-    (cons (car xs) (append (cdr xs) v))))
+  (match xs
+    (nil (list v))
+    ((cons h t) (cons h (append t v)))))
 
+;; The list `xs` followed by the elements of the list `ys`.
 (defrec join (xs ys)
-  (if (eq ys nil)
-    xs
-    (join (append xs (car ys)) (cdr ys))))
+  (match ys
+    (nil xs)
+    ((cons h t) (join (append xs h) t))))
 
 (export index)
 
 (assert (eq (index 0 (list 1 2 3 4 5)) 1))
 (assert (eq (index 2 (list 1 2 3 4 5)) 3))
 (assert (eq (index 3 (list 1 2 3 4 5)) 4))
+
+
+
+(defrec take (n xs)
+  (if (eq n 0)
+    nil
+    (cons (car xs) (take (- n 1) (cdr xs)))))
+
+(defrec iterate (f x) (cons x (iterate f (f x))))
+
+;; Components of cons taken by car / cdr are evaluated only with `--strategy value` or `need`,
+;; so these are useful there.
+(export take iterate)
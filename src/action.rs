@@ -1 +1,26 @@
+use crate::common::resolver::ContentResolver;
+use crate::corelang::eval::EvalError;
+use crate::resolver::lib::LibResolver;
+
+pub mod debug;
+pub mod expand;
+pub mod repl;
 pub mod run;
+
+/// Prints `e` with the source lines it points at.
+/// sources: (filepath, content) of files not on the file system, e.g. repl inputs.
+/// Other files are read from the file system or the built-in lib.
+pub(crate) fn print_error(e: &EvalError, sources: &[(&str, &str)], color: bool) {
+    let source_of = |filepath: &str| {
+        if let Some((_, content)) = sources.iter().find(|(path, _)| *path == filepath) {
+            return Some(content.to_string());
+        }
+        std::fs::read_to_string(filepath).ok().or_else(|| {
+            LibResolver::default()
+                .resolve(&None, &filepath.into())
+                .ok()
+                .map(|c| c.content)
+        })
+    };
+    print!("{}", e.diagnostic().render(&source_of, color));
+}
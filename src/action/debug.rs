@@ -0,0 +1,398 @@
+use super::print_error;
+use crate::common::fileinfo::{CompileError, FileInfo};
+use crate::corelang::eval::{EvalError, EvalLimit, EvalStrategy};
+use crate::corelang::printer::simple::SimplePrinter as CorePrinter;
+use crate::corelang::trace::{TraceStep, Tracer};
+use crate::corelang::value::{Closure, Code, Env, Thunk};
+use crate::metalang::eval::MetaEvaluator;
+use crate::metalang::parser::MetaParser;
+use crate::metalang::syntax::{DefRecFun, MetaStatement, MetaTerm};
+use crate::resolver::fs::FsResolver;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
+
+const HELP: &str = "\
+break <file>:<line>   stop at applications starting at <line> of <file>
+delete <file>:<line>  remove the breakpoint
+breakpoints           list breakpoints
+step                  stop at the next application, stepping into the current one
+next                  stop at the next application not nested in the current one
+                      (tail calls in the current one are not nested)
+continue              run until a breakpoint is hit
+redex                 show the current application
+bindings              show arguments bound in the current environment
+quit                  stop debugging";
+
+#[derive(Clone, Debug, PartialEq)]
+struct Breakpoint {
+    filepath: String,
+    /// 1-based line number
+    line: usize,
+}
+
+impl Breakpoint {
+    fn parse(s: &str) -> Option<Self> {
+        let (filepath, line) = s.rsplit_once(':')?;
+        Some(Self {
+            filepath: filepath.into(),
+            line: line.parse().ok().filter(|line| *line > 0)?,
+        })
+    }
+
+    fn hit(&self, info: &Option<FileInfo>) -> bool {
+        match info {
+            Some(info) => {
+                info.range.from.line + 1 == self.line
+                    && Path::new(&info.filepath).ends_with(&self.filepath)
+            }
+            None => false,
+        }
+    }
+}
+
+/// When to stop at the next application.
+enum Mode {
+    Step,
+    /// stop at the depth or shallower
+    Next(usize),
+    Continue,
+}
+
+/// Tracer which stops at applications and waits for commands from `input`.
+pub struct Debugger {
+    input: Box<dyn BufRead>,
+    output: Rc<RefCell<dyn Write>>,
+    /// (file info of lambda, arg names) in the debugged file
+    name_vec: Vec<(FileInfo, Vec<String>)>,
+    breakpoint_vec: Vec<Breakpoint>,
+    mode: Mode,
+}
+
+/// Arg names of the lambdas made by defrec or letrec for `fun_vec`.
+fn rec_arg_names(fun_vec: &[DefRecFun]) -> Vec<(FileInfo, Vec<String>)> {
+    let fun_name_vec: Vec<String> = fun_vec.iter().map(|fun| fun.name.clone()).collect();
+    let mut name_vec = Vec::new();
+    for fun in fun_vec {
+        // the lambda defined and the inner lambda taking all functions first
+        if let Some(info) = &fun.info {
+            name_vec.push((info.clone(), fun.arg_name_vec.clone()));
+        }
+        if let Some(name_info) = &fun.name_info {
+            let mut inner_name_vec = fun_name_vec.clone();
+            inner_name_vec.extend(fun.arg_name_vec.iter().cloned());
+            name_vec.push((name_info.clone(), inner_name_vec));
+        }
+    }
+    name_vec
+}
+
+/// Arg names of every lambda in `stmt_vec`, including ones made by defrec, let, letrec and match.
+fn collect_arg_names(stmt_vec: &[MetaStatement]) -> Vec<(FileInfo, Vec<String>)> {
+    let mut name_vec = Vec::new();
+    let mut term_vec: Vec<&MetaTerm> = Vec::new();
+    for stmt in stmt_vec {
+        match stmt {
+            MetaStatement::Def(_, _, term)
+            | MetaStatement::Term(_, term)
+            | MetaStatement::Assert(_, term) => term_vec.push(term),
+            MetaStatement::DefRec(_, fun_vec) => {
+                name_vec.extend(rec_arg_names(fun_vec));
+                term_vec.extend(fun_vec.iter().map(|fun| &fun.term));
+            }
+            _ => {}
+        }
+    }
+    while let Some(term) = term_vec.pop() {
+        match term {
+            MetaTerm::Lambda(Some(info), arg_name_vec, _) => {
+                name_vec.push((info.clone(), arg_name_vec.clone()));
+            }
+            // let* is not here since its nested lambdas of one arg cannot be told apart
+            MetaTerm::Let(Some(info), binding_vec, _) => {
+                let arg_name_vec = binding_vec.iter().map(|(name, _)| name.clone()).collect();
+                name_vec.push((info.clone(), arg_name_vec));
+            }
+            MetaTerm::LetRec(Some(info), fun_vec, _) => {
+                let fun_name_vec = fun_vec.iter().map(|fun| fun.name.clone()).collect();
+                name_vec.push((info.clone(), fun_name_vec));
+                name_vec.extend(rec_arg_names(fun_vec));
+            }
+            MetaTerm::Match(_, _, clause_vec) => {
+                for (pattern, _) in clause_vec {
+                    if let Some(info) = pattern.file_info() {
+                        let arg_name_vec = pattern.binding_names().into_iter().cloned().collect();
+                        name_vec.push((info.clone(), arg_name_vec));
+                    }
+                }
+            }
+            _ => {}
+        }
+        term_vec.extend(term.subterms());
+    }
+    name_vec
+}
+
+impl Debugger {
+    pub fn new(
+        stmt_vec: &[MetaStatement],
+        input: Box<dyn BufRead>,
+        output: Rc<RefCell<dyn Write>>,
+    ) -> Self {
+        Self {
+            input,
+            output,
+            name_vec: collect_arg_names(stmt_vec),
+            breakpoint_vec: Vec::new(),
+            mode: Mode::Continue,
+        }
+    }
+
+    fn say(&self, message: &str) {
+        writeln!(self.output.borrow_mut(), "{}", message).unwrap();
+    }
+
+    /// Arg names of the lambda at `info` with `arg_num` args.
+    /// Lambdas outside of the debugged file get `arg0`, `arg1`, ...
+    fn arg_names(&self, info: &Option<FileInfo>, arg_num: usize) -> Vec<String> {
+        info.as_ref()
+            .and_then(|info| {
+                self.name_vec
+                    .iter()
+                    .find(|(i, _)| i.filepath == info.filepath && i.range == info.range)
+            })
+            .map(|(_, name_vec)| name_vec.clone())
+            .filter(|name_vec| name_vec.len() == arg_num)
+            .unwrap_or_else(|| (0..arg_num).map(|a| format!("arg{}", a)).collect())
+    }
+
+    /// Prints `code` with variables named after the args of lambdas.
+    /// scope_vec: arg names of the enclosing lambdas, innermost last
+    fn print_code(&self, code: &Code, scope_vec: &mut Vec<Vec<String>>) -> String {
+        match code {
+            Code::Apply(_, c1, cs) => {
+                let mut s_vec = vec![self.print_code(c1, scope_vec)];
+                s_vec.extend(cs.iter().map(|c| self.print_code(c, scope_vec)));
+                format!("({})", s_vec.join(" "))
+            }
+            Code::Lambda(info, arg_num, body) => {
+                let name_vec = self.arg_names(info, *arg_num);
+                let args = name_vec.join(" ");
+                scope_vec.push(name_vec);
+                let body = self.print_code(body, scope_vec);
+                scope_vec.pop();
+                format!("(lambda ({}) {})", args, body)
+            }
+            Code::Quote(_, c1) => format!("(quote {})", self.print_code(c1, scope_vec)),
+            Code::Variable(_, v, a) => scope_vec
+                .len()
+                .checked_sub(v + 1)
+                .and_then(|i| scope_vec[i].get(*a))
+                .cloned()
+                .unwrap_or_else(|| format!("ARG<{}-up {}-th>", v, a)),
+            Code::If(_, c1, c2, c3) => format!(
+                "(if {} {} {})",
+                self.print_code(c1, scope_vec),
+                self.print_code(c2, scope_vec),
+                self.print_code(c3, scope_vec)
+            ),
+            Code::Cons(_, c1, c2) => format!(
+                "(cons {} {})",
+                self.print_code(c1, scope_vec),
+                self.print_code(c2, scope_vec)
+            ),
+            Code::Atom(term) => CorePrinter::default().print(term),
+        }
+    }
+
+    /// Arg names of frames in `env`, outermost first.
+    fn scope_vec(&self, env: &Env) -> Vec<Vec<String>> {
+        let mut scope_vec = Vec::new();
+        let mut frame = env.as_ref();
+        while let Some(f) = frame {
+            scope_vec.push(self.arg_names(&f.info, f.arg_vec.len()));
+            frame = f.parent.as_ref();
+        }
+        scope_vec.reverse();
+        scope_vec
+    }
+
+    fn render(&self, closure: &Closure) -> String {
+        self.print_code(&closure.code, &mut self.scope_vec(&closure.env))
+    }
+
+    fn print_bindings(&self, env: &Env) {
+        let mut frame = env.as_ref();
+        let mut i = 0;
+        while let Some(f) = frame {
+            let name_vec = self.arg_names(&f.info, f.arg_vec.len());
+            match &f.info {
+                Some(info) => self.say(&format!("#{} lambda at {}", i, info)),
+                None => self.say(&format!("#{} lambda", i)),
+            }
+            for (name, arg) in name_vec.iter().zip(f.arg_vec.iter()) {
+                let value = match &*arg.borrow() {
+                    Thunk::Forced(value) => self.render(value),
+                    Thunk::Delayed(arg) => format!("<delayed> {}", self.render(arg)),
+                };
+                self.say(&format!("  {} = {}", name, value));
+            }
+            frame = f.parent.as_ref();
+            i += 1;
+        }
+        if i == 0 {
+            self.say("no bindings");
+        }
+    }
+
+    fn print_stop(&self, closure: &Closure) {
+        match closure.code.file_info() {
+            Some(info) => self.say(&format!("stopped at {}", info)),
+            None => self.say("stopped"),
+        }
+        self.say(&format!("  {}", self.render(closure)));
+    }
+
+    /// Reads commands until one resuming the evaluation.
+    /// current: the application stopped at and its depth, or None before the evaluation starts
+    /// Returns false if debugging is quit.
+    pub fn prompt(&mut self, current: Option<(&Closure, usize)>) -> bool {
+        loop {
+            write!(self.output.borrow_mut(), "(debug) ").unwrap();
+            self.output.borrow_mut().flush().unwrap();
+            let mut line = String::new();
+            if self.input.read_line(&mut line).unwrap() == 0 {
+                return false;
+            }
+            let line = line.trim();
+            let (command, rest) = match line.split_once(char::is_whitespace) {
+                Some((command, rest)) => (command, rest.trim()),
+                None => (line, ""),
+            };
+            match (command, current) {
+                ("", _) => {}
+                ("help" | "h", _) => self.say(HELP),
+                ("break" | "b", _) => match Breakpoint::parse(rest) {
+                    Some(breakpoint) => {
+                        self.say(&format!(
+                            "breakpoint at {}:{}",
+                            breakpoint.filepath, breakpoint.line
+                        ));
+                        self.breakpoint_vec.push(breakpoint);
+                    }
+                    None => self.say("expected <file>:<line>"),
+                },
+                ("delete" | "d", _) => match Breakpoint::parse(rest) {
+                    Some(breakpoint) if self.breakpoint_vec.contains(&breakpoint) => {
+                        self.breakpoint_vec.retain(|b| *b != breakpoint)
+                    }
+                    _ => self.say(&format!("no breakpoint at {}", rest)),
+                },
+                ("breakpoints", _) => {
+                    for breakpoint in self.breakpoint_vec.iter() {
+                        self.say(&format!("{}:{}", breakpoint.filepath, breakpoint.line));
+                    }
+                }
+                ("step" | "s", _) => {
+                    self.mode = Mode::Step;
+                    return true;
+                }
+                ("next" | "n", _) => {
+                    self.mode = match current {
+                        Some((_, depth)) => Mode::Next(depth),
+                        None => Mode::Step,
+                    };
+                    return true;
+                }
+                ("continue" | "c", _) => {
+                    self.mode = Mode::Continue;
+                    return true;
+                }
+                ("quit" | "q", _) => return false,
+                ("redex" | "r", Some((closure, _))) => self.say(&self.render(closure)),
+                ("bindings" | "env", Some((closure, _))) => self.print_bindings(&closure.env),
+                ("redex" | "r" | "bindings" | "env", None) => self.say("not running"),
+                _ => self.say(&format!("unknown command \"{}\". see help", command)),
+            }
+        }
+    }
+}
+
+impl Tracer for Debugger {
+    fn accepts(&self, _depth: usize) -> bool {
+        false
+    }
+
+    fn trace(&mut self, _step: &TraceStep) {}
+
+    fn apply(&mut self, closure: &Closure, depth: usize) -> Result<(), EvalError> {
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(next_depth) => depth <= next_depth,
+            Mode::Continue => self
+                .breakpoint_vec
+                .iter()
+                .any(|breakpoint| breakpoint.hit(closure.code.file_info())),
+        };
+        if !stop {
+            return Ok(());
+        }
+        self.print_stop(closure);
+        if self.prompt(Some((closure, depth))) {
+            Ok(())
+        } else {
+            Err(EvalError::Abort(CompileError {
+                info: closure.code.file_info().clone(),
+                message: Some("evaluation quit by debugger".into()),
+            }))
+        }
+    }
+}
+
+/// Evaluates `source` under the debugger reading commands from `input`.
+pub fn debug_source(
+    filepath: String,
+    source: &str,
+    limit: EvalLimit,
+    strategy: EvalStrategy,
+    input: Box<dyn BufRead>,
+    output: Rc<RefCell<dyn Write>>,
+) -> Result<(), EvalError> {
+    let mut p = MetaParser::new(filepath, source.chars().collect());
+    let stmt_vec = p.parse_stmt_vec().map_err(EvalError::Parse)?;
+    let mut debugger = Debugger::new(&stmt_vec, input, output.clone());
+    if !debugger.prompt(None) {
+        return Ok(());
+    }
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.set_resolver(Box::new(FsResolver::default()));
+    evaluator.do_assert = true;
+    evaluator.limit = limit;
+    evaluator.strategy = strategy;
+    evaluator.tracer = Some(Box::new(debugger));
+    for (i, stmt) in stmt_vec.iter().enumerate() {
+        if let Some(term) = evaluator.eval(stmt)? {
+            let cp = CorePrinter::default();
+            writeln!(output.borrow_mut(), "Out[{}] = {}", i, cp.print(&term)).unwrap();
+        }
+    }
+    Ok(())
+}
+
+pub fn debug(filepath: String, limit: EvalLimit, strategy: EvalStrategy, color: bool) {
+    let mut content = String::new();
+    File::open(&filepath)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    let input = Box::new(io::BufReader::new(io::stdin()));
+    let output = Rc::new(RefCell::new(io::stdout()));
+    if let Err(e) = debug_source(filepath.clone(), &content, limit, strategy, input, output) {
+        print_error(&e, &[(&filepath, &content)], color);
+        std::process::exit(1);
+    }
+}
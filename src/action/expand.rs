@@ -0,0 +1,59 @@
+use super::print_error;
+use crate::corelang::eval::{EvalError, EvalLimit, EvalStrategy};
+use crate::metalang::eval::MetaEvaluator;
+use crate::metalang::parser::MetaParser;
+use crate::metalang::printer::simple::SimplePrinter as MetaPrinter;
+use crate::metalang::syntax::MetaStatement;
+use crate::resolver::fs::FsResolver;
+
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Statements of `source` with macro calls expanded, printed one per line.
+/// Definitions and imports are evaluated so that later statements can use the macros,
+/// but terms and assertions are not.
+pub fn expand_source(
+    filepath: String,
+    source: &str,
+    limit: EvalLimit,
+    strategy: EvalStrategy,
+) -> Result<Vec<String>, EvalError> {
+    let mut p = MetaParser::new(filepath, source.chars().collect());
+    let stmt_vec = p.parse_stmt_vec().map_err(EvalError::Parse)?;
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.set_resolver(Box::new(FsResolver::default()));
+    evaluator.limit = limit;
+    evaluator.strategy = strategy;
+    let mp = MetaPrinter::default();
+    let mut line_vec = Vec::new();
+    for stmt in stmt_vec.iter() {
+        let expanded = evaluator.expand_stmt(stmt)?;
+        line_vec.push(mp.print_stmt(&expanded));
+        if !matches!(
+            expanded,
+            MetaStatement::Term(..) | MetaStatement::Assert(..)
+        ) {
+            evaluator.eval(&expanded)?;
+        }
+    }
+    Ok(line_vec)
+}
+
+pub fn expand(filepath: String, limit: EvalLimit, strategy: EvalStrategy, color: bool) {
+    let mut content = String::new();
+    File::open(&filepath)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    match expand_source(filepath.clone(), &content, limit, strategy) {
+        Ok(line_vec) => {
+            for line in line_vec {
+                println!("{}", line);
+            }
+        }
+        Err(e) => {
+            print_error(&e, &[(&filepath, &content)], color);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use super::print_error;
+use crate::common::fileinfo::CompileError;
+use crate::corelang::eval::{EvalError, EvalLimit, EvalStrategy};
+use crate::corelang::printer::simple::SimplePrinter as CorePrinter;
+use crate::corelang::trace::{PrintTracer, TraceOption};
+use crate::metalang::eval::MetaEvaluator;
+use crate::metalang::parser::MetaParser;
+use crate::metalang::printer::simple::SimplePrinter as MetaPrinter;
+use crate::metalang::syntax::MetaBinding;
+use crate::resolver::fs::FsResolver;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+const HELP: &str = "\
+:help           show this help
+:env            list variables defined in the current environment
+:reset          discard all definitions and start over
+:load <file>    evaluate all statements in <file> into the current environment
+:core <expr>    show the Core LambdaLISP term transformed from <expr>
+:expand <expr>  show <expr> with macro calls expanded
+:history        show inputs entered so far
+:quit           exit the repl";
+
+/// Whether `input` waits for more lines.
+/// Commands are always one line. Terms wait for closing parentheses.
+pub(crate) fn is_incomplete(input: &str) -> bool {
+    !input.trim_start().starts_with(':') && open_parenthesis_count(input) > 0
+}
+
+/// Counts how many parentheses are left open in `source`.
+/// Parentheses in string literals, char literals and comments are ignored.
+fn open_parenthesis_count(source: &str) -> isize {
+    let mut count = 0;
+    let mut in_string = false;
+    let mut in_line_comment = false;
+    let mut block_comment_depth = 0;
+    let mut escaped = false;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        if in_line_comment {
+            in_line_comment = c != '\n';
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('#', Some('|')) => {
+                chars.next();
+                block_comment_depth += 1;
+            }
+            ('|', Some('#')) if block_comment_depth > 0 => {
+                chars.next();
+                block_comment_depth -= 1;
+            }
+            _ if block_comment_depth > 0 => {}
+            // parentheses of the datum commented out are still balanced
+            ('#', Some(';')) => {
+                chars.next();
+            }
+            // e.g. `#\(`
+            ('#', Some('\\')) => {
+                chars.next();
+                chars.next();
+            }
+            ('"', _) => in_string = true,
+            (';', _) => in_line_comment = true,
+            ('(', _) => count += 1,
+            (')', _) => count -= 1,
+            _ => {}
+        }
+    }
+    count
+}
+
+pub struct Repl {
+    evaluator: MetaEvaluator,
+    history: Vec<String>,
+    /// (filepath, content) of the inputs parsed so far, e.g. `<repl:3>` for the input 3 in history,
+    /// so that errors can point at terms defined by earlier inputs
+    sources: Vec<(String, String)>,
+    /// number of statements evaluated, used for In/Out numbering
+    count: usize,
+    verbose: bool,
+    color: bool,
+}
+
+fn new_evaluator(
+    limit: &EvalLimit,
+    strategy: EvalStrategy,
+    trace: &Option<TraceOption>,
+) -> MetaEvaluator {
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.set_resolver(Box::new(FsResolver::default()));
+    evaluator.do_assert = true;
+    evaluator.limit = limit.clone();
+    evaluator.strategy = strategy;
+    evaluator.tracer = trace
+        .clone()
+        .map(|option| Box::new(PrintTracer::new(option)) as _);
+    evaluator
+}
+
+impl Repl {
+    pub fn new(
+        verbose: bool,
+        limit: EvalLimit,
+        strategy: EvalStrategy,
+        trace: Option<TraceOption>,
+        color: bool,
+    ) -> Self {
+        Self {
+            evaluator: new_evaluator(&limit, strategy, &trace),
+            history: Vec::new(),
+            sources: Vec::new(),
+            count: 0,
+            verbose,
+            color,
+        }
+    }
+
+    pub fn evaluator(&self) -> &MetaEvaluator {
+        &self.evaluator
+    }
+
+    /// Evaluates every statement in `source` one by one.
+    /// Nothing is evaluated if `source` has parse errors, and all of them are returned.
+    /// Otherwise stops at the first error, but keeps definitions made before it.
+    fn eval_source(&mut self, filepath: String, source: &str) -> Result<(), Vec<EvalError>> {
+        let mut p = MetaParser::new(filepath, source.chars().collect());
+        let (stmt_vec, error_vec) = p.parse_stmt_vec_recovering();
+        if !error_vec.is_empty() {
+            return Err(error_vec.into_iter().map(EvalError::Parse).collect());
+        }
+        for stmt in stmt_vec.iter() {
+            let i = self.count;
+            self.count += 1;
+            if self.verbose {
+                let mp = MetaPrinter::default();
+                println!(" In[{}] = {}", i, mp.print_stmt(stmt));
+            }
+            if let Some(term) = self.evaluator.eval(stmt).map_err(|e| vec![e])? {
+                let cp = CorePrinter::default();
+                println!("Out[{}] = {}", i, cp.print(&term));
+            }
+        }
+        Ok(())
+    }
+
+    fn print_core(&self, filepath: String, source: &str) -> Result<(), EvalError> {
+        let mut p = MetaParser::new(filepath, source.chars().collect());
+        let term = p.parse_term().map_err(EvalError::Parse)?;
+        let cp = CorePrinter::default();
+        println!("{}", cp.print(&self.evaluator.transform(&term)?));
+        Ok(())
+    }
+
+    fn print_expanded(&self, filepath: String, source: &str) -> Result<(), EvalError> {
+        let mut p = MetaParser::new(filepath, source.chars().collect());
+        let term = p.parse_term().map_err(EvalError::Parse)?;
+        let mp = MetaPrinter::default();
+        println!("{}", mp.print_term(&self.evaluator.expand(&term)?));
+        Ok(())
+    }
+
+    fn print_env(&self) {
+        let cp = CorePrinter::default();
+        let mut name_vec: Vec<&String> = self.evaluator.env.keys().collect();
+        name_vec.sort();
+        for name in name_vec {
+            match &self.evaluator.env[name] {
+                MetaBinding::Value(term) => println!("{} = {}", name, cp.print(term)),
+                MetaBinding::Macro(term) => println!("{} = macro {}", name, cp.print(term)),
+            }
+        }
+    }
+
+    fn load(&mut self, filepath: &str) -> Result<(), Vec<EvalError>> {
+        let mut content = String::new();
+        if File::open(filepath)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .is_err()
+        {
+            return Err(vec![EvalError::Resolve(CompileError {
+                info: None,
+                message: Some(format!("could not read \"{}\"", filepath)),
+            })]);
+        }
+        self.eval_source(filepath.into(), &content)
+    }
+
+    /// Handles one complete input.
+    /// Returns false if the repl should exit.
+    pub fn handle(&mut self, input: &str) -> bool {
+        let input = input.trim();
+        if input.is_empty() {
+            return true;
+        }
+        let filepath = format!("<repl:{}>", self.history.len());
+        self.history.push(input.into());
+        let (command, rest) = match input.split_once(char::is_whitespace) {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (input, ""),
+        };
+        let mut add_source = |source: &str| {
+            self.sources.push((filepath.clone(), source.into()));
+            filepath.clone()
+        };
+        let result = match command {
+            ":quit" | ":q" => return false,
+            ":help" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            ":env" => {
+                self.print_env();
+                Ok(())
+            }
+            ":reset" => {
+                self.evaluator.reset();
+                self.count = 0;
+                Ok(())
+            }
+            ":history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", i, line);
+                }
+                Ok(())
+            }
+            ":load" => self.load(rest),
+            ":core" => {
+                let filepath = add_source(rest);
+                self.print_core(filepath, rest).map_err(|e| vec![e])
+            }
+            ":expand" => {
+                let filepath = add_source(rest);
+                self.print_expanded(filepath, rest).map_err(|e| vec![e])
+            }
+            _ if command.starts_with(':') => Err(vec![EvalError::Parse(CompileError {
+                info: None,
+                message: Some(format!("unknown command \"{}\". see :help", command)),
+            })]),
+            _ => {
+                let filepath = add_source(input);
+                self.eval_source(filepath, input)
+            }
+        };
+        if let Err(error_vec) = result {
+            let source_vec: Vec<(&str, &str)> = self
+                .sources
+                .iter()
+                .map(|(filepath, content)| (filepath.as_str(), content.as_str()))
+                .collect();
+            for e in error_vec.iter() {
+                print_error(e, &source_vec, self.color);
+            }
+        }
+        true
+    }
+}
+
+pub fn repl(
+    verbose: bool,
+    limit: EvalLimit,
+    strategy: EvalStrategy,
+    trace: Option<TraceOption>,
+    color: bool,
+) {
+    let mut repl = Repl::new(verbose, limit, strategy, trace, color);
+    let stdin = io::stdin();
+    let mut buf = String::new();
+    loop {
+        print!("{}", if buf.is_empty() { "> " } else { ". " });
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        buf.push_str(&line);
+        if is_incomplete(&buf) {
+            continue;
+        }
+        let input = std::mem::take(&mut buf);
+        if !repl.handle(&input) {
+            break;
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use super::print_error;
+use crate::corelang::eval::{EvalError, EvalLimit, EvalStrategy};
 use crate::corelang::printer::simple::SimplePrinter as CorePrinter;
+use crate::corelang::trace::{PrintTracer, TraceOption};
 use crate::metalang::eval::MetaEvaluator;
 use crate::metalang::parser::MetaParser;
 use crate::metalang::printer::simple::SimplePrinter as MetaPrinter;
@@ -7,18 +10,43 @@ use crate::resolver::fs::FsResolver;
 use std::fs::File;
 use std::io::prelude::*;
 
-pub fn run(filepath: String, verbose: bool, do_assert: bool) {
+pub fn run(
+    filepath: String,
+    verbose: bool,
+    do_assert: bool,
+    limit: EvalLimit,
+    strategy: EvalStrategy,
+    trace: Option<TraceOption>,
+    color: bool,
+) {
     let mut content = String::new();
     File::open(&filepath)
         .unwrap()
         .read_to_string(&mut content)
         .unwrap();
-    let mut p = MetaParser::new(filepath.into(), content.chars().collect());
+    let mut p = MetaParser::new(filepath.clone(), content.chars().collect());
+    let exit_with_error = |e: EvalError| -> ! {
+        print_error(&e, &[(&filepath, &content)], color);
+        std::process::exit(1);
+    };
 
-    let stmt_vec = &p.parse_stmt_vec().unwrap();
+    let (stmt_vec, error_vec) = &p.parse_stmt_vec_recovering();
+    if !error_vec.is_empty() {
+        for e in error_vec.iter() {
+            print_error(
+                &EvalError::Parse(e.clone()),
+                &[(&filepath, &content)],
+                color,
+            );
+        }
+        std::process::exit(1);
+    }
     let mut evaluator = MetaEvaluator::default();
     evaluator.set_resolver(Box::new(FsResolver::default()));
     evaluator.do_assert = do_assert;
+    evaluator.limit = limit;
+    evaluator.strategy = strategy;
+    evaluator.tracer = trace.map(|option| Box::new(PrintTracer::new(option)) as _);
     for (i, stmt) in stmt_vec.iter().enumerate() {
         let cp = CorePrinter::default();
         if verbose {
@@ -32,10 +60,7 @@ pub fn run(filepath: String, verbose: bool, do_assert: bool) {
                 }
                 None => {}
             },
-            Err(e) => {
-                println!("EVAL ERROR: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => exit_with_error(e),
         }
     }
 }
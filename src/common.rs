@@ -1,2 +1,3 @@
+pub mod diagnostic;
 pub mod fileinfo;
 pub mod resolver;
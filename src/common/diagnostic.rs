@@ -0,0 +1,179 @@
+use super::fileinfo::FileInfo;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Part of the source pointed by a diagnostic, with an optional message shown beside the underline.
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub info: FileInfo,
+    pub message: Option<String>,
+}
+
+/// Error report rendered with the offending source lines, like rustc.
+///
+/// ```text
+/// error[E0005]: the lambda function needs 1 args but found 2 arg(s)
+///  --> main.lisp:2:1
+///   |
+/// 1 | (def f (lambda (x) x))
+///   |        -------------- lambda defined here
+/// 2 | (f 1 2)
+///   | ^^^^^^^
+/// ```
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub code: String,
+    pub message: String,
+    /// underlined with `^`
+    pub primary: Option<Label>,
+    /// underlined with `-`
+    pub secondary: Vec<Label>,
+    /// shown after the source as `= note: ..`
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &str, message: String, info: Option<FileInfo>) -> Self {
+        Self {
+            code: code.into(),
+            message,
+            primary: info.map(|info| Label {
+                info,
+                message: None,
+            }),
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_secondary(mut self, info: FileInfo, message: &str) -> Self {
+        self.secondary.push(Label {
+            info,
+            message: Some(message.into()),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /// Renders this diagnostic.
+    /// source_of: content of the file by its path, used to show the labeled lines.
+    /// Labels in files without content are shown only by their location.
+    /// color: whether to use ANSI escape sequences
+    pub fn render(&self, source_of: &dyn Fn(&str) -> Option<String>, color: bool) -> String {
+        let paint = |style: &str, s: &str| {
+            if color {
+                format!("{}{}{}", style, s, RESET)
+            } else {
+                s.to_string()
+            }
+        };
+        let mut out = format!(
+            "{}{}\n",
+            paint(RED, &format!("error[{}]", self.code)),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        // (label, is primary), grouped by file in order of appearance, then sorted by position
+        let mut label_vec: Vec<(&Label, bool)> = self.primary.iter().map(|l| (l, true)).collect();
+        label_vec.extend(self.secondary.iter().map(|l| (l, false)));
+        let mut file_vec: Vec<&str> = Vec::new();
+        for (label, _) in label_vec.iter() {
+            if !file_vec.contains(&label.info.filepath.as_str()) {
+                file_vec.push(&label.info.filepath);
+            }
+        }
+        let width = label_vec
+            .iter()
+            .map(|(label, _)| (label.info.range.from.line + 1).to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = |line: Option<usize>| {
+            let number = line.map_or(String::new(), |line| (line + 1).to_string());
+            paint(BLUE, &format!("{:>width$} |", number, width = width))
+        };
+
+        for (i, filepath) in file_vec.iter().enumerate() {
+            let mut file_label_vec: Vec<&(&Label, bool)> = label_vec
+                .iter()
+                .filter(|(label, _)| label.info.filepath == *filepath)
+                .collect();
+            let from = &file_label_vec[0].0.info.range.from;
+            out.push_str(&format!(
+                "{}{} {}:{}:{}\n",
+                " ".repeat(width),
+                paint(BLUE, if i == 0 { "-->" } else { ":::" }),
+                filepath,
+                from.line + 1,
+                from.col + 1
+            ));
+            let line_vec: Vec<String> = match source_of(filepath) {
+                Some(source) => source.lines().map(String::from).collect(),
+                None => continue,
+            };
+            file_label_vec
+                .sort_by_key(|(label, _)| (label.info.range.from.line, label.info.range.from.col));
+            out.push_str(&format!("{}\n", gutter(None)));
+            let mut last_line = None;
+            for (label, is_primary) in file_label_vec {
+                let range = &label.info.range;
+                // errors at the end of file may point at the line after the last one
+                let source_line = match line_vec.get(range.from.line) {
+                    Some(source_line) => source_line.as_str(),
+                    None if range.from.line == line_vec.len() => "",
+                    None => continue,
+                };
+                if last_line != Some(range.from.line) {
+                    if last_line.is_some_and(|last| last + 1 < range.from.line) {
+                        out.push_str(&format!("{}\n", paint(BLUE, "...")));
+                    }
+                    let line = format!("{} {}", gutter(Some(range.from.line)), source_line);
+                    out.push_str(line.trim_end());
+                    out.push('\n');
+                }
+                last_line = Some(range.from.line);
+
+                // multi-line ranges are underlined till the end of the first line
+                let chars: Vec<char> = source_line.chars().collect();
+                let start = range.from.col.min(chars.len());
+                let end = if range.to.line == range.from.line {
+                    (range.to.col + 1).min(chars.len())
+                } else {
+                    chars.len()
+                };
+                // keep tabs so that the underline is aligned with the source
+                let padding: String = chars[..start]
+                    .iter()
+                    .map(|c| if *c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let (mark, style) = if *is_primary { ('^', RED) } else { ('-', BLUE) };
+                let mut underline = mark.to_string().repeat(end.saturating_sub(start).max(1));
+                if let Some(message) = &label.message {
+                    underline.push(' ');
+                    underline.push_str(message);
+                }
+                out.push_str(&format!(
+                    "{} {}{}\n",
+                    gutter(None),
+                    padding,
+                    paint(style, &underline)
+                ));
+            }
+        }
+        for note in self.notes.iter() {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                " ".repeat(width),
+                paint(BLUE, "="),
+                paint(BOLD, &format!("note: {}", note))
+            ));
+        }
+        out
+    }
+}
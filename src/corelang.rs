@@ -1,3 +1,6 @@
 pub mod eval;
+pub mod normalize;
 pub mod printer;
 pub mod syntax;
+pub mod trace;
+pub mod value;
@@ -1,183 +1,1237 @@
-use super::syntax::{equiv_term, substitution, Term};
-use crate::common::fileinfo::CompileError;
-use num_traits::Zero;
+use super::normalize::normalize_with_fuel;
+use super::printer::simple::SimplePrinter;
+use super::syntax::{equiv_term, fill_hole, next_hole, rational_to_term, Term};
+use super::trace::{operator_name, Rule, TraceStep, Tracer};
+use super::value::{compile, equiv_closure, Closure, Code, Frame, Thunk};
+use crate::common::diagnostic::Diagnostic;
+use crate::common::fileinfo::{CompileError, FileInfo};
+use crate::metalang::parser::is_name;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-type EvalResult = Result<Term, CompileError>;
+/// Max number of bits of integers made by `pow` and `shl`, to stop them allocating gigabytes.
+const MAX_INTEGER_BITS: u64 = 1 << 26;
 
+/// Budget for one evaluation. `None` means unlimited.
+#[derive(Clone, Debug, Default)]
+pub struct EvalLimit {
+    /// max number of reduction steps (application and if)
+    pub max_steps: Option<u64>,
+    /// max nesting depth of evaluation
+    pub max_depth: Option<usize>,
+    /// max wall-clock time
+    pub timeout: Option<Duration>,
+}
+
+/// How arguments of lambda application are passed.
+/// Operands of built-in operators are always evaluated before the operation,
+/// and components of cons are evaluated when taken by car and cdr.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EvalStrategy {
+    /// Arguments are evaluated every time they are used.
+    /// Same as substituting argument terms into the lambda body.
+    #[default]
+    CallByName,
+    /// Arguments are evaluated before the lambda body.
+    CallByValue,
+    /// Arguments are evaluated at the first use, and the value is shared by later uses.
+    CallByNeed,
+}
+
+impl FromStr for EvalStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" | "call-by-name" => Ok(EvalStrategy::CallByName),
+            "value" | "call-by-value" => Ok(EvalStrategy::CallByValue),
+            "need" | "call-by-need" | "lazy" => Ok(EvalStrategy::CallByNeed),
+            _ => Err(format!(
+                "unknown strategy \"{}\": expected name, value or need",
+                s
+            )),
+        }
+    }
+}
+
+/// Which part of `EvalLimit` ran out.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Depth(usize),
+    Timeout(Duration),
+}
+
+/// Error of parsing, transforming or evaluating a program.
+/// Each kind has a stable code, see `EvalError::code`.
+#[derive(Clone, Debug)]
+pub enum EvalError {
+    /// source is not a valid program
+    Parse(CompileError),
+    /// imported file is not found or not readable
+    Resolve(CompileError),
+    /// meta term cannot be transformed to core term, e.g. undefined variable
+    Transform(CompileError),
+    /// (error, offending term) value of wrong type or out of domain, e.g. `(+ 1 true)` and division by 0
+    Type(CompileError, Option<Box<Term>>),
+    /// (error, offending term) lambda or operator applied to wrong number of args
+    Arity(CompileError, Option<Box<Term>>),
+    /// (error, asserted term) assertion evaluated to other than true
+    Assertion(CompileError, Option<Box<Term>>),
+    /// (the term being reduced when the budget ran out, exhausted limit)
+    FuelExhausted(Option<FileInfo>, Limit),
+    /// evaluation stopped from outside, e.g. quit in debugger
+    Abort(CompileError),
+}
+
+impl EvalError {
+    /// Stable code of the kind of error, e.g. `E0004` for type error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvalError::Parse(_) => "E0001",
+            EvalError::Resolve(_) => "E0002",
+            EvalError::Transform(_) => "E0003",
+            EvalError::Type(..) => "E0004",
+            EvalError::Arity(..) => "E0005",
+            EvalError::Assertion(..) => "E0006",
+            EvalError::FuelExhausted(..) => "E0007",
+            EvalError::Abort(_) => "E0008",
+        }
+    }
+
+    /// Human readable name of the kind of error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::Parse(_) => "parse error",
+            EvalError::Resolve(_) => "resolve error",
+            EvalError::Transform(_) => "transform error",
+            EvalError::Type(..) => "type error",
+            EvalError::Arity(..) => "arity error",
+            EvalError::Assertion(..) => "assertion failed",
+            EvalError::FuelExhausted(..) => "limit exceeded",
+            EvalError::Abort(_) => "aborted",
+        }
+    }
+
+    pub fn file_info(&self) -> &Option<FileInfo> {
+        match self {
+            EvalError::Parse(e)
+            | EvalError::Resolve(e)
+            | EvalError::Transform(e)
+            | EvalError::Type(e, _)
+            | EvalError::Arity(e, _)
+            | EvalError::Assertion(e, _)
+            | EvalError::Abort(e) => &e.info,
+            EvalError::FuelExhausted(info, _) => info,
+        }
+    }
+
+    /// The term which caused the error, if known.
+    pub fn term(&self) -> Option<&Term> {
+        match self {
+            EvalError::Type(_, term)
+            | EvalError::Arity(_, term)
+            | EvalError::Assertion(_, term) => term.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Diagnostic pointing at the source of this error.
+    /// The offending term is shown as a note, and for a lambda applied to wrong number of args,
+    /// the lambda definition is labeled as well.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let message = match self {
+            EvalError::FuelExhausted(_, limit) => limit.to_string(),
+            EvalError::Parse(e)
+            | EvalError::Resolve(e)
+            | EvalError::Transform(e)
+            | EvalError::Type(e, _)
+            | EvalError::Arity(e, _)
+            | EvalError::Assertion(e, _)
+            | EvalError::Abort(e) => e.message.clone().unwrap_or_else(|| "<no message>".into()),
+        };
+        let mut diagnostic = Diagnostic::new(self.code(), message, self.file_info().clone());
+        if let (EvalError::Arity(..), Some(Term::Apply(_, t1, _))) = (self, self.term()) {
+            if let Term::Lambda(Some(info_lambda), ..) = &**t1 {
+                diagnostic = diagnostic.with_secondary(info_lambda.clone(), "lambda defined here");
+            }
+        }
+        if let Some(term) = self.term() {
+            let cp = SimplePrinter::default();
+            diagnostic = diagnostic.with_note(format!("term: {}", cp.print(term)));
+        }
+        diagnostic
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps(n) => write!(f, "exceeded {} reduction steps", n),
+            Limit::Depth(n) => write!(f, "exceeded evaluation depth {}", n),
+            Limit::Timeout(d) => write!(f, "exceeded timeout of {} ms", d.as_millis()),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Parse(e)
+            | EvalError::Resolve(e)
+            | EvalError::Transform(e)
+            | EvalError::Type(e, _)
+            | EvalError::Arity(e, _)
+            | EvalError::Assertion(e, _)
+            | EvalError::Abort(e) => e.fmt(f),
+            EvalError::FuelExhausted(info, limit) => {
+                match info {
+                    Some(info) => info.fmt(f)?,
+                    None => write!(f, "<no file>")?,
+                };
+                write!(f, ": fuel exhausted: {}", limit)
+            }
+        }
+    }
+}
+impl std::error::Error for EvalError {}
+
+pub type EvalResult = Result<Term, EvalError>;
+
+pub(crate) struct Fuel<'a> {
+    limit: &'a EvalLimit,
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Fuel<'a> {
+    pub(crate) fn new(limit: &'a EvalLimit) -> Self {
+        Self {
+            limit,
+            steps: 0,
+            deadline: limit.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// Consumes one reduction step for the term at `info` with `depth` pending continuations.
+    pub(crate) fn consume(
+        &mut self,
+        info: &Option<FileInfo>,
+        depth: usize,
+    ) -> Result<(), EvalError> {
+        self.steps += 1;
+        let exhausted = match self.limit {
+            EvalLimit {
+                max_steps: Some(max_steps),
+                ..
+            } if self.steps > *max_steps => Some(Limit::Steps(*max_steps)),
+            EvalLimit {
+                max_depth: Some(max_depth),
+                ..
+            } if depth > *max_depth => Some(Limit::Depth(*max_depth)),
+            EvalLimit {
+                timeout: Some(timeout),
+                ..
+            } if self.deadline.is_some_and(|d| Instant::now() >= d) => {
+                Some(Limit::Timeout(*timeout))
+            }
+            _ => None,
+        };
+        match exhausted {
+            Some(limit) => Err(EvalError::FuelExhausted(info.clone(), limit)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Evaluates `term` without any limit.
 pub fn eval(term: &Term) -> EvalResult {
+    eval_with_limit(term, &EvalLimit::default())
+}
+
+/// Evaluates `term` by call-by-name and fails with `EvalError::FuelExhausted` when `limit` runs out.
+pub fn eval_with_limit(term: &Term, limit: &EvalLimit) -> EvalResult {
+    eval_with(term, limit, EvalStrategy::default())
+}
+
+/// Evaluates `term` with `strategy` and fails with `EvalError::FuelExhausted` when `limit` runs out.
+///
+/// The evaluator is a machine with an explicit continuation stack,
+/// so it does not consume the Rust stack however deep the recursion is.
+/// Tail positions (lambda body, if branches, eval of quote) push no continuation.
+///
+/// Arguments are not substituted into lambda bodies but bound in `Rc` shared environments,
+/// so application is O(1). The value is read back to `Term` at the end.
+pub fn eval_with(term: &Term, limit: &EvalLimit, strategy: EvalStrategy) -> EvalResult {
+    run_machine(term, limit, strategy, None)
+}
+
+/// Same as `eval_with`, but reports each reduction step to `tracer`.
+pub fn eval_traced(
+    term: &Term,
+    limit: &EvalLimit,
+    strategy: EvalStrategy,
+    tracer: &mut dyn Tracer,
+) -> EvalResult {
+    run_machine(term, limit, strategy, Some(tracer))
+}
+
+fn run_machine<'a>(
+    term: &Term,
+    limit: &'a EvalLimit,
+    strategy: EvalStrategy,
+    tracer: Option<&'a mut dyn Tracer>,
+) -> EvalResult {
+    let mut machine = Machine {
+        strategy,
+        fuel: Fuel::new(limit),
+        cont: Vec::new(),
+        tracer,
+        reductions: 0,
+    };
+    let mut state = State::Eval(Closure::new(compile(term), None));
+    loop {
+        state = match state {
+            State::Eval(closure) => machine.eval_closure(closure)?,
+            State::Return(value) => match machine.cont.pop() {
+                Some(c) => machine.apply_cont(c, value)?,
+                None => return Ok(value.read_back().map_file_info(|_info| None)),
+            },
+        };
+    }
+}
+
+enum State {
+    /// closure to be evaluated
+    Eval(Closure),
+    /// evaluated value passed to the top continuation
+    Return(Closure),
+}
+
+/// What to do with a value once it is evaluated.
+/// Each continuation keeps the closure of the apply or if it came from.
+enum Cont {
+    /// waiting for the operator
+    Operator(Closure),
+    /// (built-in operator or lambda, evaluated operands) waiting for the next operand
+    Operand(Closure, Closure, Vec<Closure>),
+    /// waiting for the condition
+    If(Closure),
+    /// (frame, arg index) waiting for the value of a call-by-need argument
+    Update(Rc<Frame>, usize),
+}
+
+struct Machine<'a> {
+    strategy: EvalStrategy,
+    fuel: Fuel<'a>,
+    cont: Vec<Cont>,
+    tracer: Option<&'a mut dyn Tracer>,
+    /// number of reductions so far
+    reductions: u64,
+}
+
+/// Read back of an application of `operator` to `args`.
+fn redex_apply(info: &Option<FileInfo>, operator: &Closure, args: &[Closure]) -> Term {
+    Term::Apply(
+        info.clone(),
+        Box::new(operator.read_back()),
+        args.iter().map(|arg| arg.read_back()).collect(),
+    )
+}
+
+/// Number of operands the built-in operator takes, or None if `term` is not a built-in operator.
+pub(crate) fn operator_arity(term: &Term) -> Option<usize> {
     match term {
-        Term::Apply(info, t1, ts) => {
-            let e1 = eval(&(**t1))?;
-            if let Term::Eq(info_eq) = e1 {
-                if ts.len() == 2 {
-                    let e2 = eval(&ts[0])?;
-                    let e3 = eval(&ts[1])?;
-                    return Ok(Term::Bool(None, equiv_term(&e2, &e3)));
-                }
-                Err(CompileError {
-                    info: info_eq.or_else(|| info.clone()),
-                    message: None, // TODO
-                })
-            } else if let Term::Eval(info_eval) = e1 {
-                if ts.len() == 1 {
-                    let e2 = eval(&ts[0])?;
-                    if let Term::Quote(_, quoted) = e2 {
-                        return eval(&quoted);
-                    }
-                    return Err(CompileError {
-                        info: info_eval.or_else(|| info.clone()),
-                        message: Some("quote expected".into()), // TODO
-                    });
+        Term::Eval(_)
+        | Term::Car(_)
+        | Term::Cdr(_)
+        | Term::Neg(_)
+        | Term::Abs(_)
+        | Term::Numerator(_)
+        | Term::Denominator(_)
+        | Term::Isqrt(_)
+        | Term::StringLength(_)
+        | Term::CharToInteger(_)
+        | Term::IntegerToChar(_)
+        | Term::CharToString(_)
+        | Term::StringToList(_)
+        | Term::ListToString(_)
+        | Term::IsSymbol(_)
+        | Term::IsNumber(_)
+        | Term::IsBool(_)
+        | Term::IsString(_)
+        | Term::IsChar(_)
+        | Term::IsCons(_)
+        | Term::IsNil(_)
+        | Term::IsLambda(_)
+        | Term::IsQuote(_)
+        | Term::TypeOf(_)
+        | Term::SymbolToString(_)
+        | Term::StringToSymbol(_)
+        | Term::Gensym(..) => Some(1),
+        Term::Eq(_)
+        | Term::BetaEq(_)
+        | Term::Add(_)
+        | Term::Sub(_)
+        | Term::Mul(_)
+        | Term::Div(_)
+        | Term::Rem(_)
+        | Term::Monus(_)
+        | Term::Lt(_)
+        | Term::Le(_)
+        | Term::Gt(_)
+        | Term::Ge(_)
+        | Term::Min(_)
+        | Term::Max(_)
+        | Term::Pow(_)
+        | Term::Gcd(_)
+        | Term::Shl(_)
+        | Term::Shr(_)
+        | Term::BitAnd(_)
+        | Term::BitOr(_)
+        | Term::BitXor(_)
+        | Term::StringAppend(_)
+        | Term::Fill(_) => Some(2),
+        Term::ModPow(_) | Term::Substring(_) => Some(3),
+        _ => None,
+    }
+}
+
+impl<'a> Machine<'a> {
+    /// Counts a reduction of `redex` into `state`, and reports it to the tracer if any.
+    /// `redex` is read back only when traced.
+    fn trace<F>(&mut self, rule: Rule, redex: F, state: &State)
+    where
+        F: FnOnce() -> Term,
+    {
+        self.reductions += 1;
+        let depth = self.cont.len();
+        if let Some(tracer) = self.tracer.as_mut() {
+            if tracer.accepts(depth) {
+                let result = match state {
+                    State::Eval(closure) | State::Return(closure) => closure.read_back(),
+                };
+                tracer.trace(&TraceStep {
+                    step: self.reductions,
+                    depth,
+                    rule,
+                    redex: redex(),
+                    result,
+                });
+            }
+        }
+    }
+
+    fn eval_closure(&mut self, closure: Closure) -> Result<State, EvalError> {
+        Ok(match &*closure.code {
+            Code::Apply(info, c1, _) => {
+                self.fuel.consume(info, self.cont.len())?;
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.apply(&closure, self.cont.len())?;
                 }
-                Err(CompileError {
-                    info: info_eval.or_else(|| info.clone()),
-                    message: Some("eval error".into()), // TODO
-                })
-            } else if let Term::Add(info_add) = e1 {
-                if ts.len() == 2 {
-                    let e2 = eval(&ts[0])?;
-                    let e3 = eval(&ts[1])?;
-                    if let Term::Number(_, n2) = e2 {
-                        if let Term::Number(_, n3) = e3 {
-                            return Ok(Term::Number(None, n2 + n3));
+                let next = Closure::new(c1.clone(), closure.env.clone());
+                self.cont.push(Cont::Operator(closure));
+                State::Eval(next)
+            }
+            Code::If(info, c1, _, _) => {
+                self.fuel.consume(info, self.cont.len())?;
+                let next = Closure::new(c1.clone(), closure.env.clone());
+                self.cont.push(Cont::If(closure));
+                State::Eval(next)
+            }
+            Code::Variable(info, v, a) => {
+                let frame = match Frame::find(&closure.env, *v) {
+                    Some(frame) if *a < frame.arg_vec.len() => frame,
+                    _ => Err(EvalError::Transform(CompileError {
+                        info: info.clone(),
+                        message: Some("unbound variable".into()),
+                    }))?,
+                };
+                let thunk = frame.arg_vec[*a].borrow().clone();
+                match thunk {
+                    Thunk::Forced(value) => State::Return(value),
+                    Thunk::Delayed(arg) => {
+                        if self.strategy == EvalStrategy::CallByNeed {
+                            self.cont.push(Cont::Update(frame.clone(), *a));
                         }
+                        State::Eval(arg)
                     }
                 }
-                Err(CompileError {
-                    info: info_add.or_else(|| info.clone()),
-                    message: Some("addition operator only accepts 2 numbers.".into()),
-                })
-            } else if let Term::Sub(info_sub) = e1 {
-                if ts.len() == 2 {
-                    let e2 = eval(&ts[0])?;
-                    let e3 = eval(&ts[1])?;
-                    if let Term::Number(_, n2) = e2 {
-                        if let Term::Number(_, n3) = e3 {
-                            return Ok(if n2 < n3 {
-                                Term::Number(None, Zero::zero())
-                            } else {
-                                Term::Number(None, n2 - n3)
-                            });
-                        }
-                    }
+            }
+            _ => State::Return(closure),
+        })
+    }
+
+    /// Thunk of an argument not evaluated yet.
+    /// A variable shares the thunk it is bound to rather than being wrapped again,
+    /// so that an argument passed through recursive calls does not grow a chain of variables.
+    /// Under call-by-need, only a forced thunk is shared, since a delayed one is updated in its slot.
+    fn delay(&self, arg: &Closure) -> Thunk {
+        if let Code::Variable(_, v, a) = &*arg.code {
+            let frame = Frame::find(&arg.env, *v);
+            if let Some(thunk) = frame.and_then(|frame| frame.arg_vec.get(*a)) {
+                let thunk = thunk.borrow();
+                if self.strategy == EvalStrategy::CallByName || matches!(*thunk, Thunk::Forced(_)) {
+                    return thunk.clone();
                 }
-                Err(CompileError {
-                    info: info_sub.or_else(|| info.clone()),
-                    message: Some(format!(
-                        "subtraction operator only accepts 2 numbers: found {} args",
-                        ts.len()
-                    )),
-                })
-            } else if let Term::Mul(info_mul) = e1 {
-                if ts.len() == 2 {
-                    let e2 = eval(&ts[0])?;
-                    let e3 = eval(&ts[1])?;
-                    if let Term::Number(_, n2) = e2 {
-                        if let Term::Number(_, n3) = e3 {
-                            return Ok(Term::Number(None, n2 * n3));
+            }
+        }
+        Thunk::Delayed(arg.clone())
+    }
+
+    fn apply_cont(&mut self, c: Cont, value: Closure) -> Result<State, EvalError> {
+        let value = value.deref();
+        match c {
+            Cont::Operator(apply) => {
+                let (info, cs) = match &*apply.code {
+                    Code::Apply(info, _, cs) => (info, cs),
+                    _ => unreachable!(),
+                };
+                let redex = || {
+                    let args: Vec<Closure> = cs
+                        .iter()
+                        .map(|c| Closure::new(c.clone(), apply.env.clone()))
+                        .collect();
+                    Some(Box::new(redex_apply(info, &value, &args)))
+                };
+                let arity = match &*value.code {
+                    Code::Lambda(info_lambda, arg_num, _) => {
+                        if cs.len() != *arg_num {
+                            return Err(EvalError::Arity(
+                                CompileError {
+                                    info: info.clone().or_else(|| info_lambda.clone()),
+                                    message: Some(format!(
+                                        "the lambda function needs {} args but found {} arg(s)",
+                                        arg_num,
+                                        cs.len()
+                                    )),
+                                },
+                                redex(),
+                            ));
                         }
-                    }
-                }
-                Err(CompileError {
-                    info: info_mul.or_else(|| info.clone()),
-                    message: Some("multiplication operator only accepts 2 numbers.".into()),
-                })
-            } else if let Term::Div(info_div) = e1 {
-                if ts.len() == 2 {
-                    let e2 = eval(&ts[0])?;
-                    let e3 = eval(&ts[1])?;
-                    if let Term::Number(_, n2) = e2 {
-                        if let Term::Number(info_e3, n3) = e3 {
-                            return if n3.is_zero() {
-                                Err(CompileError {
-                                    info: info_e3.or_else(|| info_div).or_else(|| info.clone()),
-                                    message: Some("division operator got 0 for divisor.".into()),
-                                })
-                            } else {
-                                Ok(Term::Number(None, n2 / n3))
-                            };
+                        if self.strategy != EvalStrategy::CallByValue {
+                            let args: Vec<Closure> = cs
+                                .iter()
+                                .map(|c| Closure::new(c.clone(), apply.env.clone()))
+                                .collect();
+                            let thunk_vec = args.iter().map(|arg| self.delay(arg)).collect();
+                            let state = enter(&value, thunk_vec);
+                            self.trace(Rule::Beta, || redex_apply(info, &value, &args), &state);
+                            return Ok(state);
                         }
+                        *arg_num
+                    }
+                    Code::Atom(operator) => match operator_arity(operator) {
+                        Some(arity) if arity == cs.len() => arity,
+                        _ => return Err(operator_error(info.clone(), operator, cs.len(), redex())),
+                    },
+                    _ => {
+                        return Err(EvalError::Type(
+                            CompileError {
+                                info: info.clone(),
+                                message: Some("operator expected".into()),
+                            },
+                            redex(),
+                        ))
                     }
+                };
+                if arity == 0 {
+                    let state = enter(&value, Vec::new());
+                    self.trace(Rule::Beta, || redex_apply(info, &value, &[]), &state);
+                    return Ok(state);
                 }
-                Err(CompileError {
-                    info: info_div.or_else(|| info.clone()),
-                    message: Some("division operator only accepts 2 numbers.".into()),
-                })
-            } else if let Term::Rem(info_rem) = e1 {
-                if ts.len() == 2 {
-                    let e2 = eval(&ts[0])?;
-                    let e3 = eval(&ts[1])?;
-                    if let Term::Number(_, n2) = e2 {
-                        if let Term::Number(info_e3, n3) = e3 {
-                            return if n3.is_zero() {
-                                Err(CompileError {
-                                    info: info_e3.or_else(|| info_rem).or_else(|| info.clone()),
-                                    message: Some("remainder operator got 0 for divisor.".into()),
-                                })
-                            } else {
-                                Ok(Term::Number(None, n2 % n3))
-                            };
-                        }
+                let first = Closure::new(cs[0].clone(), apply.env.clone());
+                self.cont
+                    .push(Cont::Operand(apply.clone(), value, Vec::new()));
+                Ok(State::Eval(first))
+            }
+            Cont::Operand(apply, operator, mut values) => {
+                let (info, cs) = match &*apply.code {
+                    Code::Apply(info, _, cs) => (info, cs),
+                    _ => unreachable!(),
+                };
+                values.push(value);
+                match cs.get(values.len()) {
+                    Some(next) => {
+                        let next = Closure::new(next.clone(), apply.env.clone());
+                        self.cont.push(Cont::Operand(apply, operator, values));
+                        Ok(State::Eval(next))
+                    }
+                    None => {
+                        let (rule, state) = match &*operator.code {
+                            Code::Atom(op) => (
+                                Rule::Delta(operator_name(op)),
+                                apply_operator(
+                                    info.clone(),
+                                    op,
+                                    &values,
+                                    self.strategy,
+                                    &mut self.fuel,
+                                )?,
+                            ),
+                            _ => (
+                                Rule::Beta,
+                                enter(
+                                    &operator,
+                                    values.iter().cloned().map(Thunk::Forced).collect(),
+                                ),
+                            ),
+                        };
+                        self.trace(rule, || redex_apply(info, &operator, &values), &state);
+                        Ok(state)
                     }
                 }
-                Err(CompileError {
-                    info: info_rem.or_else(|| info.clone()),
-                    message: Some("remainder operator only accepts 2 numbers.".into()),
-                })
-            } else if let Term::Car(info_car) = e1 {
-                if ts.len() == 1 {
-                    let e2 = eval(&ts[0])?;
-                    if let Term::Cons(_, t1, _) = e2 {
-                        return Ok(*t1);
+            }
+            Cont::If(closure) => {
+                let (info, c2, c3) = match &*closure.code {
+                    Code::If(info, _, c2, c3) => (info, c2, c3),
+                    _ => unreachable!(),
+                };
+                let (rule, branch) = match &*value.code {
+                    Code::Atom(Term::Bool(_, true)) => (Rule::IfTrue, c2),
+                    Code::Atom(Term::Bool(_, false)) => (Rule::IfFalse, c3),
+                    _ => {
+                        return Err(EvalError::Type(
+                            CompileError {
+                                info: value.code.file_info().clone().or_else(|| info.clone()),
+                                message: Some("expect bool for if condition".into()),
+                            },
+                            Some(Box::new(value.read_back())),
+                        ))
                     }
+                };
+                let state = State::Eval(Closure::new(branch.clone(), closure.env.clone()));
+                self.trace(
+                    rule,
+                    || {
+                        let read_back = |c: &Rc<Code>| {
+                            Box::new(Closure::new(c.clone(), closure.env.clone()).read_back())
+                        };
+                        Term::If(
+                            info.clone(),
+                            Box::new(value.read_back()),
+                            read_back(c2),
+                            read_back(c3),
+                        )
+                    },
+                    &state,
+                );
+                Ok(state)
+            }
+            Cont::Update(frame, a) => {
+                *frame.arg_vec[a].borrow_mut() = Thunk::Forced(value.clone());
+                Ok(State::Return(value))
+            }
+        }
+    }
+}
+
+/// Evaluates the body of `lambda` with `arg_vec` bound.
+fn enter(lambda: &Closure, arg_vec: Vec<Thunk>) -> State {
+    match &*lambda.code {
+        Code::Lambda(info, _, body) => {
+            let frame = Frame {
+                info: info.clone(),
+                arg_vec: arg_vec.into_iter().map(RefCell::new).collect(),
+                parent: lambda.env.clone(),
+            };
+            State::Eval(Closure::new(body.clone(), Some(Rc::new(frame))))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Error for a built-in operator applied to wrong operands.
+/// It is an arity error if the number of operands is wrong, otherwise a type error.
+/// term: the application of the operator
+fn operator_error(
+    info: Option<FileInfo>,
+    operator: &Term,
+    arg_len: usize,
+    term: Option<Box<Term>>,
+) -> EvalError {
+    let message = match operator {
+        Term::Eq(_) => None, // TODO
+        Term::BetaEq(_) => Some("beta-eq operator only accepts 2 terms.".into()),
+        Term::Eval(_) => Some("eval error".into()), // TODO
+        Term::Fill(_) => Some("fill operator only accepts a quote and a value.".into()),
+        Term::Add(_) => Some("addition operator only accepts 2 numbers.".into()),
+        Term::Sub(_) => Some(format!(
+            "subtraction operator only accepts 2 numbers: found {} args",
+            arg_len
+        )),
+        Term::Mul(_) => Some("multiplication operator only accepts 2 numbers.".into()),
+        Term::Div(_) => Some("division operator only accepts 2 numbers.".into()),
+        Term::Rem(_) => Some("remainder operator only accepts 2 integers.".into()),
+        Term::Monus(_) => Some("monus operator only accepts 2 numbers.".into()),
+        Term::Neg(_) => Some("neg operator only accepts 1 number.".into()),
+        Term::Abs(_) => Some("abs operator only accepts 1 number.".into()),
+        Term::Numerator(_) => Some("numerator operator only accepts 1 number.".into()),
+        Term::Denominator(_) => Some("denominator operator only accepts 1 number.".into()),
+        Term::Lt(_) | Term::Le(_) | Term::Gt(_) | Term::Ge(_) => {
+            Some("comparison operator only accepts 2 numbers.".into())
+        }
+        Term::Min(_) => Some("min operator only accepts 2 numbers.".into()),
+        Term::Max(_) => Some("max operator only accepts 2 numbers.".into()),
+        Term::Pow(_) => Some("pow operator only accepts 2 integers.".into()),
+        Term::ModPow(_) => Some("modpow operator only accepts 3 integers.".into()),
+        Term::Gcd(_) => Some("gcd operator only accepts 2 integers.".into()),
+        Term::Isqrt(_) => Some("isqrt operator only accepts 1 integer.".into()),
+        Term::Shl(_) | Term::Shr(_) => Some("shift operator only accepts 2 integers.".into()),
+        Term::BitAnd(_) | Term::BitOr(_) | Term::BitXor(_) => {
+            Some("bitwise operator only accepts 2 integers.".into())
+        }
+        Term::StringLength(_) => Some("string-length operator only accepts 1 string.".into()),
+        Term::StringAppend(_) => Some("string-append operator only accepts 2 strings.".into()),
+        Term::Substring(_) => Some(
+            "substring operator only accepts 1 string and 2 integers for start and end.".into(),
+        ),
+        Term::CharToInteger(_) => Some("char->integer operator only accepts 1 char.".into()),
+        Term::IntegerToChar(_) => Some("integer->char operator only accepts 1 integer.".into()),
+        Term::CharToString(_) => Some("char->string operator only accepts 1 char.".into()),
+        Term::StringToList(_) => Some("string->list operator only accepts 1 string.".into()),
+        Term::ListToString(_) => Some("list->string operator only accepts 1 list of chars.".into()),
+        Term::IsSymbol(_)
+        | Term::IsNumber(_)
+        | Term::IsBool(_)
+        | Term::IsString(_)
+        | Term::IsChar(_)
+        | Term::IsCons(_)
+        | Term::IsNil(_)
+        | Term::IsLambda(_)
+        | Term::IsQuote(_) => Some("type predicate only accepts 1 term.".into()),
+        Term::TypeOf(_) => Some("type-of operator only accepts 1 term.".into()),
+        Term::SymbolToString(_) => Some("symbol->string operator only accepts 1 symbol.".into()),
+        Term::StringToSymbol(_) => Some("string->symbol operator only accepts 1 string.".into()),
+        Term::Gensym(..) => Some("gensym operator only accepts 1 symbol.".into()),
+        Term::Car(_) => Some("car operator only 1 cons.".into()),
+        Term::Cdr(_) => Some("cdr operator only 1 cons.".into()),
+        _ => Some("operator expected".into()),
+    };
+    let e = CompileError {
+        info: operator.file_info().clone().or(info),
+        message,
+    };
+    match operator_arity(operator) {
+        Some(arity) if arity != arg_len => EvalError::Arity(e, term),
+        _ => EvalError::Type(e, term),
+    }
+}
+
+/// Applies a built-in operator to its evaluated operands.
+fn apply_operator(
+    info: Option<FileInfo>,
+    operator: &Term,
+    values: &[Closure],
+    strategy: EvalStrategy,
+    fuel: &mut Fuel,
+) -> Result<State, EvalError> {
+    let arg_len = values.len();
+    let e2 = &values[0];
+    let e3 = values.get(1);
+    let value = match (operator, &*e2.code, e3.map(|e3| &*e3.code)) {
+        (Term::Eq(_), _, Some(_)) => Term::Bool(None, equiv_closure(e2, e3.unwrap())),
+        (Term::BetaEq(_), _, Some(_)) => {
+            let n2 = normalize_with_fuel(&e2.read_back(), fuel, true)?;
+            let n3 = normalize_with_fuel(&e3.unwrap().read_back(), fuel, true)?;
+            Term::Bool(None, equiv_term(&n2, &n3))
+        }
+        (_, c2, None) if is_type_operator(operator) => {
+            let name = match c2 {
+                Code::Lambda(..) => "lambda",
+                Code::Quote(..) => "quote",
+                Code::Cons(..) => "cons",
+                Code::Atom(t) => type_of(t).unwrap(),
+                // an unevaluated component of cons taken by call by name
+                Code::Apply(..) | Code::Variable(..) | Code::If(..) => {
+                    let term = Term::Apply(
+                        info.clone(),
+                        Box::new(operator.clone()),
+                        vec![e2.read_back()],
+                    );
+                    return Err(operator_error(
+                        info,
+                        operator,
+                        arg_len,
+                        Some(Box::new(term)),
+                    ));
                 }
-                Err(CompileError {
-                    info: info_car.or_else(|| info.clone()),
-                    message: Some("car operator only 1 cons.".into()),
-                })
-            } else if let Term::Cdr(info_cdr) = e1 {
-                if ts.len() == 1 {
-                    let e2 = eval(&ts[0])?;
-                    if let Term::Cons(_, _, t2) = e2 {
-                        return Ok(*t2);
+            };
+            apply_type_operator(operator, name)
+        }
+        (Term::Eval(info_eval), c2, None) => {
+            if let Code::Quote(_, quoted) = c2 {
+                return Ok(State::Eval(Closure::new(quoted.clone(), e2.env.clone())));
+            }
+            return Err(EvalError::Type(
+                CompileError {
+                    info: info_eval.clone().or(info),
+                    message: Some("quote expected".into()), // TODO
+                },
+                Some(Box::new(e2.read_back())),
+            ));
+        }
+        (Term::Fill(_), Code::Quote(..), Some(_)) => return fill(info, e2, e3.unwrap()),
+        // Components of cons are evaluated when taken, except call by name returns them as they are.
+        (Term::Car(_), Code::Cons(_, c1, _), None) => {
+            return Ok(take(strategy, Closure::new(c1.clone(), e2.env.clone())))
+        }
+        (Term::Cdr(_), Code::Cons(_, _, c2), None) => {
+            return Ok(take(strategy, Closure::new(c2.clone(), e2.env.clone())))
+        }
+        // Built as code directly, since a long string makes a deep list.
+        (Term::StringToList(_), Code::Atom(Term::String(_, s2)), None) => {
+            let atom = |t: Term| Rc::new(Code::Atom(t));
+            let code = s2.chars().rev().fold(atom(Term::Nil(None)), |list, c| {
+                Rc::new(Code::Cons(None, atom(Term::Char(None, c)), list))
+            });
+            return Ok(State::Return(Closure::new(code, None)));
+        }
+        // Chars are taken one by one since components of cons are not evaluated yet.
+        (Term::ListToString(_), Code::Cons(_, c1, c2), None) => {
+            let atom = |t: Term| Rc::new(Code::Atom(t));
+            let code = Code::Apply(
+                info.clone(),
+                atom(Term::StringAppend(None)),
+                vec![
+                    Rc::new(Code::Apply(
+                        info.clone(),
+                        atom(Term::CharToString(None)),
+                        vec![c1.clone()],
+                    )),
+                    Rc::new(Code::Apply(info, atom(operator.clone()), vec![c2.clone()])),
+                ],
+            );
+            return Ok(State::Eval(Closure::new(Rc::new(code), e2.env.clone())));
+        }
+        _ => {
+            let mut arg_vec = Vec::with_capacity(arg_len);
+            for value in values.iter() {
+                match &*value.code {
+                    Code::Atom(t) => arg_vec.push(t),
+                    _ => {
+                        let term = Term::Apply(
+                            info.clone(),
+                            Box::new(operator.clone()),
+                            values.iter().map(|value| value.read_back()).collect(),
+                        );
+                        return Err(operator_error(
+                            info,
+                            operator,
+                            arg_len,
+                            Some(Box::new(term)),
+                        ));
                     }
                 }
-                Err(CompileError {
-                    info: info_cdr.or_else(|| info.clone()),
-                    message: Some("cdr operator only 1 cons.".into()),
-                })
-            } else if let Term::Lambda(info_lambda, arg_num, body) = e1 {
-                if ts.len() == arg_num {
-                    return eval(&substitution(&*body, 0, ts));
+            }
+            apply_atom_operator(info, operator, &arg_vec)?
+        }
+    };
+    // compiled since the value may be a cons, e.g. by `string->list`
+    Ok(State::Return(Closure::new(compile(&value), None)))
+}
+
+/// The state taking a component of cons.
+fn take(strategy: EvalStrategy, component: Closure) -> State {
+    match strategy {
+        EvalStrategy::CallByName => State::Return(component),
+        EvalStrategy::CallByValue | EvalStrategy::CallByNeed => State::Eval(component),
+    }
+}
+
+/// Fills the next hole of the quasiquote `template`, which is a quote, by `value`.
+/// A quote fills the hole by its code, and other values by themselves.
+/// A hole for splicing is filled by the elements of a list one by one,
+/// since components of cons are not evaluated yet.
+fn fill(info: Option<FileInfo>, template: &Closure, value: &Closure) -> Result<State, EvalError> {
+    let template = match template.read_back() {
+        Term::Quote(_, t) => *t,
+        _ => unreachable!(),
+    };
+    let quote = |t: Term| Rc::new(Code::Quote(info.clone(), compile(&t)));
+    let hole = match next_hole(&template) {
+        Some(hole) => hole,
+        None => return Ok(State::Return(Closure::new(quote(template), None))),
+    };
+    let filling = match (hole, &*value.code) {
+        ((_, false), _) => match value.read_back() {
+            Term::Quote(_, t) => vec![*t],
+            t => vec![t],
+        },
+        ((_, true), Code::Atom(Term::Nil(_))) => vec![],
+        // (fill (fill <template with a hole for the car before the hole for splicing> car) cdr)
+        ((i, true), Code::Cons(_, c1, c2)) => {
+            let hole_vec = vec![
+                Term::Unquote(info.clone(), i),
+                Term::UnquoteSplicing(info.clone(), i),
+            ];
+            let template = fill_hole(&template, hole, &hole_vec).unwrap();
+            let atom = || Rc::new(Code::Atom(Term::Fill(None)));
+            let first = Code::Apply(info.clone(), atom(), vec![quote(template), c1.clone()]);
+            let code = Code::Apply(info, atom(), vec![Rc::new(first), c2.clone()]);
+            return Ok(State::Eval(Closure::new(Rc::new(code), value.env.clone())));
+        }
+        _ => {
+            return Err(EvalError::Type(
+                CompileError {
+                    info,
+                    message: Some("unquote-splicing expects a list".into()),
+                },
+                Some(Box::new(value.read_back())),
+            ))
+        }
+    };
+    let filled = fill_hole(&template, hole, &filling).unwrap();
+    Ok(State::Return(Closure::new(quote(filled), None)))
+}
+
+/// Applies a built-in operator whose operands are all atoms, e.g. arithmetic on numbers.
+pub(crate) fn apply_atom_operator(
+    info: Option<FileInfo>,
+    operator: &Term,
+    arg_vec: &[&Term],
+) -> Result<Term, EvalError> {
+    let arg_len = arg_vec.len();
+    let term = || {
+        Some(Box::new(Term::Apply(
+            info.clone(),
+            Box::new(operator.clone()),
+            arg_vec.iter().map(|t| (*t).clone()).collect(),
+        )))
+    };
+    Ok(match (operator, arg_vec) {
+        (
+            Term::Lt(_) | Term::Le(_) | Term::Gt(_) | Term::Ge(_) | Term::Min(_) | Term::Max(_),
+            [t2 @ (Term::Number(..) | Term::Rational(..)), t3 @ (Term::Number(..) | Term::Rational(..))],
+        ) => {
+            let ordering = match (t2, t3) {
+                (Term::Number(_, n2), Term::Number(_, n3)) => n2.cmp(n3),
+                _ => to_rational(t2).cmp(&to_rational(t3)),
+            };
+            match operator {
+                Term::Lt(_) => Term::Bool(None, ordering.is_lt()),
+                Term::Le(_) => Term::Bool(None, ordering.is_le()),
+                Term::Gt(_) => Term::Bool(None, ordering.is_gt()),
+                Term::Ge(_) => Term::Bool(None, ordering.is_ge()),
+                Term::Min(_) => (if ordering.is_le() { *t2 } else { *t3 }).clone(),
+                Term::Max(_) => (if ordering.is_ge() { *t2 } else { *t3 }).clone(),
+                _ => unreachable!(),
+            }
+        }
+        (Term::Pow(_), [Term::Number(_, n2), t3 @ Term::Number(_, n3)]) => {
+            let exp = n3.to_u32().ok_or_else(|| {
+                operand_error(
+                    &info,
+                    operator,
+                    t3,
+                    "pow operator got exponent out of 0..2^32.",
+                )
+            })?;
+            // at most 1 in magnitude if n2 is -1, 0 or 1
+            if n2.bits() > 1 && n2.bits().saturating_mul(u64::from(exp)) > MAX_INTEGER_BITS {
+                return Err(operand_error(
+                    &info,
+                    operator,
+                    t3,
+                    "pow operator got exponent making too large integer.",
+                ));
+            }
+            Term::Number(None, n2.pow(exp))
+        }
+        (
+            Term::ModPow(_),
+            [Term::Number(_, n2), t3 @ Term::Number(_, n3), t4 @ Term::Number(_, n4)],
+        ) => {
+            if n3.is_negative() {
+                return Err(operand_error(
+                    &info,
+                    operator,
+                    t3,
+                    "modpow operator got negative exponent.",
+                ));
+            }
+            if n4.is_zero() {
+                return Err(operand_error(
+                    &info,
+                    operator,
+                    t4,
+                    "modpow operator got 0 for modulus.",
+                ));
+            }
+            Term::Number(None, n2.modpow(n3, n4))
+        }
+        (Term::Gcd(_), [Term::Number(_, n2), Term::Number(_, n3)]) => {
+            Term::Number(None, n2.gcd(n3))
+        }
+        (Term::Isqrt(_), [t2 @ Term::Number(_, n2)]) => {
+            if n2.is_negative() {
+                return Err(operand_error(
+                    &info,
+                    operator,
+                    t2,
+                    "isqrt operator got negative number.",
+                ));
+            }
+            Term::Number(None, n2.sqrt())
+        }
+        (Term::Shl(_) | Term::Shr(_), [Term::Number(_, n2), t3 @ Term::Number(_, n3)]) => {
+            let shift = n3.to_usize().ok_or_else(|| {
+                operand_error(
+                    &info,
+                    operator,
+                    t3,
+                    "shift operator got negative or too large shift.",
+                )
+            })?;
+            if let Term::Shl(_) = operator {
+                if !n2.is_zero() && n2.bits().saturating_add(shift as u64) > MAX_INTEGER_BITS {
+                    return Err(operand_error(
+                        &info,
+                        operator,
+                        t3,
+                        "shift operator got shift making too large integer.",
+                    ));
+                }
+            }
+            match operator {
+                Term::Shl(_) => Term::Number(None, n2 << shift),
+                _ => Term::Number(None, n2 >> shift),
+            }
+        }
+        (Term::StringLength(_), [Term::String(_, s2)]) => {
+            Term::Number(None, s2.chars().count().into())
+        }
+        (Term::StringAppend(_), [Term::String(_, s2), Term::String(_, s3)]) => {
+            Term::String(None, format!("{}{}", s2, s3))
+        }
+        (
+            Term::Substring(_),
+            [Term::String(_, s2), t3 @ Term::Number(_, n3), t4 @ Term::Number(_, n4)],
+        ) => {
+            let char_vec: Vec<char> = s2.chars().collect();
+            let index = |t: &Term, n: &BigInt, min: usize| {
+                n.to_usize()
+                    .filter(|i| min <= *i && *i <= char_vec.len())
+                    .ok_or_else(|| {
+                        operand_error(
+                            &info,
+                            operator,
+                            t,
+                            "substring operator got index out of range.",
+                        )
+                    })
+            };
+            let start = index(t3, n3, 0)?;
+            let end = index(t4, n4, start)?;
+            Term::String(None, char_vec[start..end].iter().collect())
+        }
+        (Term::CharToInteger(_), [Term::Char(_, c2)]) => Term::Number(None, (*c2 as u32).into()),
+        (Term::IntegerToChar(_), [t2 @ Term::Number(_, n2)]) => {
+            let c = n2.to_u32().and_then(char::from_u32).ok_or_else(|| {
+                operand_error(
+                    &info,
+                    operator,
+                    t2,
+                    "integer->char operator got invalid code point.",
+                )
+            })?;
+            Term::Char(None, c)
+        }
+        (Term::CharToString(_), [Term::Char(_, c2)]) => Term::String(None, c2.to_string()),
+        (Term::StringToList(_), [Term::String(_, s2)]) => {
+            s2.chars().rev().fold(Term::Nil(None), |list, c| {
+                Term::Cons(None, Box::new(Term::Char(None, c)), Box::new(list))
+            })
+        }
+        (Term::ListToString(_), [Term::Nil(_)]) => Term::String(None, String::new()),
+        (Term::SymbolToString(_), [Term::Symbol(_, s2)]) => Term::String(None, s2.clone()),
+        // symbols are made only of names, so that they are printed as they are read
+        (Term::StringToSymbol(_), [t2 @ Term::String(_, s2)]) => {
+            if !is_name(s2) {
+                return Err(operand_error(
+                    &info,
+                    operator,
+                    t2,
+                    "string->symbol operator got a string not of a name.",
+                ));
+            }
+            Term::Symbol(None, s2.clone())
+        }
+        (Term::Gensym(_, count), [Term::Symbol(_, s2)]) => {
+            Term::Symbol(None, format!("{}'{}", s2, count))
+        }
+        (Term::BitAnd(_), [Term::Number(_, n2), Term::Number(_, n3)]) => {
+            Term::Number(None, n2 & n3)
+        }
+        (Term::BitOr(_), [Term::Number(_, n2), Term::Number(_, n3)]) => Term::Number(None, n2 | n3),
+        (Term::BitXor(_), [Term::Number(_, n2), Term::Number(_, n3)]) => {
+            Term::Number(None, n2 ^ n3)
+        }
+        (operator, [Term::Number(_, n2), t3 @ Term::Number(_, n3)]) => match operator {
+            Term::Add(_) => Term::Number(None, n2 + n3),
+            Term::Sub(_) => Term::Number(None, n2 - n3),
+            Term::Monus(_) => {
+                if n2 < n3 {
+                    Term::Number(None, Zero::zero())
+                } else {
+                    Term::Number(None, n2 - n3)
                 }
-                Err(CompileError {
-                    info: info_lambda.or_else(|| info.clone()),
-                    message: Some(format!(
-                        "the lambda function needs {} args but found {} arg(s)",
-                        arg_num,
-                        ts.len()
-                    )),
-                })
-            } else {
-                Err(CompileError {
-                    info: info.clone(),
-                    message: Some("operator expected".into()),
-                })
-            }
-        }
-        Term::If(info, t1, t2, t3) => {
-            let e1 = eval(&t1)?;
-            match e1 {
-                Term::Bool(_, true) => return Ok(eval(&t2)?),
-                Term::Bool(_, false) => return Ok(eval(&t3)?),
-                _ => {
-                    return Err(CompileError {
-                        info: e1.file_info().clone().or_else(|| info.clone()),
-                        message: Some("expect bool for if condition".into()),
-                    });
+            }
+            Term::Mul(_) => Term::Number(None, n2 * n3),
+            Term::Div(_) => {
+                if n3.is_zero() {
+                    return Err(operand_error(
+                        &info,
+                        operator,
+                        t3,
+                        "division operator got 0 for divisor.",
+                    ));
+                }
+                Term::Number(None, n2.div_floor(n3))
+            }
+            Term::Rem(_) => {
+                if n3.is_zero() {
+                    return Err(operand_error(
+                        &info,
+                        operator,
+                        t3,
+                        "remainder operator got 0 for divisor.",
+                    ));
+                }
+                Term::Number(None, n2.mod_floor(n3))
+            }
+            _ => return Err(operator_error(info.clone(), operator, arg_len, term())),
+        },
+        // Integers are promoted when either operand is rational.
+        (
+            Term::Add(_) | Term::Sub(_) | Term::Mul(_) | Term::Div(_) | Term::Monus(_),
+            [t2 @ (Term::Number(..) | Term::Rational(..)), t3 @ (Term::Number(..) | Term::Rational(..))],
+        ) => {
+            let r2 = to_rational(t2);
+            let r3 = to_rational(t3);
+            let r = match operator {
+                Term::Add(_) => r2 + r3,
+                Term::Sub(_) => r2 - r3,
+                Term::Monus(_) => {
+                    if r2 < r3 {
+                        Zero::zero()
+                    } else {
+                        r2 - r3
+                    }
+                }
+                Term::Mul(_) => r2 * r3,
+                Term::Div(_) => {
+                    if r3.is_zero() {
+                        return Err(operand_error(
+                            &info,
+                            operator,
+                            t3,
+                            "division operator got 0 for divisor.",
+                        ));
+                    }
+                    r2 / r3
                 }
+                _ => unreachable!(),
             };
+            rational_to_term(None, r)
         }
-        _ => Ok((*term).map_file_info(|_info| None)),
+        (Term::Neg(_), [Term::Number(_, n2)]) => Term::Number(None, -n2),
+        (Term::Neg(_), [Term::Rational(_, r2)]) => Term::Rational(None, -r2),
+        (Term::Abs(_), [Term::Number(_, n2)]) => Term::Number(None, n2.abs()),
+        (Term::Abs(_), [Term::Rational(_, r2)]) => Term::Rational(None, r2.abs()),
+        (Term::Numerator(_), [Term::Number(_, n2)]) => Term::Number(None, n2.clone()),
+        (Term::Numerator(_), [Term::Rational(_, r2)]) => Term::Number(None, r2.numer().clone()),
+        (Term::Denominator(_), [Term::Number(..)]) => Term::Number(None, One::one()),
+        (Term::Denominator(_), [Term::Rational(_, r2)]) => Term::Number(None, r2.denom().clone()),
+        _ => return Err(operator_error(info.clone(), operator, arg_len, term())),
+    })
+}
+
+/// Name of the type of the value `term`, or None if `term` is not evaluated yet.
+/// Integers and rationals are both `number`.
+pub(crate) fn type_of(term: &Term) -> Option<&'static str> {
+    Some(match term {
+        Term::Apply(..) | Term::Variable(..) | Term::If(..) => return None,
+        Term::Lambda(..) => "lambda",
+        Term::Quote(..) => "quote",
+        Term::Cons(..) => "cons",
+        Term::Nil(_) => "nil",
+        Term::Number(..) | Term::Rational(..) => "number",
+        Term::String(..) => "string",
+        Term::Char(..) => "char",
+        Term::Symbol(..) => "symbol",
+        Term::Bool(..) => "bool",
+        _ => "operator",
+    })
+}
+
+/// Whether `operator` is a type predicate or `type-of`, which accept values of any type.
+pub(crate) fn is_type_operator(operator: &Term) -> bool {
+    matches!(
+        operator,
+        Term::IsSymbol(_)
+            | Term::IsNumber(_)
+            | Term::IsBool(_)
+            | Term::IsString(_)
+            | Term::IsChar(_)
+            | Term::IsCons(_)
+            | Term::IsNil(_)
+            | Term::IsLambda(_)
+            | Term::IsQuote(_)
+            | Term::TypeOf(_)
+    )
+}
+
+/// Applies the type predicate or `type-of` to a value of the type `name`.
+pub(crate) fn apply_type_operator(operator: &Term, name: &'static str) -> Term {
+    let expected = match operator {
+        Term::TypeOf(_) => return Term::Symbol(None, name.into()),
+        Term::IsSymbol(_) => "symbol",
+        Term::IsNumber(_) => "number",
+        Term::IsBool(_) => "bool",
+        Term::IsString(_) => "string",
+        Term::IsChar(_) => "char",
+        Term::IsCons(_) => "cons",
+        Term::IsNil(_) => "nil",
+        Term::IsLambda(_) => "lambda",
+        Term::IsQuote(_) => "quote",
+        _ => unreachable!(),
+    };
+    Term::Bool(None, name == expected)
+}
+
+/// Type error for an operand out of the domain of the built-in operator.
+fn operand_error(
+    info: &Option<FileInfo>,
+    operator: &Term,
+    operand: &Term,
+    message: &str,
+) -> EvalError {
+    EvalError::Type(
+        CompileError {
+            info: operand
+                .file_info()
+                .clone()
+                .or_else(|| operator.file_info().clone())
+                .or_else(|| info.clone()),
+            message: Some(message.into()),
+        },
+        Some(Box::new(operand.clone())),
+    )
+}
+
+/// Number or rational as a rational.
+fn to_rational(term: &Term) -> BigRational {
+    match term {
+        Term::Number(_, n) => BigRational::from_integer(n.clone()),
+        Term::Rational(_, r) => r.clone(),
+        _ => unreachable!(),
     }
 }
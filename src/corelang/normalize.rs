@@ -0,0 +1,202 @@
+use super::eval::{
+    apply_atom_operator, apply_type_operator, is_type_operator, operator_arity, type_of, EvalError,
+    EvalLimit, EvalResult, Fuel,
+};
+use super::syntax::{
+    equiv_term, fill_hole, next_hole, shift_index, substitution, unshift_index, Term,
+};
+use crate::common::fileinfo::FileInfo;
+
+/// Reduces `term` to beta normal form, or beta-eta normal form if `eta`.
+/// Unlike `eval`, redexes in lambda bodies and cons are also reduced. Quoted terms are left as they are.
+///
+/// Reduction is in normal order (leftmost outermost first), so the normal form is found whenever it exists.
+/// Fails with `EvalError::FuelExhausted` when it is not found within `limit`,
+/// e.g. for terms without normal form like recursive functions by fixed point.
+pub fn normalize(term: &Term, limit: &EvalLimit, eta: bool) -> EvalResult {
+    normalize_with_fuel(term, &mut Fuel::new(limit), eta)
+}
+
+pub(crate) fn normalize_with_fuel(term: &Term, fuel: &mut Fuel, eta: bool) -> EvalResult {
+    let mut term = term.clone();
+    while let Some(next) = reduce_once(&term, eta, fuel)? {
+        term = next;
+    }
+    Ok(term)
+}
+
+/// Contracts the leftmost outermost redex, or returns None if `term` is normal.
+fn reduce_once(term: &Term, eta: bool, fuel: &mut Fuel) -> Result<Option<Term>, EvalError> {
+    if let Some(contracted) = contract(term, eta) {
+        fuel.consume(term.file_info(), 0)?;
+        return Ok(Some(contracted));
+    }
+    if let Term::Quote(..) = term {
+        return Ok(None);
+    }
+    let sub_vec = term.subterms();
+    for (i, st) in sub_vec.iter().enumerate() {
+        if let Some(reduced) = reduce_once(st, eta, fuel)? {
+            let mut sub_vec: Vec<Term> = sub_vec.into_iter().cloned().collect();
+            sub_vec[i] = reduced;
+            return Ok(Some(term.with_subterms(sub_vec)));
+        }
+    }
+    Ok(None)
+}
+
+fn has_redex(term: &Term, eta: bool) -> bool {
+    if contract(term, eta).is_some() {
+        return true;
+    }
+    match term {
+        Term::Quote(..) => false,
+        _ => term.subterms().into_iter().any(|st| has_redex(st, eta)),
+    }
+}
+
+/// Whether `term` has a variable bound outside of it which satisfies `pred`.
+/// pred: takes the De Bruijn index seen from `term`
+fn has_free_var<F>(term: &Term, pred: F) -> bool
+where
+    F: Fn(usize) -> bool,
+{
+    let mut task_vec = vec![(term, 0)];
+    while let Some((t, depth)) = task_vec.pop() {
+        match t {
+            Term::Variable(_, v, _) if *v >= depth && pred(v - depth) => return true,
+            Term::Lambda(_, _, t1) => task_vec.push((t1, depth + 1)),
+            _ => task_vec.extend(t.subterms().into_iter().map(|st| (st, depth))),
+        }
+    }
+    false
+}
+
+/// Body of the lambda with `args` substituted. Free variables of the body are shifted down
+/// since the lambda disappears, which `eval` does not need as it only substitutes closed terms.
+fn beta(body: &Term, args: &[Term]) -> Term {
+    let arg_vec: Vec<Term> = args.iter().map(|arg| shift_index(arg, 0, 1)).collect();
+    unshift_index(&substitution(body, 0, &arg_vec), 0, 1)
+}
+
+/// The redex `term` contracted, or None if `term` itself is not a redex.
+fn contract(term: &Term, eta: bool) -> Option<Term> {
+    match term {
+        Term::Apply(info, t1, ts) => match &**t1 {
+            Term::Lambda(_, arg_num, body) if *arg_num == ts.len() => Some(beta(body, ts)),
+            operator if operator_arity(operator) == Some(ts.len()) => {
+                contract_operator(info, operator, ts, eta)
+            }
+            _ => None,
+        },
+        Term::If(_, t1, t2, t3) => match &**t1 {
+            Term::Bool(_, true) => Some((**t2).clone()),
+            Term::Bool(_, false) => Some((**t3).clone()),
+            _ => None,
+        },
+        // (lambda (x1 .. xn) (f x1 .. xn)) => f
+        Term::Lambda(_, arg_num, body) if eta && *arg_num > 0 => match &**body {
+            Term::Apply(_, t1, ts)
+                if ts.len() == *arg_num
+                    && ts
+                        .iter()
+                        .enumerate()
+                        .all(|(i, t)| matches!(t, Term::Variable(_, 0, a) if *a == i))
+                    && !has_free_var(t1, |v| v == 0) =>
+            {
+                Some(unshift_index(t1, 0, 1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn contract_operator(
+    info: &Option<FileInfo>,
+    operator: &Term,
+    ts: &[Term],
+    eta: bool,
+) -> Option<Term> {
+    match (operator, ts) {
+        // Terms with free variables may become equal after substitution.
+        (Term::Eq(_) | Term::BetaEq(_), [t2, t3]) => {
+            if [t2, t3]
+                .iter()
+                .any(|t| has_free_var(t, |_| true) || has_redex(t, eta))
+            {
+                None
+            } else {
+                Some(Term::Bool(None, equiv_term(t2, t3)))
+            }
+        }
+        (_, [t2]) if is_type_operator(operator) => {
+            type_of(t2).map(|name| apply_type_operator(operator, name))
+        }
+        (Term::Eval(_), [Term::Quote(_, t2)]) => Some((**t2).clone()),
+        (Term::Car(_), [Term::Cons(_, t2, _)]) => Some((**t2).clone()),
+        (Term::Cdr(_), [Term::Cons(_, _, t3)]) => Some((**t3).clone()),
+        (Term::ListToString(_), [Term::Cons(_, t2, t3)]) => Some(Term::Apply(
+            info.clone(),
+            Box::new(Term::StringAppend(None)),
+            vec![
+                Term::Apply(
+                    info.clone(),
+                    Box::new(Term::CharToString(None)),
+                    vec![(**t2).clone()],
+                ),
+                Term::Apply(
+                    info.clone(),
+                    Box::new(operator.clone()),
+                    vec![(**t3).clone()],
+                ),
+            ],
+        )),
+        // same as `eval`, only for closed values since the value may be put under lambdas in the template
+        (Term::Fill(_), [Term::Quote(_, t2), t3]) if !has_free_var(t3, |_| true) => {
+            let quote = |t: Term| Term::Quote(info.clone(), t.into());
+            let fill = |hole, filling: &[Term]| Some(quote(fill_hole(t2, hole, filling).unwrap()));
+            match (next_hole(t2), t3) {
+                (None, _) => Some(quote((**t2).clone())),
+                (Some((_, false)), Term::Apply(..) | Term::If(..)) => None,
+                (Some(hole @ (_, false)), Term::Quote(_, t3)) => fill(hole, &[(**t3).clone()]),
+                (Some(hole @ (_, false)), _) => fill(hole, std::slice::from_ref(t3)),
+                (Some(hole @ (_, true)), Term::Nil(_)) => fill(hole, &[]),
+                (Some(hole @ (i, true)), Term::Cons(_, t31, t32)) => {
+                    let hole_vec = [
+                        Term::Unquote(info.clone(), i),
+                        Term::UnquoteSplicing(info.clone(), i),
+                    ];
+                    let apply = |t2: Term, t3: Term| {
+                        Term::Apply(info.clone(), Box::new(Term::Fill(None)), vec![t2, t3])
+                    };
+                    Some(apply(
+                        apply(fill(hole, &hole_vec).unwrap(), (**t31).clone()),
+                        (**t32).clone(),
+                    ))
+                }
+                _ => None,
+            }
+        }
+        (
+            Term::Eq(_)
+            | Term::BetaEq(_)
+            | Term::Eval(_)
+            | Term::Fill(_)
+            | Term::Car(_)
+            | Term::Cdr(_),
+            _,
+        ) => None,
+        _ => {
+            let is_atom = |t: &Term| t.subterms().is_empty() && !matches!(t, Term::Variable(..));
+            if ts.iter().all(is_atom) {
+                let arg_vec: Vec<&Term> = ts.iter().collect();
+                // Operators failing on the operands, e.g. division by 0, are left as they are,
+                // since the branch having them may never be taken.
+                apply_atom_operator(info.clone(), operator, &arg_vec).ok()
+            } else {
+                None
+            }
+        }
+    }
+}
@@ -1,5 +1,37 @@
 use super::super::syntax::Term;
 
+/// String literal of `s`, escaped so that the parser reads it back as `s`.
+pub fn print_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Char literal of `c`, e.g. `#\a`, `#\space`.
+pub fn print_char(c: char) -> String {
+    match c {
+        ' ' => "#\\space".into(),
+        '\n' => "#\\newline".into(),
+        '\r' => "#\\return".into(),
+        '\t' => "#\\tab".into(),
+        '\0' => "#\\nul".into(),
+        c if c.is_control() => format!("#\\u{{{:x}}}", c as u32),
+        c => format!("#\\{}", c),
+    }
+}
+
 pub struct SimplePrinter {}
 
 impl Default for SimplePrinter {
@@ -24,6 +56,7 @@ impl SimplePrinter {
             Term::Quote(_, t) => format!("(quote {})", self.print(t)),
             Term::Variable(_, v, a) => format!("ARG<{}-up {}-th>", v, a),
             Term::Eq(_) => "eq".into(),
+            Term::BetaEq(_) => "beta-eq".into(),
 
             Term::If(_, t1, t2, t3) => format!(
                 "(if {} {} {})",
@@ -36,16 +69,66 @@ impl SimplePrinter {
             Term::Nil(_) => "nil".into(),
 
             Term::Number(_, n) => n.to_string(),
+            Term::Rational(_, r) => r.to_string(),
+
+            Term::String(_, s) => print_string(s),
+            Term::Char(_, c) => print_char(*c),
+
+            Term::Symbol(_, s) => s.clone(),
 
             Term::Bool(_, b) => b.to_string(),
 
             Term::Eval(_) => "eval".into(),
+            Term::Fill(_) => "fill".into(),
+            Term::Unquote(_, i) => format!("UNQUOTE<{}>", i),
+            Term::UnquoteSplicing(_, i) => format!("UNQUOTE-SPLICING<{}>", i),
 
             Term::Add(_) => "+".into(),
             Term::Sub(_) => "-".into(),
             Term::Mul(_) => "*".into(),
             Term::Div(_) => "/".into(),
             Term::Rem(_) => "%".into(),
+            Term::Monus(_) => "monus".into(),
+            Term::Neg(_) => "neg".into(),
+            Term::Abs(_) => "abs".into(),
+            Term::Numerator(_) => "numerator".into(),
+            Term::Denominator(_) => "denominator".into(),
+            Term::Lt(_) => "<".into(),
+            Term::Le(_) => "<=".into(),
+            Term::Gt(_) => ">".into(),
+            Term::Ge(_) => ">=".into(),
+            Term::Min(_) => "min".into(),
+            Term::Max(_) => "max".into(),
+            Term::Pow(_) => "pow".into(),
+            Term::ModPow(_) => "modpow".into(),
+            Term::Gcd(_) => "gcd".into(),
+            Term::Isqrt(_) => "isqrt".into(),
+            Term::Shl(_) => "shl".into(),
+            Term::Shr(_) => "shr".into(),
+            Term::BitAnd(_) => "bit-and".into(),
+            Term::BitOr(_) => "bit-or".into(),
+            Term::BitXor(_) => "bit-xor".into(),
+            Term::StringLength(_) => "string-length".into(),
+            Term::StringAppend(_) => "string-append".into(),
+            Term::Substring(_) => "substring".into(),
+            Term::CharToInteger(_) => "char->integer".into(),
+            Term::IntegerToChar(_) => "integer->char".into(),
+            Term::CharToString(_) => "char->string".into(),
+            Term::StringToList(_) => "string->list".into(),
+            Term::ListToString(_) => "list->string".into(),
+            Term::IsSymbol(_) => "symbol?".into(),
+            Term::SymbolToString(_) => "symbol->string".into(),
+            Term::StringToSymbol(_) => "string->symbol".into(),
+            Term::Gensym(..) => "gensym".into(),
+            Term::IsNumber(_) => "number?".into(),
+            Term::IsBool(_) => "bool?".into(),
+            Term::IsString(_) => "string?".into(),
+            Term::IsChar(_) => "char?".into(),
+            Term::IsCons(_) => "cons?".into(),
+            Term::IsNil(_) => "nil?".into(),
+            Term::IsLambda(_) => "lambda?".into(),
+            Term::IsQuote(_) => "quote?".into(),
+            Term::TypeOf(_) => "type-of".into(),
 
             Term::Car(_) => "car".into(),
             Term::Cdr(_) => "cdr".into(),
@@ -1,5 +1,6 @@
 use crate::common::fileinfo::FileInfo;
-use num_bigint::BigUint;
+use num_bigint::BigInt;
+use num_rational::BigRational;
 
 // syntax
 #[derive(Clone, Debug)]
@@ -13,6 +14,8 @@ pub enum Term {
     /// (De Bruijn indexed, arg index in lambda)
     Variable(Option<FileInfo>, usize, usize),
     Eq(Option<FileInfo>),
+    /// equality of beta-eta normal forms
+    BetaEq(Option<FileInfo>),
 
     // (condition, then-clause, else-clause)
     If(Option<FileInfo>, Box<Term>, Box<Term>, Box<Term>),
@@ -22,13 +25,31 @@ pub enum Term {
     Nil(Option<FileInfo>),
 
     // arith
-    Number(Option<FileInfo>, BigUint),
+    Number(Option<FileInfo>, BigInt),
+    /// always reduced and not an integer. Integers are `Number`.
+    Rational(Option<FileInfo>, BigRational),
+
+    // string
+    String(Option<FileInfo>, String),
+    Char(Option<FileInfo>, char),
+
+    // symbol
+    /// name of an identifier unbound in quote
+    Symbol(Option<FileInfo>, String),
 
     // bool
     Bool(Option<FileInfo>, bool),
 
     // meta op
     Eval(Option<FileInfo>),
+    /// `(fill template value)` fills the hole with the smallest index in the quote `template` by `value`
+    Fill(Option<FileInfo>),
+
+    // quasiquote
+    /// (index of the unquoted term) hole in a quasiquote template
+    Unquote(Option<FileInfo>, usize),
+    /// (index of the unquoted term) hole for the elements of a list, in args of apply or car of cons
+    UnquoteSplicing(Option<FileInfo>, usize),
 
     // arith op
     Add(Option<FileInfo>),
@@ -36,6 +57,49 @@ pub enum Term {
     Mul(Option<FileInfo>),
     Div(Option<FileInfo>),
     Rem(Option<FileInfo>),
+    /// subtraction saturating at 0, i.e. subtraction of natural numbers
+    Monus(Option<FileInfo>),
+    Neg(Option<FileInfo>),
+    Abs(Option<FileInfo>),
+    Numerator(Option<FileInfo>),
+    Denominator(Option<FileInfo>),
+    Lt(Option<FileInfo>),
+    Le(Option<FileInfo>),
+    Gt(Option<FileInfo>),
+    Ge(Option<FileInfo>),
+    Min(Option<FileInfo>),
+    Max(Option<FileInfo>),
+    Pow(Option<FileInfo>),
+    ModPow(Option<FileInfo>),
+    Gcd(Option<FileInfo>),
+    Isqrt(Option<FileInfo>),
+    Shl(Option<FileInfo>),
+    Shr(Option<FileInfo>),
+    BitAnd(Option<FileInfo>),
+    BitOr(Option<FileInfo>),
+    BitXor(Option<FileInfo>),
+    StringLength(Option<FileInfo>),
+    StringAppend(Option<FileInfo>),
+    Substring(Option<FileInfo>),
+    CharToInteger(Option<FileInfo>),
+    IntegerToChar(Option<FileInfo>),
+    CharToString(Option<FileInfo>),
+    StringToList(Option<FileInfo>),
+    ListToString(Option<FileInfo>),
+    IsSymbol(Option<FileInfo>),
+    SymbolToString(Option<FileInfo>),
+    StringToSymbol(Option<FileInfo>),
+    /// makes names unique to each expansion of macros by appending `'` and the expansion number
+    Gensym(Option<FileInfo>, usize),
+    IsNumber(Option<FileInfo>),
+    IsBool(Option<FileInfo>),
+    IsString(Option<FileInfo>),
+    IsChar(Option<FileInfo>),
+    IsCons(Option<FileInfo>),
+    IsNil(Option<FileInfo>),
+    IsLambda(Option<FileInfo>),
+    IsQuote(Option<FileInfo>),
+    TypeOf(Option<FileInfo>),
 
     // structure op
     Car(Option<FileInfo>),
@@ -50,17 +114,66 @@ impl Term {
             Term::Quote(info, ..) => info,
             Term::Variable(info, ..) => info,
             Term::Eq(info, ..) => info,
+            Term::BetaEq(info, ..) => info,
             Term::Cons(info, ..) => info,
             Term::Nil(info, ..) => info,
             Term::Number(info, ..) => info,
+            Term::Rational(info, ..) => info,
+            Term::String(info, ..) => info,
+            Term::Char(info, ..) => info,
+            Term::Symbol(info, ..) => info,
             Term::Bool(info, ..) => info,
             Term::Eval(info, ..) => info,
+            Term::Fill(info, ..) => info,
+            Term::Unquote(info, ..) => info,
+            Term::UnquoteSplicing(info, ..) => info,
             Term::If(info, ..) => info,
             Term::Add(info, ..) => info,
             Term::Sub(info, ..) => info,
             Term::Mul(info, ..) => info,
             Term::Div(info, ..) => info,
             Term::Rem(info, ..) => info,
+            Term::Monus(info, ..) => info,
+            Term::Neg(info, ..) => info,
+            Term::Abs(info, ..) => info,
+            Term::Numerator(info, ..) => info,
+            Term::Denominator(info, ..) => info,
+            Term::Lt(info, ..) => info,
+            Term::Le(info, ..) => info,
+            Term::Gt(info, ..) => info,
+            Term::Ge(info, ..) => info,
+            Term::Min(info, ..) => info,
+            Term::Max(info, ..) => info,
+            Term::Pow(info, ..) => info,
+            Term::ModPow(info, ..) => info,
+            Term::Gcd(info, ..) => info,
+            Term::Isqrt(info, ..) => info,
+            Term::Shl(info, ..) => info,
+            Term::Shr(info, ..) => info,
+            Term::BitAnd(info, ..) => info,
+            Term::BitOr(info, ..) => info,
+            Term::BitXor(info, ..) => info,
+            Term::StringLength(info, ..) => info,
+            Term::StringAppend(info, ..) => info,
+            Term::Substring(info, ..) => info,
+            Term::CharToInteger(info, ..) => info,
+            Term::IntegerToChar(info, ..) => info,
+            Term::CharToString(info, ..) => info,
+            Term::StringToList(info, ..) => info,
+            Term::ListToString(info, ..) => info,
+            Term::IsSymbol(info, ..) => info,
+            Term::SymbolToString(info, ..) => info,
+            Term::StringToSymbol(info, ..) => info,
+            Term::Gensym(info, ..) => info,
+            Term::IsNumber(info, ..) => info,
+            Term::IsBool(info, ..) => info,
+            Term::IsString(info, ..) => info,
+            Term::IsChar(info, ..) => info,
+            Term::IsCons(info, ..) => info,
+            Term::IsNil(info, ..) => info,
+            Term::IsLambda(info, ..) => info,
+            Term::IsQuote(info, ..) => info,
+            Term::TypeOf(info, ..) => info,
             Term::Car(info, ..) => info,
             Term::Cdr(info, ..) => info,
         }
@@ -80,6 +193,7 @@ impl Term {
             Term::Variable(info, v1, a1) => Term::Variable(f((*info).clone()), *v1, *a1),
 
             Term::Eq(info) => Term::Eq(f((*info).clone())),
+            Term::BetaEq(info) => Term::BetaEq(f((*info).clone())),
 
             // structure
             Term::Cons(info, t1, t2) => {
@@ -89,12 +203,23 @@ impl Term {
 
             // arith
             Term::Number(info, n1) => Term::Number(f((*info).clone()), (*n1).clone()),
+            Term::Rational(info, r1) => Term::Rational(f((*info).clone()), (*r1).clone()),
+
+            // string
+            Term::String(info, s1) => Term::String(f((*info).clone()), (*s1).clone()),
+            Term::Char(info, c1) => Term::Char(f((*info).clone()), *c1),
+
+            // symbol
+            Term::Symbol(info, s1) => Term::Symbol(f((*info).clone()), (*s1).clone()),
 
             // bool
             Term::Bool(info, b1) => Term::Bool(f((*info).clone()), *b1),
 
             // meta op
             Term::Eval(info) => Term::Eval(f((*info).clone())),
+            Term::Fill(info) => Term::Fill(f((*info).clone())),
+            Term::Unquote(info, i1) => Term::Unquote(f((*info).clone()), *i1),
+            Term::UnquoteSplicing(info, i1) => Term::UnquoteSplicing(f((*info).clone()), *i1),
 
             // bool op
             Term::If(info, t1, t2, t3) => Term::If(
@@ -110,6 +235,47 @@ impl Term {
             Term::Mul(info) => Term::Mul(f((*info).clone())),
             Term::Div(info) => Term::Div(f((*info).clone())),
             Term::Rem(info) => Term::Rem(f((*info).clone())),
+            Term::Monus(info) => Term::Monus(f((*info).clone())),
+            Term::Neg(info) => Term::Neg(f((*info).clone())),
+            Term::Abs(info) => Term::Abs(f((*info).clone())),
+            Term::Numerator(info) => Term::Numerator(f((*info).clone())),
+            Term::Denominator(info) => Term::Denominator(f((*info).clone())),
+            Term::Lt(info) => Term::Lt(f((*info).clone())),
+            Term::Le(info) => Term::Le(f((*info).clone())),
+            Term::Gt(info) => Term::Gt(f((*info).clone())),
+            Term::Ge(info) => Term::Ge(f((*info).clone())),
+            Term::Min(info) => Term::Min(f((*info).clone())),
+            Term::Max(info) => Term::Max(f((*info).clone())),
+            Term::Pow(info) => Term::Pow(f((*info).clone())),
+            Term::ModPow(info) => Term::ModPow(f((*info).clone())),
+            Term::Gcd(info) => Term::Gcd(f((*info).clone())),
+            Term::Isqrt(info) => Term::Isqrt(f((*info).clone())),
+            Term::Shl(info) => Term::Shl(f((*info).clone())),
+            Term::Shr(info) => Term::Shr(f((*info).clone())),
+            Term::BitAnd(info) => Term::BitAnd(f((*info).clone())),
+            Term::BitOr(info) => Term::BitOr(f((*info).clone())),
+            Term::BitXor(info) => Term::BitXor(f((*info).clone())),
+            Term::StringLength(info) => Term::StringLength(f((*info).clone())),
+            Term::StringAppend(info) => Term::StringAppend(f((*info).clone())),
+            Term::Substring(info) => Term::Substring(f((*info).clone())),
+            Term::CharToInteger(info) => Term::CharToInteger(f((*info).clone())),
+            Term::IntegerToChar(info) => Term::IntegerToChar(f((*info).clone())),
+            Term::CharToString(info) => Term::CharToString(f((*info).clone())),
+            Term::StringToList(info) => Term::StringToList(f((*info).clone())),
+            Term::ListToString(info) => Term::ListToString(f((*info).clone())),
+            Term::IsSymbol(info) => Term::IsSymbol(f((*info).clone())),
+            Term::SymbolToString(info) => Term::SymbolToString(f((*info).clone())),
+            Term::StringToSymbol(info) => Term::StringToSymbol(f((*info).clone())),
+            Term::Gensym(info, count) => Term::Gensym(f((*info).clone()), *count),
+            Term::IsNumber(info) => Term::IsNumber(f((*info).clone())),
+            Term::IsBool(info) => Term::IsBool(f((*info).clone())),
+            Term::IsString(info) => Term::IsString(f((*info).clone())),
+            Term::IsChar(info) => Term::IsChar(f((*info).clone())),
+            Term::IsCons(info) => Term::IsCons(f((*info).clone())),
+            Term::IsNil(info) => Term::IsNil(f((*info).clone())),
+            Term::IsLambda(info) => Term::IsLambda(f((*info).clone())),
+            Term::IsQuote(info) => Term::IsQuote(f((*info).clone())),
+            Term::TypeOf(info) => Term::TypeOf(f((*info).clone())),
 
             // structure op
             Term::Car(info) => Term::Car(f((*info).clone())),
@@ -149,24 +315,134 @@ impl Term {
             _ => self.clone(),
         }
     }
+
+    /// Direct subterms in order.
+    pub fn subterms(&self) -> Vec<&Self> {
+        match self {
+            Self::Apply(_, t1, ts) => {
+                let mut vec = vec![&**t1];
+                vec.extend(ts.iter());
+                vec
+            }
+            Self::Lambda(_, _, t1) => vec![t1],
+            Self::Quote(_, t1) => vec![t1],
+            Self::Cons(_, t1, t2) => vec![t1, t2],
+            Self::If(_, t1, t2, t3) => vec![t1, t2, t3],
+            _ => vec![],
+        }
+    }
+
+    /// Same node as self with subterms replaced by `sub_vec`, given in the order of `subterms`.
+    pub fn with_subterms(&self, sub_vec: Vec<Self>) -> Self {
+        let mut it = sub_vec.into_iter();
+        let mut next = || Box::new(it.next().unwrap());
+        match self {
+            Self::Apply(info, ..) => {
+                let t1 = next();
+                Self::Apply((*info).clone(), t1, it.collect())
+            }
+            Self::Lambda(info, a1, _) => Self::Lambda((*info).clone(), *a1, next()),
+            Self::Quote(info, _) => Self::Quote((*info).clone(), next()),
+            Self::Cons(info, ..) => Self::Cons((*info).clone(), next(), next()),
+            Self::If(info, ..) => Self::If((*info).clone(), next(), next(), next()),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// (index, whether for splicing) of the hole of the quasiquote template `t` filled next,
+/// which has the smallest index. Each hole is unique, but a hole for splicing is followed by
+/// a hole of the same index for each element, which is filled first.
+pub fn next_hole(t: &Term) -> Option<(usize, bool)> {
+    let mut next = None;
+    let mut task_vec = vec![t];
+    while let Some(t) = task_vec.pop() {
+        let hole = match t {
+            Term::Unquote(_, i) => (*i, false),
+            Term::UnquoteSplicing(_, i) => (*i, true),
+            _ => {
+                task_vec.extend(t.subterms());
+                continue;
+            }
+        };
+        next = Some(next.map_or(hole, |next: (usize, bool)| next.min(hole)));
+    }
+    next
+}
+
+/// `t` with the hole `(index, whether for splicing)` replaced by `filling`,
+/// or None if `t` does not have the hole.
+/// A hole for splicing in args of apply or car of cons is replaced by the terms of `filling` in order.
+/// Other holes are replaced by the first term.
+pub fn fill_hole(t: &Term, hole: (usize, bool), filling: &[Term]) -> Option<Term> {
+    let is_hole = |t: &Term| match t {
+        Term::Unquote(_, i) => (*i, false) == hole,
+        Term::UnquoteSplicing(_, i) => (*i, true) == hole,
+        _ => false,
+    };
+    if is_hole(t) {
+        return filling.first().cloned();
+    }
+    match t {
+        Term::Apply(info, t1, ts) => {
+            if let Some(p) = ts.iter().position(is_hole) {
+                let mut arg_vec = ts[..p].to_vec();
+                arg_vec.extend(filling.iter().cloned());
+                arg_vec.extend(ts[p + 1..].iter().cloned());
+                return Some(Term::Apply(info.clone(), t1.clone(), arg_vec));
+            }
+        }
+        Term::Cons(info, t1, t2) if is_hole(t1) => {
+            return Some(filling.iter().rev().fold((**t2).clone(), |acc, e| {
+                Term::Cons(info.clone(), e.clone().into(), acc.into())
+            }));
+        }
+        _ => {}
+    }
+    let mut sub_vec: Vec<Term> = t.subterms().into_iter().cloned().collect();
+    for i in 0..sub_vec.len() {
+        if let Some(filled) = fill_hole(&sub_vec[i], hole, filling) {
+            sub_vec[i] = filled;
+            return Some(t.with_subterms(sub_vec));
+        }
+    }
+    None
+}
+
+/// Number term of `r`, or rational term if `r` is not an integer.
+pub fn rational_to_term(info: Option<FileInfo>, r: BigRational) -> Term {
+    if r.is_integer() {
+        Term::Number(info, r.to_integer())
+    } else {
+        Term::Rational(info, r)
+    }
 }
 
 /// Shift De Bruijn index by d more than or equal to c.
 /// c: threshold inclusive
 /// d: increased width
 pub fn shift_index(t: &Term, c: usize, d: usize) -> Term {
-    match t {
-        Term::Variable(info, v, a) => {
-            Term::Variable((*info).clone(), if *v < c { *v } else { v + d }, *a)
-        }
-        Term::Lambda(info, arg_num, body) => Term::Lambda(
+    rebuild(t, |s, depth| match s {
+        Term::Variable(info, v, a) => Some(Term::Variable(
             (*info).clone(),
-            *arg_num,
-            shift_index(&**body, c + 1, d).into(),
-        ),
+            if *v < c + depth { *v } else { v + d },
+            *a,
+        )),
+        _ => None,
+    })
+}
 
-        _ => t.map_subterm(|s| shift_index(&s, c, d)),
-    }
+/// Shift De Bruijn index by -d more than or equal to c, the inverse of `shift_index`.
+/// Indices in [c, c + d) must not appear.
+pub fn unshift_index(t: &Term, c: usize, d: usize) -> Term {
+    rebuild(t, |s, depth| match s {
+        Term::Variable(info, v, a) => Some(Term::Variable(
+            (*info).clone(),
+            if *v < c + depth { *v } else { v - d },
+            *a,
+        )),
+        _ => None,
+    })
 }
 
 pub fn equiv_term_vec(t1: &Vec<Term>, t2: &Vec<Term>) -> bool {
@@ -206,6 +482,11 @@ pub fn equiv_term(t1: &Term, t2: &Term) -> bool {
                 return true;
             }
         }
+        Term::BetaEq(_) => {
+            if let Term::BetaEq(_) = t2 {
+                return true;
+            }
+        }
 
         Term::If(_, t11, t12, t13) => {
             if let Term::If(_, t21, t22, t23) = t2 {
@@ -233,6 +514,30 @@ pub fn equiv_term(t1: &Term, t2: &Term) -> bool {
                 return n1 == n2;
             }
         }
+        Term::Rational(_, r1) => {
+            if let Term::Rational(_, r2) = t2 {
+                return r1 == r2;
+            }
+        }
+
+        // string
+        Term::String(_, s1) => {
+            if let Term::String(_, s2) = t2 {
+                return s1 == s2;
+            }
+        }
+        Term::Char(_, c1) => {
+            if let Term::Char(_, c2) = t2 {
+                return c1 == c2;
+            }
+        }
+
+        // symbol
+        Term::Symbol(_, s1) => {
+            if let Term::Symbol(_, s2) = t2 {
+                return s1 == s2;
+            }
+        }
 
         // bool
         Term::Bool(_, b1) => {
@@ -247,6 +552,23 @@ pub fn equiv_term(t1: &Term, t2: &Term) -> bool {
                 return true;
             }
         }
+        Term::Fill(_) => {
+            if let Term::Fill(_) = t2 {
+                return true;
+            }
+        }
+
+        // quasiquote
+        Term::Unquote(_, i1) => {
+            if let Term::Unquote(_, i2) = t2 {
+                return i1 == i2;
+            }
+        }
+        Term::UnquoteSplicing(_, i1) => {
+            if let Term::UnquoteSplicing(_, i2) = t2 {
+                return i1 == i2;
+            }
+        }
 
         // arith op
         Term::Add(_) => {
@@ -274,6 +596,211 @@ pub fn equiv_term(t1: &Term, t2: &Term) -> bool {
                 return true;
             }
         }
+        Term::Monus(_) => {
+            if let Term::Monus(_) = t2 {
+                return true;
+            }
+        }
+        Term::Neg(_) => {
+            if let Term::Neg(_) = t2 {
+                return true;
+            }
+        }
+        Term::Abs(_) => {
+            if let Term::Abs(_) = t2 {
+                return true;
+            }
+        }
+        Term::Numerator(_) => {
+            if let Term::Numerator(_) = t2 {
+                return true;
+            }
+        }
+        Term::Denominator(_) => {
+            if let Term::Denominator(_) = t2 {
+                return true;
+            }
+        }
+        Term::Lt(_) => {
+            if let Term::Lt(_) = t2 {
+                return true;
+            }
+        }
+        Term::Le(_) => {
+            if let Term::Le(_) = t2 {
+                return true;
+            }
+        }
+        Term::Gt(_) => {
+            if let Term::Gt(_) = t2 {
+                return true;
+            }
+        }
+        Term::Ge(_) => {
+            if let Term::Ge(_) = t2 {
+                return true;
+            }
+        }
+        Term::Min(_) => {
+            if let Term::Min(_) = t2 {
+                return true;
+            }
+        }
+        Term::Max(_) => {
+            if let Term::Max(_) = t2 {
+                return true;
+            }
+        }
+        Term::Pow(_) => {
+            if let Term::Pow(_) = t2 {
+                return true;
+            }
+        }
+        Term::ModPow(_) => {
+            if let Term::ModPow(_) = t2 {
+                return true;
+            }
+        }
+        Term::Gcd(_) => {
+            if let Term::Gcd(_) = t2 {
+                return true;
+            }
+        }
+        Term::Isqrt(_) => {
+            if let Term::Isqrt(_) = t2 {
+                return true;
+            }
+        }
+        Term::Shl(_) => {
+            if let Term::Shl(_) = t2 {
+                return true;
+            }
+        }
+        Term::Shr(_) => {
+            if let Term::Shr(_) = t2 {
+                return true;
+            }
+        }
+        Term::BitAnd(_) => {
+            if let Term::BitAnd(_) = t2 {
+                return true;
+            }
+        }
+        Term::BitOr(_) => {
+            if let Term::BitOr(_) = t2 {
+                return true;
+            }
+        }
+        Term::BitXor(_) => {
+            if let Term::BitXor(_) = t2 {
+                return true;
+            }
+        }
+        Term::StringLength(_) => {
+            if let Term::StringLength(_) = t2 {
+                return true;
+            }
+        }
+        Term::StringAppend(_) => {
+            if let Term::StringAppend(_) = t2 {
+                return true;
+            }
+        }
+        Term::Substring(_) => {
+            if let Term::Substring(_) = t2 {
+                return true;
+            }
+        }
+        Term::CharToInteger(_) => {
+            if let Term::CharToInteger(_) = t2 {
+                return true;
+            }
+        }
+        Term::IntegerToChar(_) => {
+            if let Term::IntegerToChar(_) = t2 {
+                return true;
+            }
+        }
+        Term::CharToString(_) => {
+            if let Term::CharToString(_) = t2 {
+                return true;
+            }
+        }
+        Term::StringToList(_) => {
+            if let Term::StringToList(_) = t2 {
+                return true;
+            }
+        }
+        Term::ListToString(_) => {
+            if let Term::ListToString(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsSymbol(_) => {
+            if let Term::IsSymbol(_) = t2 {
+                return true;
+            }
+        }
+        Term::SymbolToString(_) => {
+            if let Term::SymbolToString(_) = t2 {
+                return true;
+            }
+        }
+        Term::StringToSymbol(_) => {
+            if let Term::StringToSymbol(_) = t2 {
+                return true;
+            }
+        }
+        Term::Gensym(_, count1) => {
+            if let Term::Gensym(_, count2) = t2 {
+                return count1 == count2;
+            }
+        }
+        Term::IsNumber(_) => {
+            if let Term::IsNumber(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsBool(_) => {
+            if let Term::IsBool(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsString(_) => {
+            if let Term::IsString(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsChar(_) => {
+            if let Term::IsChar(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsCons(_) => {
+            if let Term::IsCons(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsNil(_) => {
+            if let Term::IsNil(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsLambda(_) => {
+            if let Term::IsLambda(_) = t2 {
+                return true;
+            }
+        }
+        Term::IsQuote(_) => {
+            if let Term::IsQuote(_) = t2 {
+                return true;
+            }
+        }
+        Term::TypeOf(_) => {
+            if let Term::TypeOf(_) = t2 {
+                return true;
+            }
+        }
 
         // structure op
         Term::Car(_) => {
@@ -290,20 +817,51 @@ pub fn equiv_term(t1: &Term, t2: &Term) -> bool {
     false
 }
 
-pub fn substitution(term: &Term, from: usize, to_vec: &Vec<Term>) -> Term {
-    match term {
-        Term::Variable(_, v1, w1) if *v1 == from => to_vec[*w1].clone(),
-        Term::Lambda(info, arg_num, body) => Term::Lambda(
-            info.clone(),
-            *arg_num,
-            substitution(
-                &**body,
-                from + 1,
-                &to_vec.iter().map(|e| shift_index(e, 0, 1)).collect(),
-            )
-            .into(),
-        ),
-
-        _ => term.map_subterm(|s| substitution(&s, from, to_vec)),
+pub fn substitution(term: &Term, from: usize, to_vec: &[Term]) -> Term {
+    rebuild(term, |s, depth| match s {
+        Term::Variable(_, v1, w1) if *v1 == from + depth => Some(if depth == 0 {
+            to_vec[*w1].clone()
+        } else {
+            shift_index(&to_vec[*w1], 0, depth)
+        }),
+        _ => None,
+    })
+}
+
+/// Rebuilds `term` bottom-up with an explicit stack instead of recursion.
+/// `f(subterm, depth)` returns a replacement of the subterm, or None to rebuild it from its children.
+/// depth: number of lambdas enclosing the subterm
+fn rebuild<F>(term: &Term, f: F) -> Term
+where
+    F: Fn(&Term, usize) -> Option<Term>,
+{
+    enum Task<'a> {
+        Visit(&'a Term, usize),
+        Build(&'a Term),
+    }
+    let mut task_vec = vec![Task::Visit(term, 0)];
+    let mut built = Vec::<Term>::new();
+    while let Some(task) = task_vec.pop() {
+        match task {
+            Task::Visit(t, depth) => {
+                if let Some(replaced) = f(t, depth) {
+                    built.push(replaced);
+                    continue;
+                }
+                task_vec.push(Task::Build(t));
+                let sub_depth = match t {
+                    Term::Lambda(..) => depth + 1,
+                    _ => depth,
+                };
+                for st in t.subterms().into_iter().rev() {
+                    task_vec.push(Task::Visit(st, sub_depth));
+                }
+            }
+            Task::Build(t) => {
+                let sub_vec = built.split_off(built.len() - t.subterms().len());
+                built.push(t.with_subterms(sub_vec));
+            }
+        }
     }
+    built.pop().unwrap()
 }
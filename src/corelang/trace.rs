@@ -0,0 +1,212 @@
+use super::eval::EvalError;
+use super::printer::simple::SimplePrinter;
+use super::syntax::Term;
+use super::value::Closure;
+use std::fmt;
+use std::str::FromStr;
+
+/// Reduction rule applied in a step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// lambda application
+    Beta,
+    /// built-in operator application, with the name of the operator
+    Delta(&'static str),
+    IfTrue,
+    IfFalse,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Beta => write!(f, "beta"),
+            Rule::Delta(name) => write!(f, "delta-{}", name),
+            Rule::IfTrue => write!(f, "if-true"),
+            Rule::IfFalse => write!(f, "if-false"),
+        }
+    }
+}
+
+/// Name of a built-in operator used in `Rule::Delta`.
+pub fn operator_name(operator: &Term) -> &'static str {
+    match operator {
+        Term::Eq(_) => "eq",
+        Term::BetaEq(_) => "beta-eq",
+        Term::Eval(_) => "eval",
+        Term::Fill(_) => "fill",
+        Term::Add(_) => "add",
+        Term::Sub(_) => "sub",
+        Term::Mul(_) => "mul",
+        Term::Div(_) => "div",
+        Term::Rem(_) => "rem",
+        Term::Monus(_) => "monus",
+        Term::Neg(_) => "neg",
+        Term::Abs(_) => "abs",
+        Term::Numerator(_) => "numerator",
+        Term::Denominator(_) => "denominator",
+        Term::Lt(_) => "lt",
+        Term::Le(_) => "le",
+        Term::Gt(_) => "gt",
+        Term::Ge(_) => "ge",
+        Term::Min(_) => "min",
+        Term::Max(_) => "max",
+        Term::Pow(_) => "pow",
+        Term::ModPow(_) => "modpow",
+        Term::Gcd(_) => "gcd",
+        Term::Isqrt(_) => "isqrt",
+        Term::Shl(_) => "shl",
+        Term::Shr(_) => "shr",
+        Term::BitAnd(_) => "bit-and",
+        Term::BitOr(_) => "bit-or",
+        Term::BitXor(_) => "bit-xor",
+        Term::StringLength(_) => "string-length",
+        Term::StringAppend(_) => "string-append",
+        Term::Substring(_) => "substring",
+        Term::CharToInteger(_) => "char->integer",
+        Term::IntegerToChar(_) => "integer->char",
+        Term::CharToString(_) => "char->string",
+        Term::StringToList(_) => "string->list",
+        Term::ListToString(_) => "list->string",
+        Term::IsSymbol(_) => "symbol?",
+        Term::SymbolToString(_) => "symbol->string",
+        Term::StringToSymbol(_) => "string->symbol",
+        Term::Gensym(..) => "gensym",
+        Term::IsNumber(_) => "number?",
+        Term::IsBool(_) => "bool?",
+        Term::IsString(_) => "string?",
+        Term::IsChar(_) => "char?",
+        Term::IsCons(_) => "cons?",
+        Term::IsNil(_) => "nil?",
+        Term::IsLambda(_) => "lambda?",
+        Term::IsQuote(_) => "quote?",
+        Term::TypeOf(_) => "type-of",
+        Term::Car(_) => "car",
+        Term::Cdr(_) => "cdr",
+        _ => "op",
+    }
+}
+
+/// One reduction step of the evaluator.
+#[derive(Clone, Debug)]
+pub struct TraceStep {
+    /// 1-origin count of reductions in the evaluation
+    pub step: u64,
+    /// number of pending continuations, i.e. how deep the redex is in the evaluation
+    pub depth: usize,
+    pub rule: Rule,
+    pub redex: Term,
+    /// term the evaluation continues with
+    pub result: Term,
+}
+
+/// Receiver of reduction steps.
+pub trait Tracer {
+    /// Whether steps at `depth` are traced. Terms of the steps are read back only if true.
+    fn accepts(&self, _depth: usize) -> bool {
+        true
+    }
+
+    fn trace(&mut self, step: &TraceStep);
+
+    /// Called before the application `closure` is evaluated, with the number of pending continuations.
+    /// It may block the evaluation, e.g. to wait for debugger commands, or abort it by an error.
+    fn apply(&mut self, _closure: &Closure, _depth: usize) -> Result<(), EvalError> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TraceFormat {
+    /// one line per step, indented by depth
+    #[default]
+    Text,
+    /// one JSON object per line
+    Json,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(format!(
+                "unknown trace format \"{}\": expected text or json",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TraceOption {
+    pub format: TraceFormat,
+    /// steps deeper than this are not traced
+    pub max_depth: Option<usize>,
+}
+
+/// Tracer printing steps to stderr, so that they do not mix with the evaluated values.
+pub struct PrintTracer {
+    option: TraceOption,
+}
+
+impl PrintTracer {
+    pub fn new(option: TraceOption) -> Self {
+        Self { option }
+    }
+
+    pub fn format(&self, step: &TraceStep) -> String {
+        let printer = SimplePrinter::default();
+        let redex = printer.print(&step.redex);
+        let result = printer.print(&step.result);
+        match self.option.format {
+            TraceFormat::Text => format!(
+                "{:>5} {}{}: {} => {}",
+                step.step,
+                " ".repeat(step.depth),
+                step.rule,
+                redex,
+                result
+            ),
+            TraceFormat::Json => format!(
+                "{{\"step\":{},\"depth\":{},\"rule\":{},\"redex\":{},\"result\":{}}}",
+                step.step,
+                step.depth,
+                json_string(&step.rule.to_string()),
+                json_string(&redex),
+                json_string(&result)
+            ),
+        }
+    }
+}
+
+impl Tracer for PrintTracer {
+    fn accepts(&self, depth: usize) -> bool {
+        self.option
+            .max_depth
+            .is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    fn trace(&mut self, step: &TraceStep) {
+        eprintln!("{}", self.format(step));
+    }
+}
+
+/// JSON string literal of `s`.
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
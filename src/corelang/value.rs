@@ -0,0 +1,313 @@
+use super::syntax::{equiv_term, Term};
+use crate::common::fileinfo::FileInfo;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Term whose subterms are shared by `Rc`, so that closures can point into it without cloning.
+#[derive(Debug)]
+pub enum Code {
+    /// (operator, operand)
+    Apply(Option<FileInfo>, Rc<Code>, Vec<Rc<Code>>),
+    /// (number of args, body)
+    Lambda(Option<FileInfo>, usize, Rc<Code>),
+    Quote(Option<FileInfo>, Rc<Code>),
+    /// (De Bruijn indexed, arg index in lambda)
+    Variable(Option<FileInfo>, usize, usize),
+    /// (condition, then-clause, else-clause)
+    If(Option<FileInfo>, Rc<Code>, Rc<Code>, Rc<Code>),
+    Cons(Option<FileInfo>, Rc<Code>, Rc<Code>),
+    /// Term without subterm, e.g. number, bool, nil and operators.
+    Atom(Term),
+}
+
+impl Code {
+    pub fn file_info(&self) -> &Option<FileInfo> {
+        match self {
+            Code::Apply(info, ..) => info,
+            Code::Lambda(info, ..) => info,
+            Code::Quote(info, ..) => info,
+            Code::Variable(info, ..) => info,
+            Code::If(info, ..) => info,
+            Code::Cons(info, ..) => info,
+            Code::Atom(term) => term.file_info(),
+        }
+    }
+
+    /// Direct subterms in order.
+    fn subcodes(&self) -> Vec<&Rc<Self>> {
+        match self {
+            Code::Apply(_, c1, cs) => {
+                let mut vec = vec![c1];
+                vec.extend(cs.iter());
+                vec
+            }
+            Code::Lambda(_, _, c1) => vec![c1],
+            Code::Quote(_, c1) => vec![c1],
+            Code::If(_, c1, c2, c3) => vec![c1, c2, c3],
+            Code::Cons(_, c1, c2) => vec![c1, c2],
+            Code::Variable(..) | Code::Atom(..) => vec![],
+        }
+    }
+
+    /// The tail of a cons not shared by others, replaced by nil.
+    fn take_tail(&mut self) -> Option<Rc<Code>> {
+        match self {
+            Code::Cons(_, _, c2) if Rc::strong_count(c2) == 1 => {
+                Some(std::mem::replace(c2, Rc::new(Code::Atom(Term::Nil(None)))))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Code {
+    /// Unlinks the tails of conses iteratively, since a list such as of `string->list` can be long.
+    fn drop(&mut self) {
+        let mut tail = self.take_tail();
+        while let Some(code) = tail {
+            tail = Rc::try_unwrap(code)
+                .ok()
+                .and_then(|mut code| code.take_tail());
+        }
+    }
+}
+
+/// Converts `term` to `Code` with an explicit stack instead of recursion.
+pub fn compile(term: &Term) -> Rc<Code> {
+    enum Task<'a> {
+        Visit(&'a Term),
+        Build(&'a Term),
+    }
+    let mut task_vec = vec![Task::Visit(term)];
+    let mut built = Vec::<Rc<Code>>::new();
+    while let Some(task) = task_vec.pop() {
+        match task {
+            Task::Visit(t) => {
+                task_vec.push(Task::Build(t));
+                for st in t.subterms().into_iter().rev() {
+                    task_vec.push(Task::Visit(st));
+                }
+            }
+            Task::Build(t) => {
+                let mut sub_vec = built.split_off(built.len() - t.subterms().len());
+                let mut next = || sub_vec.remove(0);
+                let code = match t {
+                    Term::Apply(info, ..) => {
+                        let c1 = next();
+                        Code::Apply(info.clone(), c1, sub_vec)
+                    }
+                    Term::Lambda(info, arg_num, _) => Code::Lambda(info.clone(), *arg_num, next()),
+                    Term::Quote(info, _) => Code::Quote(info.clone(), next()),
+                    Term::Variable(info, v, a) => Code::Variable(info.clone(), *v, *a),
+                    Term::If(info, ..) => Code::If(info.clone(), next(), next(), next()),
+                    Term::Cons(info, ..) => Code::Cons(info.clone(), next(), next()),
+                    _ => Code::Atom(t.clone()),
+                };
+                built.push(code.into());
+            }
+        }
+    }
+    built.pop().unwrap()
+}
+
+/// Linked frames of arguments. The innermost lambda's arguments come first.
+pub type Env = Option<Rc<Frame>>;
+
+pub struct Frame {
+    /// file info of the lambda applied to make this frame
+    pub info: Option<FileInfo>,
+    pub arg_vec: Vec<RefCell<Thunk>>,
+    pub parent: Env,
+}
+
+/// Argument bound in a frame.
+#[derive(Clone)]
+pub enum Thunk {
+    /// argument term not evaluated yet
+    Delayed(Closure),
+    /// evaluated value of the argument
+    Forced(Closure),
+}
+
+impl Thunk {
+    pub fn closure(&self) -> &Closure {
+        match self {
+            Thunk::Delayed(closure) => closure,
+            Thunk::Forced(closure) => closure,
+        }
+    }
+}
+
+impl Drop for Frame {
+    /// Unlinks frames iteratively, since chains of frames can be as long as the recursion.
+    fn drop(&mut self) {
+        let mut env_vec: Vec<Rc<Frame>> = self.take_env_vec();
+        while let Some(env) = env_vec.pop() {
+            if let Ok(mut frame) = Rc::try_unwrap(env) {
+                env_vec.append(&mut frame.take_env_vec());
+            }
+        }
+    }
+}
+
+impl Frame {
+    /// The frame of the lambda `v` levels up.
+    pub fn find(env: &Env, v: usize) -> Option<&Rc<Frame>> {
+        let mut frame = env.as_ref()?;
+        for _ in 0..v {
+            frame = frame.parent.as_ref()?;
+        }
+        Some(frame)
+    }
+
+    fn take_env_vec(&mut self) -> Vec<Rc<Frame>> {
+        std::mem::take(&mut self.arg_vec)
+            .into_iter()
+            .filter_map(|arg| match arg.into_inner() {
+                Thunk::Delayed(closure) => closure.env,
+                Thunk::Forced(closure) => closure.env,
+            })
+            .chain(self.parent.take())
+            .collect()
+    }
+}
+
+/// `Code` paired with the environment its free variables refer to.
+#[derive(Clone)]
+pub struct Closure {
+    pub code: Rc<Code>,
+    pub env: Env,
+}
+
+impl Closure {
+    pub fn new(code: Rc<Code>, env: Env) -> Self {
+        Self { code, env }
+    }
+
+    /// Looks up the argument which the De Bruijn indexed variable refers to.
+    pub fn lookup(env: &Env, v: usize, a: usize) -> Option<Closure> {
+        let arg = Frame::find(env, v)?.arg_vec.get(a)?;
+        let closure = arg.borrow().closure().clone();
+        Some(closure)
+    }
+
+    /// Follows variables to the argument they are bound to.
+    /// Same as the term placed by substitution.
+    pub fn deref(&self) -> Closure {
+        let mut closure = self.clone();
+        while let Code::Variable(_, v, a) = &*closure.code {
+            match Closure::lookup(&closure.env, *v, *a) {
+                Some(arg) => closure = arg,
+                None => break,
+            }
+        }
+        closure
+    }
+
+    /// Converts back to `Term` by substituting the environment,
+    /// so that quoted terms and printed values look the same as ones evaluated by substitution.
+    pub fn read_back(&self) -> Term {
+        enum Task {
+            Visit(Closure, usize),
+            Build(Rc<Code>),
+        }
+        let mut task_vec = vec![Task::Visit(self.clone(), 0)];
+        let mut built = Vec::<Term>::new();
+        while let Some(task) = task_vec.pop() {
+            match task {
+                Task::Visit(closure, depth) => match &*closure.code {
+                    Code::Variable(info, v, a) => {
+                        if *v < depth {
+                            built.push(Term::Variable(info.clone(), *v, *a));
+                        } else {
+                            match Closure::lookup(&closure.env, v - depth, *a) {
+                                // Arguments are closed, so they need no shift.
+                                Some(arg) => task_vec.push(Task::Visit(arg, 0)),
+                                None => built.push(Term::Variable(info.clone(), *v, *a)),
+                            }
+                        }
+                    }
+                    Code::Atom(t) => built.push(t.clone()),
+                    code => {
+                        let sub_depth = match code {
+                            Code::Lambda(..) => depth + 1,
+                            _ => depth,
+                        };
+                        task_vec.push(Task::Build(closure.code.clone()));
+                        for sc in code.subcodes().into_iter().rev() {
+                            task_vec.push(Task::Visit(
+                                Closure::new(sc.clone(), closure.env.clone()),
+                                sub_depth,
+                            ));
+                        }
+                    }
+                },
+                Task::Build(code) => {
+                    let mut sub_vec = built
+                        .split_off(built.len() - code.subcodes().len())
+                        .into_iter();
+                    let mut next = || Box::new(sub_vec.next().unwrap());
+                    let term = match &*code {
+                        Code::Apply(info, ..) => {
+                            let t1 = next();
+                            Term::Apply(info.clone(), t1, sub_vec.collect())
+                        }
+                        Code::Lambda(info, arg_num, _) => {
+                            Term::Lambda(info.clone(), *arg_num, next())
+                        }
+                        Code::Quote(info, _) => Term::Quote(info.clone(), next()),
+                        Code::If(info, ..) => Term::If(info.clone(), next(), next(), next()),
+                        Code::Cons(info, ..) => Term::Cons(info.clone(), next(), next()),
+                        Code::Variable(..) | Code::Atom(..) => unreachable!(),
+                    };
+                    built.push(term);
+                }
+            }
+        }
+        built.pop().unwrap()
+    }
+}
+
+/// Same as `equiv_term` on the read back terms,
+/// but reads back lazily and stops at the first difference.
+pub fn equiv_closure(c1: &Closure, c2: &Closure) -> bool {
+    // Follows free variables. Bound ones are compared by index.
+    let resolve = |closure: Closure, depth: usize| match &*closure.code {
+        Code::Variable(_, v, a) if *v >= depth => {
+            match Closure::lookup(&closure.env, v - depth, *a) {
+                Some(arg) => (arg.deref(), 0),
+                None => (closure, depth),
+            }
+        }
+        _ => (closure, depth),
+    };
+    let mut pair_vec = vec![((c1.clone(), 0), (c2.clone(), 0))];
+    while let Some(((c1, d1), (c2, d2))) = pair_vec.pop() {
+        let (c1, d1) = resolve(c1, d1);
+        let (c2, d2) = resolve(c2, d2);
+        let same_node = match (&*c1.code, &*c2.code) {
+            (Code::Apply(_, _, cs1), Code::Apply(_, _, cs2)) => cs1.len() == cs2.len(),
+            (Code::Lambda(_, a1, _), Code::Lambda(_, a2, _)) => a1 == a2,
+            (Code::Quote(..), Code::Quote(..)) => true,
+            (Code::Variable(_, v1, a1), Code::Variable(_, v2, a2)) => v1 == v2 && a1 == a2,
+            (Code::If(..), Code::If(..)) => true,
+            (Code::Cons(..), Code::Cons(..)) => true,
+            (Code::Atom(t1), Code::Atom(t2)) => equiv_term(t1, t2),
+            _ => false,
+        };
+        if !same_node {
+            return false;
+        }
+        let (sd1, sd2) = match &*c1.code {
+            Code::Lambda(..) => (d1 + 1, d2 + 1),
+            _ => (d1, d2),
+        };
+        for (sc1, sc2) in c1.code.subcodes().into_iter().zip(c2.code.subcodes()) {
+            pair_vec.push((
+                (Closure::new(sc1.clone(), c1.env.clone()), sd1),
+                (Closure::new(sc2.clone(), c2.env.clone()), sd2),
+            ));
+        }
+    }
+    true
+}
@@ -1,5 +1,8 @@
 use clap::Parser;
-use lambdalisp::action::run;
+use lambdalisp::action::{debug, expand, repl, run};
+use lambdalisp::corelang::eval::{EvalLimit, EvalStrategy};
+use lambdalisp::corelang::trace::{TraceFormat, TraceOption};
+use std::time::Duration;
 
 // use lambdalisp::common::fileinfo::CompileError;
 // use lambdalisp::corelang::printer::simple::SimplePrinter;
@@ -16,6 +19,9 @@ struct Args {
 enum Action {
     Run(RunAction),
     Test(TestAction),
+    Repl(ReplAction),
+    Debug(DebugAction),
+    Expand(ExpandAction),
 }
 
 #[derive(clap::Args, Debug)]
@@ -24,6 +30,8 @@ struct RunAction {
     filepath: String,
     #[clap(short, long)]
     verbose: bool,
+    #[clap(flatten)]
+    eval: EvalArgs,
 }
 
 #[derive(clap::Args, Debug)]
@@ -32,6 +40,77 @@ struct TestAction {
     filepath: String,
     #[clap(short, long)]
     verbose: bool,
+    #[clap(flatten)]
+    eval: EvalArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplAction {
+    #[clap(short, long)]
+    verbose: bool,
+    #[clap(flatten)]
+    eval: EvalArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DebugAction {
+    #[clap(value_parser)]
+    filepath: String,
+    #[clap(flatten)]
+    eval: EvalArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ExpandAction {
+    #[clap(value_parser)]
+    filepath: String,
+    #[clap(flatten)]
+    eval: EvalArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct EvalArgs {
+    /// Argument passing of lambda application: name, value or need
+    #[clap(long, default_value = "name")]
+    strategy: EvalStrategy,
+    /// Max reduction steps for each evaluated term
+    #[clap(long)]
+    max_steps: Option<u64>,
+    /// Max evaluation depth for each evaluated term
+    #[clap(long)]
+    max_depth: Option<usize>,
+    /// Timeout in milliseconds for each evaluated term
+    #[clap(long)]
+    timeout: Option<u64>,
+    /// Print each reduction step to stderr
+    #[clap(long)]
+    trace: bool,
+    /// Trace only steps nested at most this deep
+    #[clap(long)]
+    trace_depth: Option<usize>,
+    /// Format of traced steps: text or json (one object per line)
+    #[clap(long, default_value = "text")]
+    trace_format: TraceFormat,
+    /// Color error messages with ANSI escape sequences
+    #[clap(long)]
+    color: bool,
+}
+
+impl EvalArgs {
+    fn to_limit(&self) -> EvalLimit {
+        EvalLimit {
+            max_steps: self.max_steps,
+            max_depth: self.max_depth,
+            timeout: self.timeout.map(Duration::from_millis),
+        }
+    }
+
+    fn to_trace(&self) -> Option<TraceOption> {
+        self.trace.then(|| TraceOption {
+            format: self.trace_format,
+            max_depth: self.trace_depth,
+        })
+    }
 }
 
 fn main() {
@@ -41,14 +120,53 @@ fn main() {
         Action::Run(RunAction {
             ref filepath,
             verbose,
+            ref eval,
         }) => {
-            run::run(filepath.clone(), verbose, false);
+            run::run(
+                filepath.clone(),
+                verbose,
+                false,
+                eval.to_limit(),
+                eval.strategy,
+                eval.to_trace(),
+                eval.color,
+            );
         }
         Action::Test(TestAction {
             ref filepath,
             verbose,
+            ref eval,
+        }) => {
+            run::run(
+                filepath.clone(),
+                verbose,
+                true,
+                eval.to_limit(),
+                eval.strategy,
+                eval.to_trace(),
+                eval.color,
+            );
+        }
+        Action::Repl(ReplAction { verbose, ref eval }) => {
+            repl::repl(
+                verbose,
+                eval.to_limit(),
+                eval.strategy,
+                eval.to_trace(),
+                eval.color,
+            );
+        }
+        Action::Debug(DebugAction {
+            ref filepath,
+            ref eval,
+        }) => {
+            debug::debug(filepath.clone(), eval.to_limit(), eval.strategy, eval.color);
+        }
+        Action::Expand(ExpandAction {
+            ref filepath,
+            ref eval,
         }) => {
-            run::run(filepath.clone(), verbose, true);
+            expand::expand(filepath.clone(), eval.to_limit(), eval.strategy, eval.color);
         }
     }
 }
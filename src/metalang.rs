@@ -1,3 +1,4 @@
+pub mod datum;
 pub mod eval;
 pub mod parser;
 pub mod printer;
@@ -0,0 +1,464 @@
+//! Syntax as data for macros, which take their args as data and return data read as syntax.
+//! Forms are lists of symbols and data, names are symbols and literals are themselves,
+//! e.g. `(if x 1 "a")` is a list of symbols `if`, `x`, 1 and "a".
+
+use super::parser::{is_reserved_name, name_to_term};
+use super::printer::simple::SimplePrinter as MetaPrinter;
+use super::syntax::{DefRecFun, MetaPattern, MetaTerm};
+use crate::common::fileinfo::{CompileError, FileInfo};
+use crate::corelang::printer::simple::SimplePrinter as CorePrinter;
+use crate::corelang::syntax::{rational_to_term, Term};
+
+/// `mt` as data, in the same shape as its printed form.
+pub fn term_to_datum(mt: &MetaTerm) -> Term {
+    let info = mt.file_info();
+    let symbol = |name: &str| Term::Symbol(info.clone(), name.into());
+    let form = |name: &str, mut datum_vec: Vec<Term>| {
+        datum_vec.insert(0, symbol(name));
+        list(info, datum_vec)
+    };
+    let names = |name_vec: &[String]| list(info, name_vec.iter().map(|n| symbol(n)).collect());
+    let terms = |ts: &[MetaTerm]| ts.iter().map(term_to_datum).collect::<Vec<_>>();
+    let bindings = |bs: &[(String, MetaTerm)]| {
+        let binding_vec = bs
+            .iter()
+            .map(|(name, t)| list(info, vec![symbol(name), term_to_datum(t)]))
+            .collect();
+        list(info, binding_vec)
+    };
+    let with_else = |mut clause_vec: Vec<Term>, else_term: &Option<Box<MetaTerm>>| {
+        if let Some(t) = else_term {
+            clause_vec.push(list(info, vec![symbol("else"), term_to_datum(t)]));
+        }
+        clause_vec
+    };
+    match mt {
+        MetaTerm::Apply(_, t, ts) => {
+            let mut datum_vec = vec![term_to_datum(t)];
+            datum_vec.extend(terms(ts));
+            list(info, datum_vec)
+        }
+        MetaTerm::Lambda(_, arg_name_vec, body) => {
+            form("lambda", vec![names(arg_name_vec), term_to_datum(body)])
+        }
+        MetaTerm::Quote(_, t) => form("quote", vec![term_to_datum(t)]),
+        MetaTerm::Quasiquote(_, t) => form("quasiquote", vec![term_to_datum(t)]),
+        MetaTerm::Unquote(_, t) => form("unquote", vec![term_to_datum(t)]),
+        MetaTerm::UnquoteSplicing(_, t) => form("unquote-splicing", vec![term_to_datum(t)]),
+        MetaTerm::Variable(_, name) => symbol(name),
+        MetaTerm::If(_, t1, t2, t3) => form("if", terms(&[*t1.clone(), *t2.clone(), *t3.clone()])),
+        MetaTerm::Cons(_, t1, t2) => form("cons", terms(&[*t1.clone(), *t2.clone()])),
+        MetaTerm::List(_, ts) => form("list", terms(ts)),
+        MetaTerm::Nil(_) => Term::Nil(info.clone()),
+        MetaTerm::Number(_, n) => Term::Number(info.clone(), n.clone()),
+        MetaTerm::Rational(_, r) => rational_to_term(info.clone(), r.clone()),
+        MetaTerm::String(_, s) => Term::String(info.clone(), s.clone()),
+        MetaTerm::Char(_, c) => Term::Char(info.clone(), *c),
+        MetaTerm::Bool(_, b) => Term::Bool(info.clone(), *b),
+        MetaTerm::Let(_, bs, body) => form("let", vec![bindings(bs), term_to_datum(body)]),
+        MetaTerm::LetStar(_, bs, body) => form("let*", vec![bindings(bs), term_to_datum(body)]),
+        MetaTerm::LetRec(_, fun_vec, body) => {
+            let fun_vec = fun_vec
+                .iter()
+                .map(|fun| {
+                    let datum_vec = vec![
+                        symbol(&fun.name),
+                        names(&fun.arg_name_vec),
+                        term_to_datum(&fun.term),
+                    ];
+                    list(info, datum_vec)
+                })
+                .collect();
+            form("letrec", vec![list(info, fun_vec), term_to_datum(body)])
+        }
+        MetaTerm::Cond(_, cs, else_term) => {
+            let clause_vec = cs
+                .iter()
+                .map(|(t1, t2)| list(info, vec![term_to_datum(t1), term_to_datum(t2)]))
+                .collect();
+            form("cond", with_else(clause_vec, else_term))
+        }
+        MetaTerm::Case(_, key, cs, else_term) => {
+            let mut clause_vec = vec![term_to_datum(key)];
+            clause_vec.extend(
+                cs.iter()
+                    .map(|(ds, t)| list(info, vec![list(info, terms(ds)), term_to_datum(t)])),
+            );
+            form("case", with_else(clause_vec, else_term))
+        }
+        MetaTerm::And(_, ts) => form("and", terms(ts)),
+        MetaTerm::Or(_, ts) => form("or", terms(ts)),
+        MetaTerm::Match(_, key, cs) => {
+            let mut datum_vec = vec![term_to_datum(key)];
+            datum_vec.extend(
+                cs.iter()
+                    .map(|(p, t)| list(info, vec![pattern_to_datum(p), term_to_datum(t)])),
+            );
+            form("match", datum_vec)
+        }
+        // operators and holes, which are names
+        _ => symbol(&MetaPrinter::default().print_term(mt)),
+    }
+}
+
+fn pattern_to_datum(pattern: &MetaPattern) -> Term {
+    let info = pattern.file_info();
+    let symbol = |name: &str| Term::Symbol(info.clone(), name.into());
+    match pattern {
+        MetaPattern::Wildcard(_) => symbol("_"),
+        MetaPattern::Bind(_, name) => symbol(name),
+        MetaPattern::Literal(_, literal) => term_to_datum(literal),
+        MetaPattern::Nil(_) => Term::Nil(info.clone()),
+        MetaPattern::Cons(_, p1, p2) => list(
+            info,
+            vec![symbol("cons"), pattern_to_datum(p1), pattern_to_datum(p2)],
+        ),
+        MetaPattern::List(_, ps) => {
+            let mut datum_vec = vec![symbol("list")];
+            datum_vec.extend(ps.iter().map(pattern_to_datum));
+            list(info, datum_vec)
+        }
+    }
+}
+
+fn list(info: &Option<FileInfo>, datum_vec: Vec<Term>) -> Term {
+    datum_vec
+        .into_iter()
+        .rev()
+        .fold(Term::Nil(info.clone()), |acc, d| {
+            Term::Cons(info.clone(), d.into(), acc.into())
+        })
+}
+
+/// Whether `datum` is made of lists, symbols and literals, so that it can be read as syntax.
+/// Quoted operators are taken as their names, e.g. `(quote +)` as `+`.
+pub fn is_datum(datum: &Term) -> bool {
+    match datum {
+        Term::Cons(..) => match list_elements(datum) {
+            Some(datum_vec) => datum_vec.into_iter().all(is_datum),
+            None => false,
+        },
+        Term::Quote(_, t) if !matches!(**t, Term::Cons(..)) => is_datum(t),
+        Term::Apply(..)
+        | Term::Lambda(..)
+        | Term::Quote(..)
+        | Term::Variable(..)
+        | Term::If(..) => false,
+        _ => true,
+    }
+}
+
+/// Elements of the proper list `datum`, or None if it is not a proper list.
+fn list_elements(datum: &Term) -> Option<Vec<&Term>> {
+    let mut datum_vec = Vec::new();
+    let mut rest = datum;
+    while let Term::Cons(_, d1, d2) = rest {
+        datum_vec.push(&**d1);
+        rest = d2;
+    }
+    match rest {
+        Term::Nil(_) => Some(datum_vec),
+        _ => None,
+    }
+}
+
+/// Syntax read from `datum`, which satisfies `is_datum`, in the same way as the parser reads its printed form.
+/// Everything read is given `info`, since the data made by macros has no source.
+pub fn datum_to_term(datum: &Term, info: &Option<FileInfo>) -> Result<MetaTerm, CompileError> {
+    DatumReader { info }.read_term(datum)
+}
+
+struct DatumReader<'a> {
+    info: &'a Option<FileInfo>,
+}
+
+impl DatumReader<'_> {
+    fn error<T>(&self, message: String) -> Result<T, CompileError> {
+        Err(CompileError {
+            info: self.info.clone(),
+            message: Some(message),
+        })
+    }
+
+    fn list<'b>(&self, datum: &'b Term, expect: &str) -> Result<Vec<&'b Term>, CompileError> {
+        match datum {
+            Term::Nil(_) => Ok(Vec::new()),
+            _ => match list_elements(datum) {
+                Some(datum_vec) => Ok(datum_vec),
+                None => self.error(format!("{}: expect a list", expect)),
+            },
+        }
+    }
+
+    fn read_term(&self, datum: &Term) -> Result<MetaTerm, CompileError> {
+        let info = self.info.clone();
+        Ok(match datum {
+            Term::Cons(..) => return self.read_form(&self.list(datum, "term")?),
+            Term::Quote(_, t) => return self.read_term(t),
+            Term::Number(_, n) => MetaTerm::Number(info, n.clone()),
+            Term::Rational(_, r) => MetaTerm::Rational(info, r.clone()),
+            Term::String(_, s) => MetaTerm::String(info, s.clone()),
+            Term::Char(_, c) => MetaTerm::Char(info, *c),
+            Term::Bool(_, b) => MetaTerm::Bool(info, *b),
+            Term::Nil(_) => MetaTerm::Nil(info),
+            Term::Symbol(_, name) => name_to_term(info, name.clone()),
+            // operators
+            t => name_to_term(info, CorePrinter::default().print(t)),
+        })
+    }
+
+    fn read_terms(&self, datum_vec: &[&Term]) -> Result<Vec<MetaTerm>, CompileError> {
+        datum_vec.iter().map(|d| self.read_term(d)).collect()
+    }
+
+    /// `(name rest..)` as a special form `name` or an application.
+    fn read_form(&self, datum_vec: &[&Term]) -> Result<MetaTerm, CompileError> {
+        let info = self.info.clone();
+        let (head, rest) = datum_vec.split_first().unwrap();
+        let name = match head {
+            Term::Symbol(_, name) => name.as_str(),
+            _ => "",
+        };
+        let fixed = |form: &str, len: usize, message: &str| {
+            if rest.len() == len {
+                self.read_terms(rest)
+            } else {
+                self.error(format!("{} only accepts {}", form, message))
+            }
+        };
+        let body = |form: &str| match rest {
+            [_, body] => self.read_term(body),
+            _ => self.error(format!("{} only accepts bindings and 1 term", form)),
+        };
+        Ok(match name {
+            "lambda" => match rest {
+                [args, body] => MetaTerm::Lambda(
+                    info,
+                    self.read_names(args, "lambda args")?,
+                    self.read_term(body)?.into(),
+                ),
+                _ => return self.error("lambda only accepts args and 1 term".into()),
+            },
+            "cons" => {
+                let mut ts = fixed("cons", 2, "2 terms")?.into_iter();
+                MetaTerm::Cons(info, ts.next().unwrap().into(), ts.next().unwrap().into())
+            }
+            "list" => MetaTerm::List(info, self.read_terms(rest)?),
+            "quote" | "quasiquote" | "unquote" | "unquote-splicing" => {
+                let wrap = match name {
+                    "quote" => MetaTerm::Quote,
+                    "quasiquote" => MetaTerm::Quasiquote,
+                    "unquote" => MetaTerm::Unquote,
+                    _ => MetaTerm::UnquoteSplicing,
+                };
+                let t = fixed(name, 1, "1 term")?.pop().unwrap();
+                wrap(info, t.into())
+            }
+            "if" => {
+                let mut ts = fixed("if", 3, "3 terms")?.into_iter();
+                MetaTerm::If(
+                    info,
+                    ts.next().unwrap().into(),
+                    ts.next().unwrap().into(),
+                    ts.next().unwrap().into(),
+                )
+            }
+            "let" | "let*" => {
+                let binding_vec = match rest.first() {
+                    Some(bindings) => self.read_bindings(bindings, name)?,
+                    None => Vec::new(),
+                };
+                let body = body(name)?.into();
+                match name {
+                    "let" => MetaTerm::Let(info, binding_vec, body),
+                    _ => MetaTerm::LetStar(info, binding_vec, body),
+                }
+            }
+            "letrec" => {
+                let fun_vec = match rest.first() {
+                    Some(bindings) => self
+                        .list(bindings, "letrec bindings")?
+                        .into_iter()
+                        .map(|fun| self.read_fun(fun))
+                        .collect::<Result<_, _>>()?,
+                    None => Vec::new(),
+                };
+                MetaTerm::LetRec(info, fun_vec, body("letrec")?.into())
+            }
+            "cond" => {
+                let mut clause_vec = Vec::new();
+                let else_term = self.read_clauses(rest, "cond", |clause| {
+                    let [condition, value] = clause else {
+                        return self.error("expect cond clause of a condition and a value".into());
+                    };
+                    clause_vec.push((self.read_term(condition)?, self.read_term(value)?));
+                    Ok(())
+                })?;
+                MetaTerm::Cond(info, clause_vec, else_term)
+            }
+            "case" => {
+                let Some((key, rest)) = rest.split_first() else {
+                    return self.error("case expects a key".into());
+                };
+                let mut clause_vec = Vec::new();
+                let else_term = self.read_clauses(rest, "case", |clause| {
+                    let [datums, value] = clause else {
+                        return self.error("expect case clause of datums and a value".into());
+                    };
+                    let datum_vec = self
+                        .list(datums, "case datums")?
+                        .into_iter()
+                        .map(|d| self.read_case_datum(d))
+                        .collect::<Result<_, _>>()?;
+                    clause_vec.push((datum_vec, self.read_term(value)?));
+                    Ok(())
+                })?;
+                MetaTerm::Case(info, self.read_term(key)?.into(), clause_vec, else_term)
+            }
+            "and" => MetaTerm::And(info, self.read_terms(rest)?),
+            "or" => MetaTerm::Or(info, self.read_terms(rest)?),
+            "match" => {
+                let Some((key, rest)) = rest.split_first() else {
+                    return self.error("match expects a key".into());
+                };
+                let clause_vec = rest
+                    .iter()
+                    .map(|clause| match self.list(clause, "match clause")?[..] {
+                        [pattern, value] => {
+                            Ok((self.read_pattern(pattern)?, self.read_term(value)?))
+                        }
+                        _ => self.error("expect match clause of a pattern and a value".into()),
+                    })
+                    .collect::<Result<_, _>>()?;
+                MetaTerm::Match(info, self.read_term(key)?.into(), clause_vec)
+            }
+            _ => MetaTerm::Apply(info, self.read_term(head)?.into(), self.read_terms(rest)?),
+        })
+    }
+
+    fn read_name(&self, datum: &Term, expect: &str) -> Result<String, CompileError> {
+        match datum {
+            Term::Symbol(_, name) if is_reserved_name(name) => {
+                self.error(format!("reserved name \"{}\" cannot be bound", name))
+            }
+            Term::Symbol(_, name) => Ok(name.clone()),
+            _ => self.error(format!("{}: expect a name", expect)),
+        }
+    }
+
+    fn read_names(&self, datum: &Term, expect: &str) -> Result<Vec<String>, CompileError> {
+        self.list(datum, expect)?
+            .into_iter()
+            .map(|d| self.read_name(d, expect))
+            .collect()
+    }
+
+    /// Bindings of let, e.g. `((x 1) (y 2))`.
+    fn read_bindings(
+        &self,
+        datum: &Term,
+        form: &str,
+    ) -> Result<Vec<(String, MetaTerm)>, CompileError> {
+        let expect = format!("{} binding", form);
+        self.list(datum, &format!("{} bindings", form))?
+            .into_iter()
+            .map(|binding| match self.list(binding, &expect)?[..] {
+                [name, value] => Ok((self.read_name(name, &expect)?, self.read_term(value)?)),
+                _ => self.error(format!("expect {} of a name and a value", expect)),
+            })
+            .collect()
+    }
+
+    /// Function of letrec, e.g. `(f (x) (f x))`.
+    fn read_fun(&self, datum: &Term) -> Result<DefRecFun, CompileError> {
+        match self.list(datum, "letrec binding")?[..] {
+            [name, args, term] => Ok(DefRecFun {
+                info: self.info.clone(),
+                name_info: self.info.clone(),
+                name: self.read_name(name, "letrec binding")?,
+                arg_name_vec: self.read_names(args, "letrec binding args")?,
+                term: self.read_term(term)?,
+            }),
+            _ => self.error("expect letrec binding of a name, args and a body".into()),
+        }
+    }
+
+    /// Clauses of cond or case, reading each clause other than `else` by `f`.
+    /// Returns the value of the `else` clause, which should be the last.
+    fn read_clauses<F>(
+        &self,
+        clause_vec: &[&Term],
+        form: &str,
+        mut f: F,
+    ) -> Result<Option<Box<MetaTerm>>, CompileError>
+    where
+        F: FnMut(&[&Term]) -> Result<(), CompileError>,
+    {
+        let mut else_term = None;
+        for clause in clause_vec {
+            if else_term.is_some() {
+                return self.error(format!("else clause of {} should be the last", form));
+            }
+            let clause = self.list(clause, &format!("{} clause", form))?;
+            match clause[..] {
+                [Term::Symbol(_, name), value] if name == "else" => {
+                    else_term = Some(self.read_term(value)?.into());
+                }
+                _ => f(&clause)?,
+            }
+        }
+        Ok(else_term)
+    }
+
+    /// Literal or symbol to be compared with the key of case.
+    fn read_case_datum(&self, datum: &Term) -> Result<MetaTerm, CompileError> {
+        match self.read_term(datum)? {
+            datum @ (MetaTerm::Number(..)
+            | MetaTerm::Rational(..)
+            | MetaTerm::Bool(..)
+            | MetaTerm::String(..)
+            | MetaTerm::Char(..)
+            | MetaTerm::Nil(..)
+            | MetaTerm::Variable(..)) => Ok(datum),
+            _ => self.error("case datum should be a literal or symbol".into()),
+        }
+    }
+
+    fn read_pattern(&self, datum: &Term) -> Result<MetaPattern, CompileError> {
+        let info = self.info.clone();
+        let error = || {
+            self.error("pattern should be cons, list, nil, a literal, _ or a variable name".into())
+        };
+        if let Term::Cons(..) = datum {
+            let datum_vec = self.list(datum, "pattern")?;
+            return match datum_vec[..] {
+                [Term::Symbol(_, name), p1, p2] if name == "cons" => Ok(MetaPattern::Cons(
+                    info,
+                    self.read_pattern(p1)?.into(),
+                    self.read_pattern(p2)?.into(),
+                )),
+                [Term::Symbol(_, name), ref ps @ ..] if name == "list" => Ok(MetaPattern::List(
+                    info,
+                    ps.iter()
+                        .map(|p| self.read_pattern(p))
+                        .collect::<Result<_, _>>()?,
+                )),
+                _ => error(),
+            };
+        }
+        match self.read_term(datum)? {
+            MetaTerm::Variable(_, name) if name == "_" => Ok(MetaPattern::Wildcard(info)),
+            MetaTerm::Variable(_, name) if is_reserved_name(&name) => {
+                self.error(format!("reserved name \"{}\" cannot be bound", name))
+            }
+            MetaTerm::Variable(_, name) => Ok(MetaPattern::Bind(info, name)),
+            MetaTerm::Nil(_) => Ok(MetaPattern::Nil(info)),
+            literal @ (MetaTerm::Number(..)
+            | MetaTerm::Rational(..)
+            | MetaTerm::Bool(..)
+            | MetaTerm::String(..)
+            | MetaTerm::Char(..)) => Ok(MetaPattern::Literal(info, literal)),
+            _ => error(),
+        }
+    }
+}
@@ -1,21 +1,41 @@
-use super::syntax::{transform_to_core, MetaEnv, MetaExport, MetaStatement, MetaTerm};
-use crate::common::fileinfo::CompileError;
+use super::datum::{datum_to_term, is_datum, term_to_datum};
+use super::syntax::{
+    transform_rec_functions, transform_to_core, DefRecFun, MetaBinding, MetaEnv, MetaExport,
+    MetaStatement, MetaTerm,
+};
+use crate::common::fileinfo::{CompileError, FileInfo};
 use crate::common::resolver::ContentResolver;
-use crate::corelang::eval::eval as core_eval;
+use crate::corelang::eval::{
+    eval_traced, eval_with, EvalError, EvalLimit, EvalResult, EvalStrategy,
+};
+use crate::corelang::printer::simple::SimplePrinter as CorePrinter;
 use crate::corelang::syntax::Term as CoreTerm;
+use crate::corelang::trace::Tracer;
 use crate::metalang::parser::MetaParser;
 use crate::resolver::lib::LibResolver;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::rc::Rc;
 
+/// Max number of macro calls expanded in a row, each in the syntax made by the previous one,
+/// to stop macros expanding forever.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
 pub struct MetaEvaluator {
     pub env: MetaEnv,
     pub exported: MetaEnv,
     pub filepath: Option<PathBuf>,
     pub do_assert: bool,
+    /// budget for evaluating each term and assertion
+    pub limit: EvalLimit,
+    pub strategy: EvalStrategy,
+    /// receives reduction steps of terms and assertions evaluated in this file, not in imported ones
+    pub tracer: Option<Box<dyn Tracer>>,
     pub resolver: Rc<RefCell<Box<dyn ContentResolver>>>,
+    /// number of macro calls expanded so far, making the names by gensym unique.
+    /// Shared with the evaluators of imported files.
+    expansion_count: Rc<Cell<usize>>,
 }
 
 impl Default for MetaEvaluator {
@@ -25,128 +45,279 @@ impl Default for MetaEvaluator {
             exported: MetaEnv::default(),
             filepath: None,
             do_assert: false,
+            limit: EvalLimit::default(),
+            strategy: EvalStrategy::default(),
+            tracer: None,
             resolver: Rc::new(RefCell::new(Box::new(LibResolver::default()))),
+            expansion_count: Rc::new(Cell::new(0)),
         }
     }
 }
 
-fn substitution_rec(mt: &MetaTerm, name_vec: &Vec<&str>, name_set: &HashSet<&str>) -> MetaTerm {
-    if let MetaTerm::Apply(info, t, ts) = mt {
-        if let MetaTerm::Variable(_, v) = &**t {
-            if name_set.contains(v.as_str()) {
-                let mut new_arg_vec: Vec<MetaTerm> = name_vec
-                    .iter()
-                    .map(|name| MetaTerm::Variable(None, name.to_string()))
-                    .collect();
-                new_arg_vec.append(&mut ts.clone());
-                return MetaTerm::Apply(info.clone(), t.clone(), new_arg_vec);
-            }
-        }
+impl MetaEvaluator {
+    /// Discards all definitions, keeping the settings.
+    /// Names made by gensym stay unique to each expansion after this.
+    pub fn reset(&mut self) {
+        self.env = MetaEnv::default();
+        self.exported = MetaEnv::default();
     }
-    mt.map_subterm(|st| substitution_rec(&st, name_vec, name_set))
-}
 
-impl MetaEvaluator {
     pub fn set_resolver(&mut self, resolver: Box<dyn ContentResolver>) {
         self.resolver = Rc::new(RefCell::new(resolver));
     }
 
-    fn import(&mut self, to_resolve: String) -> Result<MetaEnv, CompileError> {
+    fn import(&mut self, to_resolve: String) -> Result<MetaEnv, EvalError> {
         let c = match (*self.resolver.borrow_mut()).resolve(&self.filepath, &to_resolve) {
             Ok(c) => c,
-            Err(e) => Err(CompileError {
+            Err(e) => Err(EvalError::Resolve(CompileError {
                 info: None,
                 message: Some(format!("resolve error: {}", e)),
-            })?,
+            }))?,
         };
         let mut p = MetaParser::new(to_resolve.clone(), c.content.to_string().chars().collect());
-        let stmt_vec = p.parse_stmt_vec()?;
+        let stmt_vec = p.parse_stmt_vec().map_err(EvalError::Parse)?;
         let mut evaluator = MetaEvaluator {
             env: Default::default(),
             exported: Default::default(),
             filepath: c.filepath.clone(),
             do_assert: self.do_assert,
+            limit: self.limit.clone(),
+            strategy: self.strategy,
+            tracer: None,
             resolver: self.resolver.clone(),
+            expansion_count: self.expansion_count.clone(),
         };
         evaluator.eval_vec(&stmt_vec)?;
         Ok(evaluator.exported)
     }
 
+    /// Core term of `mt` after expanding macros.
+    pub fn transform(&self, mt: &MetaTerm) -> Result<CoreTerm, EvalError> {
+        transform_to_core(&self.env, &self.expand(mt)?).map_err(EvalError::Transform)
+    }
+
+    /// `mt` with all macro calls replaced by the syntax the macros made.
+    pub fn expand(&self, mt: &MetaTerm) -> Result<MetaTerm, EvalError> {
+        self.expand_internal(mt, &HashSet::new())
+    }
+
+    /// `stmt` with all macro calls replaced by the syntax the macros made.
+    pub fn expand_stmt(&self, stmt: &MetaStatement) -> Result<MetaStatement, EvalError> {
+        Ok(match stmt {
+            MetaStatement::Def(info, name, term) => {
+                MetaStatement::Def(info.clone(), name.clone(), self.expand(term)?)
+            }
+            MetaStatement::DefRec(info, fun_vec) => {
+                let bound = fun_vec.iter().map(|fun| fun.name.clone()).collect();
+                MetaStatement::DefRec(
+                    info.clone(),
+                    fun_vec
+                        .iter()
+                        .map(|fun| {
+                            Ok(DefRecFun {
+                                term: self.expand_internal(
+                                    &fun.term,
+                                    &with_names(&bound, &fun.arg_name_vec),
+                                )?,
+                                ..fun.clone()
+                            })
+                        })
+                        .collect::<Result<_, EvalError>>()?,
+                )
+            }
+            MetaStatement::Term(info, term) => {
+                MetaStatement::Term(info.clone(), self.expand(term)?)
+            }
+            MetaStatement::Assert(info, term) => {
+                MetaStatement::Assert(info.clone(), self.expand(term)?)
+            }
+            MetaStatement::DefMacro(info, name, arg_name_vec, body) => MetaStatement::DefMacro(
+                info.clone(),
+                name.clone(),
+                arg_name_vec.clone(),
+                self.expand_internal(body, &macro_arg_names(arg_name_vec).into_iter().collect())?,
+            ),
+            MetaStatement::Import(..) | MetaStatement::Export(..) => stmt.clone(),
+        })
+    }
+
+    /// bound: names bound locally, which shadow macros
+    fn expand_internal(
+        &self,
+        mt: &MetaTerm,
+        bound: &HashSet<String>,
+    ) -> Result<MetaTerm, EvalError> {
+        // walked with a stack of tasks rather than recursively, as the syntax made by macros can nest deeply
+        let mut task_vec = vec![ExpandTask::Visit(
+            mt.clone(),
+            Rc::new(bound.clone()),
+            0,
+            false,
+        )];
+        let mut expanded_vec = Vec::new();
+        while let Some(task) = task_vec.pop() {
+            let (mut mt, bound, mut depth, template) = match task {
+                ExpandTask::Visit(mt, bound, depth, template) => (mt, bound, depth, template),
+                ExpandTask::Build(mt, n) => {
+                    let subterm_vec = expanded_vec.split_off(expanded_vec.len() - n);
+                    expanded_vec.push(mt.with_subterms(subterm_vec));
+                    continue;
+                }
+            };
+            // expanded in a loop while the syntax made is a macro call
+            while let (false, Some((info, name, macro_term, arg_vec))) =
+                (template, self.macro_call(&mt, &bound))
+            {
+                let expanded = self.expand_call(info, name, macro_term, arg_vec, depth)?;
+                (mt, depth) = (expanded, depth + 1);
+            }
+            let Some(context_vec) = subterm_contexts(&mt, &bound, template) else {
+                expanded_vec.push(mt);
+                continue;
+            };
+            let subterm_vec: Vec<MetaTerm> = mt.subterms().into_iter().cloned().collect();
+            task_vec.push(ExpandTask::Build(mt, subterm_vec.len()));
+            for (st, (bound, template)) in subterm_vec.into_iter().zip(context_vec).rev() {
+                task_vec.push(ExpandTask::Visit(st, bound, depth, template));
+            }
+        }
+        Ok(expanded_vec.pop().unwrap())
+    }
+
+    /// (info, macro name, macro, args) if `mt` is a call of a macro not shadowed by `bound`.
+    fn macro_call<'a>(
+        &'a self,
+        mt: &'a MetaTerm,
+        bound: &HashSet<String>,
+    ) -> Option<(&'a Option<FileInfo>, &'a str, &'a CoreTerm, &'a [MetaTerm])> {
+        if let MetaTerm::Apply(info, t, ts) = mt {
+            if let MetaTerm::Variable(_, name) = &**t {
+                if let (false, Some(MetaBinding::Macro(macro_term))) =
+                    (bound.contains(name), self.env.get(name))
+                {
+                    return Some((info, name, macro_term, ts));
+                }
+            }
+        }
+        None
+    }
+
+    /// Syntax made by the macro `name` for the call `(name arg_vec..)`.
+    /// The args are passed as data, and the returned data is read as syntax.
+    fn expand_call(
+        &self,
+        info: &Option<FileInfo>,
+        name: &str,
+        macro_term: &CoreTerm,
+        arg_vec: &[MetaTerm],
+        depth: usize,
+    ) -> Result<MetaTerm, EvalError> {
+        let error = |message: String| {
+            EvalError::Transform(CompileError {
+                info: info.clone(),
+                message: Some(message),
+            })
+        };
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(error(format!(
+                "Macro \"{}\" is expanded more than {} times in a row.",
+                name, MAX_EXPANSION_DEPTH
+            )));
+        }
+        let arg_num = match macro_term {
+            CoreTerm::Lambda(_, n, _) => n - 1,
+            _ => 0,
+        };
+        if arg_num != arg_vec.len() {
+            return Err(error(format!(
+                "Macro \"{}\" needs {} args but found {}.",
+                name,
+                arg_num,
+                arg_vec.len()
+            )));
+        }
+        let count = self.expansion_count.get() + 1;
+        self.expansion_count.set(count);
+
+        // (macro gensym (quote arg1) .. (quote argn))
+        let mut operand_vec = vec![CoreTerm::Gensym(info.clone(), count)];
+        operand_vec.extend(arg_vec.iter().map(term_to_datum));
+        let call = CoreTerm::Apply(info.clone(), macro_term.clone().into(), operand_vec);
+        let datum = self.eval_datum(&call)?;
+        if !is_datum(&datum) {
+            return Err(error(format!(
+                "Macro \"{}\" should return syntax made of lists, symbols and literals: found {}",
+                name,
+                CorePrinter::default().print(&datum)
+            )));
+        }
+        datum_to_term(&datum, info).map_err(EvalError::Parse)
+    }
+
+    /// Value of `term` with the components of conses also evaluated, as syntax made by macros is read as a whole.
+    fn eval_datum(&self, term: &CoreTerm) -> EvalResult {
+        Ok(match eval_with(term, &self.limit, self.strategy)? {
+            CoreTerm::Cons(info, t1, t2) => CoreTerm::Cons(
+                info,
+                self.eval_datum(&t1)?.into(),
+                self.eval_datum(&t2)?.into(),
+            ),
+            value => value,
+        })
+    }
+
+    fn core_eval(&mut self, term: &CoreTerm) -> EvalResult {
+        match self.tracer.as_mut() {
+            Some(tracer) => eval_traced(term, &self.limit, self.strategy, tracer.as_mut()),
+            None => eval_with(term, &self.limit, self.strategy),
+        }
+    }
+
     /// (new env, evaluated value)
-    pub fn eval(&mut self, stmt: &MetaStatement) -> Result<Option<CoreTerm>, CompileError> {
+    pub fn eval(&mut self, stmt: &MetaStatement) -> Result<Option<CoreTerm>, EvalError> {
         match stmt {
             MetaStatement::Def(_, name, term) => {
-                self.env
-                    .insert(name.clone(), transform_to_core(&self.env, term)?);
+                let value = MetaBinding::Value(self.transform(term)?);
+                self.env.insert(name.clone(), value);
                 Ok(None)
             }
             MetaStatement::DefRec(info, fun_vec) => {
-                let name_vec: Vec<&str> = fun_vec.iter().map(|fun| fun.name.as_str()).collect();
-                let name_set: HashSet<&str> = fun_vec.iter().map(|fun| fun.name.as_str()).collect();
-                let real_lambda_in_vec: Vec<_> = fun_vec
-                    .clone()
-                    .into_iter()
-                    .map(|fun| {
-                        let real_body = substitution_rec(&fun.term, &name_vec, &name_set);
-                        let real_arg_name_vec = {
-                            let mut new_vec: Vec<String> =
-                                name_vec.clone().iter().map(|e| (*e).into()).collect();
-                            new_vec.append(&mut fun.arg_name_vec.clone());
-                            new_vec
-                        };
-                        let real_lambda_in = MetaTerm::Lambda(
-                            info.clone(),
-                            real_arg_name_vec.clone(),
-                            real_body.into(),
-                        );
-                        (fun, real_lambda_in)
-                    })
-                    .collect();
-
-                for (fun, real_lambda_in) in real_lambda_in_vec.iter() {
-                    // (lambda (<orig_arg>) (real_lambda <real_lambda_vec> <orig_arg>))
-
-                    // <real_lambda_vec> <orig_arg>
-                    let real_operand = {
-                        let mut real_operand: Vec<_> = real_lambda_in_vec
-                            .iter()
-                            .map(|(_, real_lambda_in)| real_lambda_in.clone())
-                            .collect();
-                        real_operand.append(
-                            &mut fun
-                                .arg_name_vec
-                                .clone()
-                                .into_iter()
-                                .map(|v| MetaTerm::Variable(info.clone(), v))
-                                .collect(),
-                        );
-                        real_operand
-                    };
-
-                    let real_lambda = MetaTerm::Lambda(
-                        info.clone(),
-                        fun.arg_name_vec.clone(),
-                        MetaTerm::Apply(info.clone(), real_lambda_in.clone().into(), real_operand)
-                            .into(),
-                    );
-                    self.env.insert(
-                        fun.name.clone(),
-                        transform_to_core(&self.env, &real_lambda)?,
-                    );
+                for (fun, real_lambda) in fun_vec.iter().zip(transform_rec_functions(info, fun_vec))
+                {
+                    let value = MetaBinding::Value(self.transform(&real_lambda)?);
+                    self.env.insert(fun.name.clone(), value);
                 }
                 Ok(None)
             }
-            MetaStatement::Term(_, mt) => Ok(Some(core_eval(&transform_to_core(&self.env, mt)?)?)),
+            // (lambda (gensym <args>) body), called at transform time
+            MetaStatement::DefMacro(info, name, arg_name_vec, body) => {
+                let lambda = MetaTerm::Lambda(
+                    info.clone(),
+                    macro_arg_names(arg_name_vec),
+                    body.clone().into(),
+                );
+                let value = MetaBinding::Macro(self.transform(&lambda)?);
+                self.env.insert(name.clone(), value);
+                Ok(None)
+            }
+            MetaStatement::Term(_, mt) => {
+                let term = self.transform(mt)?;
+                Ok(Some(self.core_eval(&term)?))
+            }
             MetaStatement::Assert(info, mt) => {
                 if self.do_assert {
-                    let v = core_eval(&transform_to_core(&self.env, mt)?)?;
+                    let term = self.transform(mt)?;
+                    let v = self.core_eval(&term)?;
                     if let CoreTerm::Bool(_, true) = v {
                         Ok(None)
                     } else {
-                        Err(CompileError {
-                            info: info.clone(),
-                            message: Some("assertion failed".into()),
-                        })
+                        Err(EvalError::Assertion(
+                            CompileError {
+                                info: info.clone(),
+                                message: Some("assertion failed".into()),
+                            },
+                            Some(Box::new(term)),
+                        ))
                     }
                 } else {
                     Ok(None)
@@ -168,13 +339,13 @@ impl MetaEvaluator {
                                 self.exported.insert(v.clone(), val.clone());
                             }
                             None => {
-                                return Err(CompileError {
+                                return Err(EvalError::Transform(CompileError {
                                     info: info.clone(),
                                     message: Some(format!(
                                         "variable \"{}\" cannot be exported. not found",
                                         v
                                     )),
-                                });
+                                }));
                             }
                         },
                         MetaExport::Path(path) => {
@@ -189,10 +360,7 @@ impl MetaEvaluator {
         }
     }
 
-    pub fn eval_vec(
-        &mut self,
-        stmt_vec: &Vec<MetaStatement>,
-    ) -> Result<Vec<CoreTerm>, CompileError> {
+    pub fn eval_vec(&mut self, stmt_vec: &Vec<MetaStatement>) -> Result<Vec<CoreTerm>, EvalError> {
         let mut value_vec = Vec::<CoreTerm>::new();
         for stmt in stmt_vec {
             let value = self.eval(stmt)?;
@@ -203,3 +371,85 @@ impl MetaEvaluator {
         Ok(value_vec)
     }
 }
+
+/// `bound` and `name_vec`.
+fn with_names(bound: &HashSet<String>, name_vec: &[String]) -> HashSet<String> {
+    let mut bound = bound.clone();
+    bound.extend(name_vec.iter().cloned());
+    bound
+}
+
+/// Args of the lambda of a macro, with `gensym` first, which makes names unique to each expansion
+/// by appending `'` and the expansion number, e.g. `(gensym (quote tmp))` to `tmp'3`.
+/// Such names cannot be written in source, as `'` is not allowed in names.
+fn macro_arg_names(arg_name_vec: &[String]) -> Vec<String> {
+    let mut name_vec = vec!["gensym".to_string()];
+    name_vec.extend(arg_name_vec.iter().cloned());
+    name_vec
+}
+
+enum ExpandTask {
+    /// (term, names bound, number of macro calls expanded in a row to make the term, in a quasiquote template)
+    Visit(MetaTerm, Rc<HashSet<String>>, usize, bool),
+    /// replaces the subterms of the term with the last n expanded terms
+    Build(MetaTerm, usize),
+}
+
+/// Names bound and whether in a quasiquote template for each of the subterms of `mt`,
+/// or None if `mt` is data and left as is.
+/// Only the unquoted parts in a template are expanded, as they are evaluated outside it.
+fn subterm_contexts(
+    mt: &MetaTerm,
+    bound: &Rc<HashSet<String>>,
+    template: bool,
+) -> Option<Vec<(Rc<HashSet<String>>, bool)>> {
+    let same = |template| Some(vec![(bound.clone(), template); mt.subterms().len()]);
+    let with = |bound, name_vec: &[String]| (Rc::new(with_names(bound, name_vec)), false);
+    if template {
+        return same(!matches!(
+            mt,
+            MetaTerm::Unquote(..) | MetaTerm::UnquoteSplicing(..)
+        ));
+    }
+    match mt {
+        // syntax in quote is data
+        MetaTerm::Quote(..) => None,
+        MetaTerm::Quasiquote(..) => same(true),
+        MetaTerm::Lambda(_, arg_name_vec, _) => Some(vec![with(bound, arg_name_vec)]),
+        MetaTerm::Let(_, binding_vec, _) => {
+            let name_vec: Vec<String> = binding_vec.iter().map(|(name, _)| name.clone()).collect();
+            let mut context_vec = vec![(bound.clone(), false); binding_vec.len()];
+            context_vec.push(with(bound, &name_vec));
+            Some(context_vec)
+        }
+        MetaTerm::LetStar(_, binding_vec, _) => {
+            let mut sub_bound = bound.clone();
+            let mut context_vec = Vec::new();
+            for (name, _) in binding_vec {
+                context_vec.push((sub_bound.clone(), false));
+                sub_bound = Rc::new(with_names(&sub_bound, std::slice::from_ref(name)));
+            }
+            context_vec.push((sub_bound, false));
+            Some(context_vec)
+        }
+        MetaTerm::LetRec(_, fun_vec, _) => {
+            let name_vec: Vec<String> = fun_vec.iter().map(|fun| fun.name.clone()).collect();
+            let (sub_bound, _) = with(bound, &name_vec);
+            let mut context_vec: Vec<_> = fun_vec
+                .iter()
+                .map(|fun| with(&sub_bound, &fun.arg_name_vec))
+                .collect();
+            context_vec.push((sub_bound, false));
+            Some(context_vec)
+        }
+        MetaTerm::Match(_, _, clause_vec) => {
+            let mut context_vec = vec![(bound.clone(), false)];
+            context_vec.extend(clause_vec.iter().map(|(pattern, _)| {
+                let name_vec: Vec<String> = pattern.binding_names().into_iter().cloned().collect();
+                with(bound, &name_vec)
+            }));
+            Some(context_vec)
+        }
+        _ => same(false),
+    }
+}
@@ -1,31 +1,152 @@
 use crate::common::fileinfo::{CompileError, FileInfo, Location, Range};
-use crate::metalang::syntax::{DefRecFun, MetaExport, MetaStatement, MetaTerm};
-use num_bigint::BigUint;
+use crate::metalang::syntax::{
+    Comment, CommentKind, DefRecFun, MetaExport, MetaPattern, MetaStatement, MetaTerm,
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Zero;
 
 pub struct MetaParser {
     filepath: String,
     p: usize,
     line: usize,
     col: usize,
+    /// location of the last char read
+    last: Location,
     chars: Vec<char>,
+    /// comments skipped so far
+    comment_vec: Vec<Comment>,
+    /// errors found in comments, e.g. unterminated block comment
+    comment_error_vec: Vec<CompileError>,
 }
 
-const RESERVED_NAMES: [&str; 15] = [
-    "if", "+", "-", "eval", "quote", "lambda", "import", "exoprt", "assert", "print", "println",
-    "for", "loop", "do", "while",
+/// Position of the parser saved by `store` to backtrack by `restore`.
+struct Store {
+    p: usize,
+    line: usize,
+    col: usize,
+    last: Location,
+    comment_len: usize,
+    comment_error_len: usize,
+}
+
+const RESERVED_NAMES: [&str; 26] = [
+    "if",
+    "+",
+    "-",
+    "eval",
+    "quote",
+    "lambda",
+    "import",
+    "exoprt",
+    "assert",
+    "print",
+    "println",
+    "for",
+    "loop",
+    "do",
+    "while",
+    "let",
+    "let*",
+    "letrec",
+    "cond",
+    "case",
+    "match",
+    "and",
+    "or",
+    "quasiquote",
+    "unquote",
+    "unquote-splicing",
 ];
 
-fn is_reserved_name(s: &str) -> bool {
+pub(crate) fn is_reserved_name(s: &str) -> bool {
     RESERVED_NAMES.into_iter().find(|e| s == *e) != None
 }
 
-fn is_identifier_head(c: char) -> bool {
-    is_identifier(c) && !c.is_digit(10)
-}
 fn is_identifier(c: char) -> bool {
-    !c.is_control() && !c.is_whitespace() && c != '(' && c != ')' && c != '"' && c != '\''
+    !c.is_control()
+        && !c.is_whitespace()
+        && c != '('
+        && c != ')'
+        && c != '"'
+        && c != '\''
+        && c != '`'
+        && c != ','
+        && c != ';'
+}
+
+/// Whether the whole `s` is read as a name, e.g. not `a b`, `1` or `a'1`.
+pub(crate) fn is_name(s: &str) -> bool {
+    let mut p = MetaParser::new(String::new(), s.chars().collect());
+    p.parse_identifier().is_some() && p.peek().is_none()
+}
+
+/// Term of the name `id`: literals and operators written by names, or a variable.
+pub(crate) fn name_to_term(info: Option<FileInfo>, id: String) -> MetaTerm {
+    match id.as_str() {
+        "nil" => MetaTerm::Nil(info),
+        "true" => MetaTerm::Bool(info, true),
+        "false" => MetaTerm::Bool(info, false),
+        "car" => MetaTerm::Car(info),
+        "cdr" => MetaTerm::Cdr(info),
+        "eval" => MetaTerm::Eval(info),
+        "eq" => MetaTerm::Eq(info),
+        "beta-eq" => MetaTerm::BetaEq(info),
+        "+" => MetaTerm::Add(info),
+        "-" => MetaTerm::Sub(info),
+        "*" => MetaTerm::Mul(info),
+        "/" => MetaTerm::Div(info),
+        "%" => MetaTerm::Rem(info),
+        "monus" => MetaTerm::Monus(info),
+        "neg" => MetaTerm::Neg(info),
+        "abs" => MetaTerm::Abs(info),
+        "numerator" => MetaTerm::Numerator(info),
+        "denominator" => MetaTerm::Denominator(info),
+        "<" => MetaTerm::Lt(info),
+        "<=" => MetaTerm::Le(info),
+        ">" => MetaTerm::Gt(info),
+        ">=" => MetaTerm::Ge(info),
+        "min" => MetaTerm::Min(info),
+        "max" => MetaTerm::Max(info),
+        "pow" => MetaTerm::Pow(info),
+        "modpow" => MetaTerm::ModPow(info),
+        "gcd" => MetaTerm::Gcd(info),
+        "isqrt" => MetaTerm::Isqrt(info),
+        "shl" => MetaTerm::Shl(info),
+        "shr" => MetaTerm::Shr(info),
+        "bit-and" => MetaTerm::BitAnd(info),
+        "bit-or" => MetaTerm::BitOr(info),
+        "bit-xor" => MetaTerm::BitXor(info),
+        "string-length" => MetaTerm::StringLength(info),
+        "string-append" => MetaTerm::StringAppend(info),
+        "substring" => MetaTerm::Substring(info),
+        "char->integer" => MetaTerm::CharToInteger(info),
+        "integer->char" => MetaTerm::IntegerToChar(info),
+        "char->string" => MetaTerm::CharToString(info),
+        "string->list" => MetaTerm::StringToList(info),
+        "list->string" => MetaTerm::ListToString(info),
+        "symbol?" => MetaTerm::IsSymbol(info),
+        "symbol->string" => MetaTerm::SymbolToString(info),
+        "string->symbol" => MetaTerm::StringToSymbol(info),
+        "number?" => MetaTerm::IsNumber(info),
+        "bool?" => MetaTerm::IsBool(info),
+        "string?" => MetaTerm::IsString(info),
+        "char?" => MetaTerm::IsChar(info),
+        "cons?" => MetaTerm::IsCons(info),
+        "nil?" => MetaTerm::IsNil(info),
+        "lambda?" => MetaTerm::IsLambda(info),
+        "quote?" => MetaTerm::IsQuote(info),
+        "type-of" => MetaTerm::TypeOf(info),
+        _ => MetaTerm::Variable(info, id),
+    }
 }
 
+/// `'x`, `` `x ``, `,x` and `,@x`
+type Shorthand = (
+    &'static str,
+    fn(Option<FileInfo>, Box<MetaTerm>) -> MetaTerm,
+);
+
 fn print_char(c: Option<char>) -> String {
     match c {
         Some(cc) => format!("'{}'", cc),
@@ -40,10 +161,18 @@ impl MetaParser {
             p: 0,
             line: 0,
             col: 0,
+            last: Location { line: 0, col: 0 },
             chars,
+            comment_vec: Vec::new(),
+            comment_error_vec: Vec::new(),
         }
     }
 
+    /// Comments skipped so far, in order of appearance.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comment_vec
+    }
+
     fn loc(&self) -> Location {
         Location {
             line: self.line,
@@ -59,14 +188,12 @@ impl MetaParser {
             filepath: self.filepath.clone(),
         })
     }
-    fn rangeinfo(&self, n: usize) -> Option<FileInfo> {
+    /// Info of the range from `from` to the last char read.
+    fn info_from(&self, from: &Location) -> Option<FileInfo> {
         Some(FileInfo {
             range: Range {
-                from: Location {
-                    line: self.loc().line,
-                    col: self.loc().col - n,
-                },
-                to: self.loc(),
+                from: from.clone(),
+                to: self.last.clone(),
             },
             filepath: self.filepath.clone(),
         })
@@ -76,6 +203,7 @@ impl MetaParser {
             return None;
         }
         let b = self.chars[self.p];
+        self.last = self.loc();
         self.p += 1;
         if b == '\n' {
             self.line += 1;
@@ -91,17 +219,97 @@ impl MetaParser {
         }
         Some(self.chars[self.p])
     }
-    fn store(&self) -> (usize, usize, usize) {
-        (self.p, self.line, self.col)
+    fn peek_second(&self) -> Option<char> {
+        self.chars.get(self.p + 1).copied()
     }
-    fn restore(&mut self, store: (usize, usize, usize)) {
-        self.p = store.0;
-        self.line = store.1;
-        self.col = store.2;
+    fn store(&self) -> Store {
+        Store {
+            p: self.p,
+            line: self.line,
+            col: self.col,
+            last: self.last.clone(),
+            comment_len: self.comment_vec.len(),
+            comment_error_len: self.comment_error_vec.len(),
+        }
+    }
+    fn restore(&mut self, store: Store) {
+        self.p = store.p;
+        self.line = store.line;
+        self.col = store.col;
+        self.last = store.last;
+        // comments are skipped again after backtracking
+        self.comment_vec.truncate(store.comment_len);
+        self.comment_error_vec.truncate(store.comment_error_len);
     }
 
-    fn skip(&mut self) -> String {
-        self.get_while(|c| c.is_whitespace())
+    /// Skips whitespace and comments.
+    fn skip(&mut self) {
+        loop {
+            self.get_while(|c| c.is_whitespace());
+            if !self.skip_comment() {
+                break;
+            }
+        }
+    }
+    /// Skips a comment at the current position if any, and returns whether skipped.
+    fn skip_comment(&mut self) -> bool {
+        let from = self.loc();
+        let start = self.p;
+        // comments in a datum comment come after it
+        let index = self.comment_vec.len();
+        let kind = match (self.peek(), self.peek_second()) {
+            (Some(';'), _) => {
+                self.get_while(|c| c != '\n');
+                CommentKind::Line
+            }
+            (Some('#'), Some('|')) => {
+                self.skip_block_comment();
+                CommentKind::Block
+            }
+            (Some('#'), Some(';')) => {
+                self.get();
+                self.get();
+                self.skip();
+                if !matches!(self.peek(), Some(')') | None) {
+                    self.skip_datum();
+                }
+                CommentKind::Datum
+            }
+            _ => return false,
+        };
+        let comment = Comment {
+            info: self.info_from(&from),
+            kind,
+            text: self.chars[start..self.p].iter().collect(),
+        };
+        self.comment_vec.insert(index, comment);
+        true
+    }
+    fn skip_block_comment(&mut self) {
+        let info = self.locinfo();
+        self.get();
+        self.get();
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.get(), self.peek()) {
+                (Some('#'), Some('|')) => {
+                    self.get();
+                    depth += 1;
+                }
+                (Some('|'), Some('#')) => {
+                    self.get();
+                    depth -= 1;
+                }
+                (Some(_), _) => {}
+                (None, _) => {
+                    self.comment_error_vec.push(CompileError {
+                        info,
+                        message: Some("unterminated block comment: found <EOF>".into()),
+                    });
+                    break;
+                }
+            }
+        }
     }
 
     fn get_while<F>(&mut self, f: F) -> String
@@ -117,11 +325,23 @@ impl MetaParser {
         }
         id.iter().collect()
     }
+    fn get_start_parenthesis(&mut self, message: &str) -> Result<(), CompileError> {
+        let info = self.locinfo();
+        let c = self.get();
+        if c != Some('(') {
+            return Err(CompileError {
+                info,
+                message: Some(format!("{}: found {}", message, print_char(c))),
+            });
+        }
+        Ok(())
+    }
     fn get_end_parenthesis(&mut self, message: &str) -> Result<(), CompileError> {
+        let info = self.locinfo();
         let c = self.get();
         if c != Some(')') {
             return Err(CompileError {
-                info: self.locinfo(),
+                info,
                 message: Some(format!("{}: found {}", message, print_char(c))),
             });
         }
@@ -129,36 +349,155 @@ impl MetaParser {
     }
 
     // parser combinators
-    fn parse_decimal_number(&mut self) -> Option<BigUint> {
-        let n = self.get_while(|c| c.is_ascii_digit());
-        if n.is_empty() {
-            None
-        } else {
-            Some(BigUint::parse_bytes(n.as_bytes(), 10).unwrap())
+    /// Integer literal with optional `-` sign, e.g. `42`, `-42`, `0xff`, `0o17`, `0b1010` and `1_000_000`.
+    /// Returns None if not starting with a digit.
+    fn parse_integer(&mut self) -> Result<Option<BigInt>, CompileError> {
+        let store = self.store();
+        let from = self.loc();
+        let negative = self.peek() == Some('-');
+        if negative {
+            self.get();
+        }
+        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.restore(store);
+            return Ok(None);
         }
+        let (radix, name) = match (self.peek(), self.peek_second()) {
+            (Some('0'), Some('x')) => (16, "hexadecimal"),
+            (Some('0'), Some('o')) => (8, "octal"),
+            (Some('0'), Some('b')) => (2, "binary"),
+            _ => (10, "decimal"),
+        };
+        if radix != 10 {
+            self.get();
+            self.get();
+        }
+        let n = self.parse_digits(radix, name, &from)?;
+        Ok(Some(if negative { -n } else { n }))
+    }
+    /// Digits in `radix`, which may be separated by `_`, e.g. `1_000`.
+    /// Letters following the digits are taken as invalid digits.
+    /// from: start of the literal
+    fn parse_digits(
+        &mut self,
+        radix: u32,
+        name: &str,
+        from: &Location,
+    ) -> Result<BigInt, CompileError> {
+        let mut token = Vec::<(char, Option<FileInfo>)>::new();
+        while let Some(c) = self.peek() {
+            if !c.is_alphanumeric() && c != '_' {
+                break;
+            }
+            token.push((c, self.locinfo()));
+            self.get();
+        }
+        if token.is_empty() {
+            return Err(CompileError {
+                info: self.info_from(from),
+                message: Some(format!(
+                    "{} literal has no digits: found {}",
+                    name,
+                    print_char(self.peek())
+                )),
+            });
+        }
+        for (i, (c, info)) in token.iter().enumerate() {
+            if *c == '_' {
+                let is_digit =
+                    |j: Option<usize>| j.and_then(|j| token.get(j)).is_some_and(|(c, _)| *c != '_');
+                if !is_digit(i.checked_sub(1)) || !is_digit(Some(i + 1)) {
+                    return Err(CompileError {
+                        info: info.clone(),
+                        message: Some("separator '_' must be between digits".into()),
+                    });
+                }
+            } else if !c.is_digit(radix) {
+                return Err(CompileError {
+                    info: info.clone(),
+                    message: Some(format!("invalid digit '{}' in {} literal", c, name)),
+                });
+            }
+        }
+        let digits: String = token
+            .iter()
+            .map(|(c, _)| *c)
+            .filter(|c| *c != '_')
+            .collect();
+        Ok(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap())
+    }
+    /// Integer or rational literal, e.g. `-3` and `3/4`.
+    /// Returns None if not starting with a digit.
+    fn parse_number(&mut self, from: &Location) -> Result<Option<MetaTerm>, CompileError> {
+        let Some(n) = self.parse_integer()? else {
+            return Ok(None);
+        };
+        Ok(Some(match self.parse_denominator()? {
+            Some(d) => MetaTerm::Rational(self.info_from(from), BigRational::new(n, d)),
+            None => MetaTerm::Number(self.info_from(from), n),
+        }))
+    }
+    /// Whether the token at the current position is a name rather than a number.
+    /// Only a whole token is taken as a number, e.g. `1+` and `-1x` are names.
+    /// Tokens starting with a digit and made of digits, letters, `_` and `/` are malformed numbers, e.g. `12abc`.
+    fn is_name_token(&mut self) -> bool {
+        let store = self.store();
+        let token: Vec<char> = self.chars[self.p..]
+            .iter()
+            .copied()
+            .take_while(|c| is_identifier(*c))
+            .collect();
+        let from = self.loc();
+        let is_name = match self.parse_number(&from) {
+            Ok(None) => true,
+            Ok(Some(_)) => self.peek().is_some_and(is_identifier),
+            Err(_) => {
+                token[0] == '-'
+                    || token
+                        .iter()
+                        .any(|c| !c.is_alphanumeric() && *c != '_' && *c != '/')
+            }
+        };
+        self.restore(store);
+        is_name
+    }
+    /// Denominator of rational literal after the numerator, e.g. `/4` of `3/4`.
+    fn parse_denominator(&mut self) -> Result<Option<BigInt>, CompileError> {
+        let store = self.store();
+        if self.get() != Some('/') || !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.restore(store);
+            return Ok(None);
+        }
+        let from = self.loc();
+        let d = self.parse_digits(10, "decimal", &from)?;
+        if d.is_zero() {
+            return Err(CompileError {
+                info: self.info_from(&from),
+                message: Some("denominator of rational literal is 0".into()),
+            });
+        }
+        Ok(Some(d))
     }
     fn parse_name_define(&mut self) -> Result<Option<String>, CompileError> {
+        let from = self.loc();
         let id = self.parse_identifier();
         if let Some(id) = &id {
             if is_reserved_name(id) {
                 return Err(CompileError {
-                    info: self.locinfo(),
-                    message: Some(format!(
-                        "expected string starting double quotation: found {}",
-                        print_char(self.peek())
-                    )),
+                    info: self.info_from(&from),
+                    message: Some(format!("reserved name \"{}\" cannot be bound", id)),
                 });
             }
         }
         Ok(id)
     }
     fn parse_identifier(&mut self) -> Option<String> {
-        if let Some(c) = self.peek() {
-            if is_identifier_head(c) {
-                return Some(self.get_while(is_identifier));
+        match self.peek() {
+            Some(c) if is_identifier(c) && self.is_name_token() => {
+                Some(self.get_while(is_identifier))
             }
+            _ => None,
         }
-        None
     }
 
     fn parse_string(&mut self) -> Result<String, CompileError> {
@@ -180,6 +519,9 @@ impl MetaParser {
                         'n' => s.push('\n'),
                         'r' => s.push('\r'),
                         't' => s.push('\t'),
+                        '0' => s.push('\0'),
+                        '"' => s.push('"'),
+                        'u' => s.push(self.parse_unicode_escape()?),
                         _ => {
                             return Err(CompileError {
                                 info: self.locinfo(),
@@ -201,26 +543,190 @@ impl MetaParser {
                     }
                 },
                 '"' => {
-                    break;
+                    return Ok(s);
                 }
                 _ => {
                     s.push(c);
                 }
             }
         }
-        Ok(s)
+        Err(CompileError {
+            info: self.locinfo(),
+            message: Some(format!(
+                "expected string ending double quotation: found {}",
+                print_char(None)
+            )),
+        })
     }
 
+    /// `{hex}` of the escape sequence `\u{hex}`, after `u`.
+    fn parse_unicode_escape(&mut self) -> Result<char, CompileError> {
+        let from = self.loc();
+        if self.get() != Some('{') {
+            return Err(CompileError {
+                info: self.info_from(&from),
+                message: Some("expected '{' after \"\\u\"".into()),
+            });
+        }
+        let hex = self.get_while(|c| c.is_ascii_hexdigit());
+        if self.get() != Some('}') || hex.is_empty() || hex.len() > 6 {
+            return Err(CompileError {
+                info: self.info_from(&from),
+                message: Some("expected 1 to 6 hex digits and '}' for \"\\u{...}\"".into()),
+            });
+        }
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| CompileError {
+                info: self.info_from(&from),
+                message: Some(format!("\"\\u{{{}}}\" is not a unicode scalar value", hex)),
+            })
+    }
+
+    /// Char literal after `#\\`, e.g. `a` of `#\\a`, `space`, `u{3bb}`.
+    fn parse_char_body(&mut self, from: &Location) -> Result<char, CompileError> {
+        let c = match self.get() {
+            Some(c) => c,
+            None => {
+                return Err(CompileError {
+                    info: self.info_from(from),
+                    message: Some(format!("char expected: found {}", print_char(None))),
+                })
+            }
+        };
+        if !c.is_alphabetic() || !self.peek().is_some_and(is_identifier) {
+            return Ok(c);
+        }
+        if c == 'u' && self.peek() == Some('{') {
+            return self.parse_unicode_escape();
+        }
+        let name = format!("{}{}", c, self.get_while(is_identifier));
+        Ok(match name.as_str() {
+            "space" => ' ',
+            "newline" => '\n',
+            "return" => '\r',
+            "tab" => '\t',
+            "nul" => '\0',
+            _ => {
+                return Err(CompileError {
+                    info: self.info_from(from),
+                    message: Some(format!("unknown char name \"{}\"", name)),
+                })
+            }
+        })
+    }
+
+    /// Parses all statements. Fails with the first error, see `parse_stmt_vec_recovering` to get all.
     pub fn parse_stmt_vec(&mut self) -> Result<Vec<MetaStatement>, CompileError> {
+        let (stmt_vec, mut error_vec) = self.parse_stmt_vec_recovering();
+        if error_vec.is_empty() {
+            Ok(stmt_vec)
+        } else {
+            Err(error_vec.swap_remove(0))
+        }
+    }
+    /// Parses all statements, skipping statements with error to continue from the next one.
+    /// Returns the statements parsed successfully and the errors in order of appearance.
+    pub fn parse_stmt_vec_recovering(&mut self) -> (Vec<MetaStatement>, Vec<CompileError>) {
         let mut stmt_vec = Vec::<MetaStatement>::new();
-        while self.peek() != None {
-            stmt_vec.push(self.parse_stmt()?);
+        let mut error_vec = Vec::<CompileError>::new();
+        self.skip();
+        while self.peek().is_some() {
+            let store = self.store();
+            match self.parse_stmt() {
+                Ok(stmt) => stmt_vec.push(stmt),
+                Err(e) => {
+                    let at_eof = self.peek().is_none();
+                    self.restore(store);
+                    match self.skip_datum() {
+                        // the end of file is found only since the parenthesis is not closed
+                        Some(unclosed) if at_eof => error_vec.push(unclosed),
+                        _ => error_vec.push(e),
+                    }
+                }
+            }
             self.skip();
         }
-        Ok(stmt_vec)
+        error_vec.extend(self.comment_error_vec.iter().cloned());
+        error_vec.sort_by_key(|e| {
+            e.info
+                .as_ref()
+                .map(|info| (info.range.from.line, info.range.from.col))
+        });
+        (stmt_vec, error_vec)
+    }
+    /// Skips the datum, e.g. a statement with error, starting at the current position.
+    /// A parenthesized datum is skipped till its matching end parenthesis,
+    /// or till a start parenthesis at the head of a line if parentheses are not balanced,
+    /// as it is likely the next statement.
+    /// Returns the error at the start parenthesis in the latter case.
+    fn skip_datum(&mut self) -> Option<CompileError> {
+        let from = self.loc();
+        match self.get() {
+            Some('(') => {}
+            Some('"') => {
+                self.skip_string_body();
+                return None;
+            }
+            // stray end parenthesis
+            Some(')') => return None,
+            _ => {
+                self.get_while(|c| !c.is_whitespace() && c != '(' && c != ')');
+                return None;
+            }
+        }
+        let unclosed = CompileError {
+            info: self.info_from(&from),
+            message: Some("unclosed parenthesis opened here".into()),
+        };
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            if self.skip_comment() {
+                continue;
+            }
+            match c {
+                '(' if self.col == 0 => break,
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '"' => {
+                    self.get();
+                    self.skip_string_body();
+                    continue;
+                }
+                // char literal, e.g. #\(
+                '#' => {
+                    self.get();
+                    if self.peek() == Some('\\') {
+                        self.get();
+                        self.get();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            self.get();
+            if depth == 0 {
+                return None;
+            }
+        }
+        Some(unclosed)
+    }
+    /// Skips till the end of the string whose starting double quotation is already read.
+    fn skip_string_body(&mut self) {
+        while let Some(c) = self.get() {
+            match c {
+                '\\' => {
+                    self.get();
+                }
+                '"' => break,
+                _ => {}
+            }
+        }
     }
     pub fn parse_stmt(&mut self) -> Result<MetaStatement, CompileError> {
         self.skip();
+        let from = self.loc();
         if self.peek() == Some('(') {
             let store = self.store();
             'special_check: loop {
@@ -234,23 +740,22 @@ impl MetaParser {
                 };
                 self.skip();
                 match id.as_str() {
-                    "def" => return self.parse_stmt_def(),
-                    "defrec" => return self.parse_stmt_defrec(),
-                    "import" => return self.parse_stmt_import(),
-                    "export" => return self.parse_stmt_export(),
-                    "assert" => return self.parse_stmt_assert(),
+                    "def" => return self.parse_stmt_def(from),
+                    "defrec" => return self.parse_stmt_defrec(from),
+                    "import" => return self.parse_stmt_import(from),
+                    "export" => return self.parse_stmt_export(from),
+                    "assert" => return self.parse_stmt_assert(from),
+                    "defmacro" => return self.parse_stmt_defmacro(from),
                     _ => break 'special_check,
                 };
             }
             self.restore(store);
         }
-        Ok(MetaStatement::Term(
-            self.locinfo(), /* TODO: locinfo */
-            self.parse_term()?,
-        ))
+        let term = self.parse_term()?;
+        Ok(MetaStatement::Term(self.info_from(&from), term))
     }
 
-    fn parse_stmt_def(&mut self) -> Result<MetaStatement, CompileError> {
+    fn parse_stmt_def(&mut self, from: Location) -> Result<MetaStatement, CompileError> {
         self.skip();
         let id = match self.parse_name_define()? {
             Some(id) => id,
@@ -265,22 +770,16 @@ impl MetaParser {
         let term = self.parse_term()?;
         self.skip();
         self.get_end_parenthesis("expect def statement end parenthesis")?;
-        Ok(MetaStatement::Def(
-            self.locinfo(), /* TODO: locinfo */
-            id,
-            term,
-        ))
+        Ok(MetaStatement::Def(self.info_from(&from), id, term))
     }
 
-    fn parse_stmt_defrec(&mut self) -> Result<MetaStatement, CompileError> {
+    fn parse_stmt_defrec(&mut self, from: Location) -> Result<MetaStatement, CompileError> {
         let fun_vec = self.parse_vec(|p| p.parse_defrec_fun().map(Some), "defrec definition")?;
-        Ok(MetaStatement::DefRec(
-            self.locinfo(), /* TODO: locinfo */
-            fun_vec,
-        ))
+        Ok(MetaStatement::DefRec(self.info_from(&from), fun_vec))
     }
 
     fn parse_defrec_fun(&mut self) -> Result<DefRecFun, CompileError> {
+        let from = self.loc();
         let name = match self.parse_name_define()? {
             Some(name) => name,
             None => {
@@ -290,29 +789,32 @@ impl MetaParser {
                 });
             }
         };
+        let name_info = self.info_from(&from);
         self.skip();
         let arg_name_vec = self.parse_arg_name_vec("defrec definition args")?;
         self.skip();
         let term = self.parse_term()?;
         Ok(DefRecFun {
+            info: self.info_from(&from),
+            name_info,
             name,
             arg_name_vec,
             term,
         })
     }
 
-    fn parse_stmt_import(&mut self) -> Result<MetaStatement, CompileError> {
+    fn parse_stmt_import(&mut self, from: Location) -> Result<MetaStatement, CompileError> {
         let string_vec = self.parse_string_vec()?;
-        Ok(MetaStatement::Import(self.locinfo(), string_vec))
+        Ok(MetaStatement::Import(self.info_from(&from), string_vec))
     }
 
     fn parse_string_vec(&mut self) -> Result<Vec<String>, CompileError> {
         self.parse_vec(|p| p.parse_string().map(Some), "string")
     }
 
-    fn parse_stmt_export(&mut self) -> Result<MetaStatement, CompileError> {
+    fn parse_stmt_export(&mut self, from: Location) -> Result<MetaStatement, CompileError> {
         Ok(MetaStatement::Export(
-            self.locinfo(),
+            self.info_from(&from),
             self.parse_vec(|p| p.parse_export().map(Some), "string or identifier")?,
         ))
     }
@@ -330,15 +832,53 @@ impl MetaParser {
         })
     }
 
-    fn parse_stmt_assert(&mut self) -> Result<MetaStatement, CompileError> {
+    fn parse_stmt_assert(&mut self, from: Location) -> Result<MetaStatement, CompileError> {
         let term = self.parse_term()?;
         self.skip();
         self.get_end_parenthesis("expect assert statement end parenthesis")?;
-        Ok(MetaStatement::Assert(self.locinfo(), term))
+        Ok(MetaStatement::Assert(self.info_from(&from), term))
+    }
+
+    fn parse_stmt_defmacro(&mut self, from: Location) -> Result<MetaStatement, CompileError> {
+        let name = match self.parse_name_define()? {
+            Some(name) => name,
+            None => {
+                return Err(CompileError {
+                    info: self.locinfo(),
+                    message: Some("defmacro should follow macro name".into()),
+                });
+            }
+        };
+        self.skip();
+        let args_from = self.loc();
+        let arg_name_vec = self.parse_arg_name_vec("defmacro args")?;
+        if arg_name_vec.iter().any(|arg| arg == "gensym") {
+            return Err(CompileError {
+                info: self.info_from(&args_from),
+                message: Some(
+                    "defmacro args cannot have \"gensym\", which is bound for the macro".into(),
+                ),
+            });
+        }
+        self.skip();
+        let term = self.parse_term()?;
+        self.skip();
+        self.get_end_parenthesis("expect defmacro statement end parenthesis")?;
+        Ok(MetaStatement::DefMacro(
+            self.info_from(&from),
+            name,
+            arg_name_vec,
+            term,
+        ))
     }
 
     pub fn parse_term(&mut self) -> Result<MetaTerm, CompileError> {
         self.skip();
+        let from = self.loc();
+        if let Some((_, wrap)) = self.parse_shorthand() {
+            let term = self.parse_term()?;
+            return Ok(wrap(self.info_from(&from), term.into()));
+        }
         if self.peek() == Some('(') {
             self.get();
             self.skip();
@@ -350,102 +890,394 @@ impl MetaParser {
                     id
                 } {
                     Some(id) => match id.as_str() {
-                        "lambda" => return self.parse_term_lambda(),
-                        "cons" => return self.parse_term_cons(),
-                        "list" => return self.parse_term_list_direct(),
-                        "quote" => return self.parse_term_quote(),
-                        "if" => return self.parse_term_if(),
+                        "lambda" => return self.parse_term_lambda(from),
+                        "cons" => return self.parse_term_cons(from),
+                        "list" => return self.parse_term_list_direct(from),
+                        "quote" => return self.parse_term_quote(from, "quote", MetaTerm::Quote),
+                        "quasiquote" => {
+                            return self.parse_term_quote(from, "quasiquote", MetaTerm::Quasiquote)
+                        }
+                        "unquote" => {
+                            return self.parse_term_quote(from, "unquote", MetaTerm::Unquote)
+                        }
+                        "unquote-splicing" => {
+                            return self.parse_term_quote(
+                                from,
+                                "unquote-splicing",
+                                MetaTerm::UnquoteSplicing,
+                            )
+                        }
+                        "if" => return self.parse_term_if(from),
+                        "let" => return self.parse_term_let(from),
+                        "let*" => return self.parse_term_let_star(from),
+                        "letrec" => return self.parse_term_letrec(from),
+                        "cond" => return self.parse_term_cond(from),
+                        "case" => return self.parse_term_case(from),
+                        "and" => return self.parse_term_and(from),
+                        "or" => return self.parse_term_or(from),
+                        "match" => return self.parse_term_match(from),
                         _ => break 'special_check,
                     },
                     None => break 'special_check,
                 }
             }
             self.restore(store);
-            self.parse_term_apply()
+            self.parse_term_apply(from)
         } else {
             self.parse_term_value()
         }
     }
 
-    fn parse_term_lambda(&mut self) -> Result<MetaTerm, CompileError> {
+    fn parse_term_lambda(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
         let arg_name_vec = self.parse_arg_name_vec("lambda args")?;
         self.skip();
         let body = self.parse_term()?;
         self.skip();
         self.get_end_parenthesis("expect lambda end parenthesis for args")?;
         Ok(MetaTerm::Lambda(
-            self.locinfo(), /* TODO: locinfo */
+            self.info_from(&from),
             arg_name_vec,
             body.into(),
         ))
     }
 
-    fn parse_term_apply(&mut self) -> Result<MetaTerm, CompileError> {
+    fn parse_term_let(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let binding_vec = self.parse_binding_vec("let")?;
+        let body = self.parse_let_body("let")?;
+        Ok(MetaTerm::Let(
+            self.info_from(&from),
+            binding_vec,
+            body.into(),
+        ))
+    }
+
+    fn parse_term_let_star(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let binding_vec = self.parse_binding_vec("let*")?;
+        let body = self.parse_let_body("let*")?;
+        Ok(MetaTerm::LetStar(
+            self.info_from(&from),
+            binding_vec,
+            body.into(),
+        ))
+    }
+
+    fn parse_term_letrec(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        self.get_start_parenthesis("letrec bindings: expect start parenthesis")?;
+        self.skip();
+        let fun_vec = self.parse_vec(
+            |p| {
+                p.get_start_parenthesis("letrec binding: expect start parenthesis")?;
+                p.skip();
+                let fun = p.parse_defrec_fun()?;
+                p.skip();
+                p.get_end_parenthesis("expect letrec binding end parenthesis")?;
+                Ok(Some(fun))
+            },
+            "letrec binding",
+        )?;
+        let body = self.parse_let_body("letrec")?;
+        Ok(MetaTerm::LetRec(
+            self.info_from(&from),
+            fun_vec,
+            body.into(),
+        ))
+    }
+
+    /// Bindings of let, e.g. `((x 1) (y 2))`.
+    fn parse_binding_vec(&mut self, form: &str) -> Result<Vec<(String, MetaTerm)>, CompileError> {
+        self.get_start_parenthesis(&format!("{} bindings: expect start parenthesis", form))?;
+        self.skip();
+        self.parse_vec(
+            |p| {
+                p.get_start_parenthesis(&format!("{} binding: expect start parenthesis", form))?;
+                p.skip();
+                let name = match p.parse_name_define()? {
+                    Some(name) => name,
+                    None => {
+                        return Err(CompileError {
+                            info: p.locinfo(),
+                            message: Some(format!("expect {} binding variable name", form)),
+                        });
+                    }
+                };
+                p.skip();
+                let value = p.parse_term()?;
+                p.skip();
+                p.get_end_parenthesis(&format!("expect {} binding end parenthesis", form))?;
+                Ok(Some((name, value)))
+            },
+            &format!("{} binding", form),
+        )
+    }
+
+    fn parse_let_body(&mut self, form: &str) -> Result<MetaTerm, CompileError> {
+        self.skip();
+        let body = self.parse_term()?;
+        self.skip();
+        self.get_end_parenthesis(&format!("expect {} end parenthesis", form))?;
+        Ok(body)
+    }
+
+    fn parse_term_cond(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let mut clause_vec = Vec::new();
+        let else_term = self.parse_clause_vec("cond", |p| {
+            let condition = p.parse_term()?;
+            let value = p.parse_clause_value("cond")?;
+            clause_vec.push((condition, value));
+            Ok(())
+        })?;
+        Ok(MetaTerm::Cond(self.info_from(&from), clause_vec, else_term))
+    }
+
+    fn parse_term_case(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let key = self.parse_term()?;
+        let mut clause_vec = Vec::new();
+        let else_term = self.parse_clause_vec("case", |p| {
+            p.get_start_parenthesis("case datums: expect start parenthesis")?;
+            p.skip();
+            let datum_vec = p.parse_vec(|p| p.parse_case_datum().map(Some), "case datum")?;
+            let value = p.parse_clause_value("case")?;
+            clause_vec.push((datum_vec, value));
+            Ok(())
+        })?;
+        Ok(MetaTerm::Case(
+            self.info_from(&from),
+            key.into(),
+            clause_vec,
+            else_term,
+        ))
+    }
+
+    /// Literal or symbol to be compared with the key of case.
+    fn parse_case_datum(&mut self) -> Result<MetaTerm, CompileError> {
+        let from = self.loc();
+        match self.parse_term_value()? {
+            datum @ (MetaTerm::Number(..)
+            | MetaTerm::Rational(..)
+            | MetaTerm::Bool(..)
+            | MetaTerm::String(..)
+            | MetaTerm::Char(..)
+            | MetaTerm::Nil(..)
+            | MetaTerm::Variable(..)) => {
+                self.skip();
+                Ok(datum)
+            }
+            _ => Err(CompileError {
+                info: self.info_from(&from),
+                message: Some("case datum should be a literal or symbol".into()),
+            }),
+        }
+    }
+
+    /// Clauses of cond or case till the end parenthesis of the form,
+    /// parsing each clause other than `else` by `f` after its start parenthesis.
+    /// Returns the value of the `else` clause, which should be the last.
+    fn parse_clause_vec<F>(
+        &mut self,
+        form: &str,
+        mut f: F,
+    ) -> Result<Option<Box<MetaTerm>>, CompileError>
+    where
+        F: FnMut(&mut Self) -> Result<(), CompileError>,
+    {
+        let mut else_term = None;
+        self.skip();
+        while self.peek() != Some(')') {
+            if else_term.is_some() {
+                return Err(CompileError {
+                    info: self.locinfo(),
+                    message: Some(format!("else clause of {} should be the last", form)),
+                });
+            }
+            self.get_start_parenthesis(&format!("{} clause: expect start parenthesis", form))?;
+            self.skip();
+            let store = self.store();
+            if self.parse_identifier().as_deref() == Some("else") {
+                else_term = Some(self.parse_clause_value(form)?.into());
+            } else {
+                self.restore(store);
+                f(self)?;
+            }
+            self.skip();
+        }
+        self.get();
+        Ok(else_term)
+    }
+
+    fn parse_clause_value(&mut self, form: &str) -> Result<MetaTerm, CompileError> {
+        self.skip();
+        let value = self.parse_term()?;
+        self.skip();
+        self.get_end_parenthesis(&format!("expect {} clause end parenthesis", form))?;
+        Ok(value)
+    }
+
+    fn parse_term_and(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let terms = self.parse_term_vec()?;
+        Ok(MetaTerm::And(self.info_from(&from), terms))
+    }
+
+    fn parse_term_or(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let terms = self.parse_term_vec()?;
+        Ok(MetaTerm::Or(self.info_from(&from), terms))
+    }
+
+    fn parse_term_match(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
+        let key = self.parse_term()?;
+        self.skip();
+        let clause_vec = self.parse_vec(
+            |p| {
+                p.get_start_parenthesis("match clause: expect start parenthesis")?;
+                p.skip();
+                let pattern = p.parse_pattern()?;
+                let value = p.parse_clause_value("match")?;
+                Ok(Some((pattern, value)))
+            },
+            "match clause",
+        )?;
+        Ok(MetaTerm::Match(
+            self.info_from(&from),
+            key.into(),
+            clause_vec,
+        ))
+    }
+
+    fn parse_pattern(&mut self) -> Result<MetaPattern, CompileError> {
+        let from = self.loc();
+        let error = |p: &Self| CompileError {
+            info: p.info_from(&from),
+            message: Some(
+                "pattern should be cons, list, nil, a literal, _ or a variable name".into(),
+            ),
+        };
+        if self.peek() == Some('(') {
+            self.get();
+            self.skip();
+            let id = self.parse_identifier();
+            self.skip();
+            return match id.as_deref() {
+                Some("cons") => {
+                    let head = self.parse_pattern()?;
+                    self.skip();
+                    let tail = self.parse_pattern()?;
+                    self.skip();
+                    self.get_end_parenthesis("expect cons pattern end parenthesis")?;
+                    Ok(MetaPattern::Cons(
+                        self.info_from(&from),
+                        head.into(),
+                        tail.into(),
+                    ))
+                }
+                Some("list") => {
+                    let pattern_vec = self.parse_vec(|p| p.parse_pattern().map(Some), "pattern")?;
+                    Ok(MetaPattern::List(self.info_from(&from), pattern_vec))
+                }
+                _ => Err(error(self)),
+            };
+        }
+        let term = self.parse_term_value()?;
+        let info = self.info_from(&from);
+        match term {
+            MetaTerm::Variable(_, name) if name == "_" => Ok(MetaPattern::Wildcard(info)),
+            MetaTerm::Variable(_, name) if is_reserved_name(&name) => Err(CompileError {
+                info,
+                message: Some(format!("reserved name \"{}\" cannot be bound", name)),
+            }),
+            MetaTerm::Variable(_, name) => Ok(MetaPattern::Bind(info, name)),
+            MetaTerm::Nil(_) => Ok(MetaPattern::Nil(info)),
+            literal @ (MetaTerm::Number(..)
+            | MetaTerm::Rational(..)
+            | MetaTerm::Bool(..)
+            | MetaTerm::String(..)
+            | MetaTerm::Char(..)) => Ok(MetaPattern::Literal(info, literal)),
+            _ => Err(error(self)),
+        }
+    }
+
+    fn parse_term_apply(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
         let terms = self.parse_term_vec()?;
         if terms.is_empty() {
             return Err(CompileError {
-                info: self.locinfo(),
+                info: self.info_from(&from),
                 message: Some("Apply without operator not allowed".into()),
             });
         }
         Ok(MetaTerm::Apply(
-            self.locinfo(), /* TODO: locinfo */
+            self.info_from(&from),
             terms[0].clone().into(),
             terms.into_iter().skip(1).collect(),
         ))
     }
 
-    fn parse_term_cons(&mut self) -> Result<MetaTerm, CompileError> {
+    fn parse_term_cons(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
         let terms = self.parse_term_vec()?;
         if terms.len() != 2 {
             return Err(CompileError {
-                info: self.locinfo(),
+                info: self.info_from(&from),
                 message: Some("cons only accepts 2 terms".into()),
             });
         }
         self.skip();
         Ok(MetaTerm::Cons(
-            self.locinfo(), /* TODO: locinfo */
+            self.info_from(&from),
             terms[0].clone().into(),
             terms[1].clone().into(),
         ))
     }
 
-    fn parse_term_list_direct(&mut self) -> Result<MetaTerm, CompileError> {
+    fn parse_term_list_direct(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
         let terms = self.parse_term_vec()?;
         self.skip();
-        Ok(MetaTerm::List(
-            self.locinfo(), /* TODO: locinfo */
-            terms,
-        ))
+        Ok(MetaTerm::List(self.info_from(&from), terms))
     }
 
-    fn parse_term_quote(&mut self) -> Result<MetaTerm, CompileError> {
+    /// `quote` and the forms written with its shorthands
+    fn parse_term_quote(
+        &mut self,
+        from: Location,
+        form: &str,
+        wrap: fn(Option<FileInfo>, Box<MetaTerm>) -> MetaTerm,
+    ) -> Result<MetaTerm, CompileError> {
         let terms = self.parse_term_vec()?;
         if terms.len() != 1 {
             return Err(CompileError {
-                info: self.locinfo(),
-                message: Some("quote only accepts 1 term".into()),
+                info: self.info_from(&from),
+                message: Some(format!("{} only accepts 1 term", form)),
             });
         }
         self.skip();
-        Ok(MetaTerm::Quote(
-            self.locinfo(), /* TODO: locinfo */
-            terms[0].clone().into(),
-        ))
+        Ok(wrap(self.info_from(&from), terms[0].clone().into()))
     }
 
-    fn parse_term_if(&mut self) -> Result<MetaTerm, CompileError> {
+    fn parse_shorthand(&mut self) -> Option<Shorthand> {
+        let shorthand: Shorthand = match self.peek()? {
+            '\'' => ("quote", MetaTerm::Quote),
+            '`' => ("quasiquote", MetaTerm::Quasiquote),
+            ',' => {
+                self.get();
+                if self.peek() == Some('@') {
+                    self.get();
+                    return Some(("unquote-splicing", MetaTerm::UnquoteSplicing));
+                }
+                return Some(("unquote", MetaTerm::Unquote));
+            }
+            _ => return None,
+        };
+        self.get();
+        Some(shorthand)
+    }
+
+    fn parse_term_if(&mut self, from: Location) -> Result<MetaTerm, CompileError> {
         let terms = self.parse_term_vec()?;
         if terms.len() != 3 {
             return Err(CompileError {
-                info: self.locinfo(),
+                info: self.info_from(&from),
                 message: Some("if only accepts 3 terms".into()),
             });
         }
         self.skip();
         Ok(MetaTerm::If(
-            self.locinfo(), /* TODO: locinfo */
+            self.info_from(&from),
             terms[0].clone().into(),
             terms[1].clone().into(),
             terms[2].clone().into(),
@@ -453,38 +1285,39 @@ impl MetaParser {
     }
 
     fn parse_term_value(&mut self) -> Result<MetaTerm, CompileError> {
+        let from = self.loc();
+        if self.peek() == Some('"') {
+            let s = self.parse_string()?;
+            return Ok(MetaTerm::String(self.info_from(&from), s));
+        }
+        let store = self.store();
+        if self.get() == Some('#') && self.get() == Some('\\') {
+            let c = self.parse_char_body(&from)?;
+            return Ok(MetaTerm::Char(self.info_from(&from), c));
+        }
+        self.restore(store);
+        // before identifiers, since `-` followed by digits is a negative number
+        if !self.is_name_token() {
+            if let Some(number) = self.parse_number(&from)? {
+                return Ok(number);
+            }
+        }
         Ok(match self.parse_identifier() {
-            Some(id) => match id.as_str() {
-                "nil" => MetaTerm::Nil(self.rangeinfo(id.len())),
-                "true" => MetaTerm::Bool(self.rangeinfo(id.len()), true),
-                "false" => MetaTerm::Bool(self.rangeinfo(id.len()), false),
-                "car" => MetaTerm::Car(self.rangeinfo(id.len())),
-                "cdr" => MetaTerm::Cdr(self.rangeinfo(id.len())),
-                "eval" => MetaTerm::Eval(self.rangeinfo(id.len())),
-                "eq" => MetaTerm::Eq(self.rangeinfo(id.len())),
-                "+" => MetaTerm::Add(self.rangeinfo(id.len())),
-                "-" => MetaTerm::Sub(self.rangeinfo(id.len())),
-                "*" => MetaTerm::Mul(self.rangeinfo(id.len())),
-                "/" => MetaTerm::Div(self.rangeinfo(id.len())),
-                "%" => MetaTerm::Rem(self.rangeinfo(id.len())),
-                _ => MetaTerm::Variable(self.rangeinfo(id.len()), id),
-            },
-            None => match self.parse_decimal_number() {
-                Some(n) => MetaTerm::Number(self.locinfo() /* TODO: locinfo */, n),
-                None => Err(CompileError {
-                    info: self.locinfo(),
-                    message: Some(format!("value expected: found {}", print_char(self.peek()))),
-                })?,
-            },
+            Some(id) => name_to_term(self.info_from(&from), id),
+            None => Err(CompileError {
+                info: self.locinfo(),
+                message: Some(format!("value expected: found {}", print_char(self.peek()))),
+            })?,
         })
     }
 
     fn parse_arg_name_vec(&mut self, message: &str) -> Result<Vec<String>, CompileError> {
         {
+            let info = self.locinfo();
             let c = self.get();
             if c != Some('(') {
                 return Err(CompileError {
-                    info: self.locinfo(),
+                    info,
                     message: Some(format!(
                         "{}: expect start parenthesis for args: got {}",
                         message,
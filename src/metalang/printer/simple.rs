@@ -1,22 +1,5 @@
-use super::super::syntax::{DefRecFun, MetaExport, MetaStatement, MetaTerm};
-
-fn print_string(s: &String) -> String {
-    format!(
-        "\"{}\"",
-        s.chars()
-            // TODO another control chars
-            .map(|c| match c {
-                '\\' => "\\\\".to_string(),
-                '\n' => "\\n".to_string(),
-                '\r' => "\\r".to_string(),
-                '\t' => "\\t".to_string(),
-                '"' => "\\\"".to_string(),
-                _ => format!("{}", c),
-            })
-            .collect::<Vec<String>>()
-            .join("")
-    )
-}
+use super::super::syntax::{DefRecFun, MetaExport, MetaPattern, MetaStatement, MetaTerm};
+use crate::corelang::printer::simple::{print_char, print_string};
 
 pub struct SimplePrinter {}
 
@@ -52,6 +35,12 @@ impl SimplePrinter {
             MetaStatement::Export(_, export_vec) => {
                 format!("(export {})", self.print_export_vec(export_vec))
             }
+            MetaStatement::DefMacro(_, name, arg_name_vec, body) => format!(
+                "(defmacro {} ({}) {})",
+                name,
+                self.print_arg_name_vec(arg_name_vec),
+                self.print_term(body)
+            ),
         }
     }
 
@@ -65,7 +54,7 @@ impl SimplePrinter {
 
     pub fn print_defrec_fun(&self, fun: &DefRecFun) -> String {
         format!(
-            "{} {} {}",
+            "{} ({}) {}",
             fun.name,
             self.print_arg_name_vec(&fun.arg_name_vec),
             self.print_term(&fun.term)
@@ -111,8 +100,14 @@ impl SimplePrinter {
             ),
 
             MetaTerm::Quote(_, t) => format!("(quote {})", self.print_term(t)),
+            MetaTerm::Quasiquote(_, t) => format!("(quasiquote {})", self.print_term(t)),
+            MetaTerm::Unquote(_, t) => format!("(unquote {})", self.print_term(t)),
+            MetaTerm::UnquoteSplicing(_, t) => format!("(unquote-splicing {})", self.print_term(t)),
+            MetaTerm::Hole(_, index, false) => format!("#<hole {}>", index),
+            MetaTerm::Hole(_, index, true) => format!("#<splicing hole {}>", index),
             MetaTerm::Variable(_, name) => name.clone(),
             MetaTerm::Eq(_) => "eq".into(),
+            MetaTerm::BetaEq(_) => "beta-eq".into(),
 
             MetaTerm::If(_, t1, t2, t3) => format!(
                 "(if {} {} {})",
@@ -127,6 +122,10 @@ impl SimplePrinter {
             MetaTerm::Nil(_) => "nil".into(),
 
             MetaTerm::Number(_, n) => n.to_string(),
+            MetaTerm::Rational(_, r) => r.to_string(),
+
+            MetaTerm::String(_, s) => print_string(s),
+            MetaTerm::Char(_, c) => print_char(*c),
 
             MetaTerm::Bool(_, b) => b.to_string(),
 
@@ -137,11 +136,136 @@ impl SimplePrinter {
             MetaTerm::Mul(_) => "*".into(),
             MetaTerm::Div(_) => "/".into(),
             MetaTerm::Rem(_) => "%".into(),
+            MetaTerm::Monus(_) => "monus".into(),
+            MetaTerm::Neg(_) => "neg".into(),
+            MetaTerm::Abs(_) => "abs".into(),
+            MetaTerm::Numerator(_) => "numerator".into(),
+            MetaTerm::Denominator(_) => "denominator".into(),
+            MetaTerm::Lt(_) => "<".into(),
+            MetaTerm::Le(_) => "<=".into(),
+            MetaTerm::Gt(_) => ">".into(),
+            MetaTerm::Ge(_) => ">=".into(),
+            MetaTerm::Min(_) => "min".into(),
+            MetaTerm::Max(_) => "max".into(),
+            MetaTerm::Pow(_) => "pow".into(),
+            MetaTerm::ModPow(_) => "modpow".into(),
+            MetaTerm::Gcd(_) => "gcd".into(),
+            MetaTerm::Isqrt(_) => "isqrt".into(),
+            MetaTerm::Shl(_) => "shl".into(),
+            MetaTerm::Shr(_) => "shr".into(),
+            MetaTerm::BitAnd(_) => "bit-and".into(),
+            MetaTerm::BitOr(_) => "bit-or".into(),
+            MetaTerm::BitXor(_) => "bit-xor".into(),
+            MetaTerm::StringLength(_) => "string-length".into(),
+            MetaTerm::StringAppend(_) => "string-append".into(),
+            MetaTerm::Substring(_) => "substring".into(),
+            MetaTerm::CharToInteger(_) => "char->integer".into(),
+            MetaTerm::IntegerToChar(_) => "integer->char".into(),
+            MetaTerm::CharToString(_) => "char->string".into(),
+            MetaTerm::StringToList(_) => "string->list".into(),
+            MetaTerm::ListToString(_) => "list->string".into(),
+            MetaTerm::IsSymbol(_) => "symbol?".into(),
+            MetaTerm::SymbolToString(_) => "symbol->string".into(),
+            MetaTerm::StringToSymbol(_) => "string->symbol".into(),
+            MetaTerm::IsNumber(_) => "number?".into(),
+            MetaTerm::IsBool(_) => "bool?".into(),
+            MetaTerm::IsString(_) => "string?".into(),
+            MetaTerm::IsChar(_) => "char?".into(),
+            MetaTerm::IsCons(_) => "cons?".into(),
+            MetaTerm::IsNil(_) => "nil?".into(),
+            MetaTerm::IsLambda(_) => "lambda?".into(),
+            MetaTerm::IsQuote(_) => "quote?".into(),
+            MetaTerm::TypeOf(_) => "type-of".into(),
 
             MetaTerm::Car(_) => "car".into(),
             MetaTerm::Cdr(_) => "cdr".into(),
 
             MetaTerm::List(_, list) => format!("(list {})", self.print_term_vec(list)),
+            MetaTerm::Let(_, binding_vec, body) => format!(
+                "(let ({}) {})",
+                self.print_binding_vec(binding_vec),
+                self.print_term(body)
+            ),
+            MetaTerm::LetStar(_, binding_vec, body) => format!(
+                "(let* ({}) {})",
+                self.print_binding_vec(binding_vec),
+                self.print_term(body)
+            ),
+            MetaTerm::LetRec(_, fun_vec, body) => format!(
+                "(letrec ({}) {})",
+                fun_vec
+                    .iter()
+                    .map(|fun| format!("({})", self.print_defrec_fun(fun)))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                self.print_term(body)
+            ),
+            MetaTerm::Cond(_, clause_vec, else_term) => {
+                let mut clause_vec: Vec<String> = clause_vec
+                    .iter()
+                    .map(|(t1, t2)| format!("({} {})", self.print_term(t1), self.print_term(t2)))
+                    .collect();
+                clause_vec.extend(self.print_else(else_term));
+                format!("(cond {})", clause_vec.join(" "))
+            }
+            MetaTerm::Case(_, key, clause_vec, else_term) => {
+                let mut clause_vec: Vec<String> = clause_vec
+                    .iter()
+                    .map(|(datum_vec, t)| {
+                        format!(
+                            "(({}) {})",
+                            self.print_term_vec(datum_vec),
+                            self.print_term(t)
+                        )
+                    })
+                    .collect();
+                clause_vec.extend(self.print_else(else_term));
+                format!("(case {} {})", self.print_term(key), clause_vec.join(" "))
+            }
+            MetaTerm::And(_, ts) if ts.is_empty() => "(and)".into(),
+            MetaTerm::And(_, ts) => format!("(and {})", self.print_term_vec(ts)),
+            MetaTerm::Or(_, ts) if ts.is_empty() => "(or)".into(),
+            MetaTerm::Or(_, ts) => format!("(or {})", self.print_term_vec(ts)),
+            MetaTerm::Match(_, key, clause_vec) => {
+                let mut vec = vec![self.print_term(key)];
+                vec.extend(clause_vec.iter().map(|(pattern, t)| {
+                    format!("({} {})", self.print_pattern(pattern), self.print_term(t))
+                }));
+                format!("(match {})", vec.join(" "))
+            }
         }
     }
+
+    pub fn print_pattern(&self, pattern: &MetaPattern) -> String {
+        match pattern {
+            MetaPattern::Wildcard(_) => "_".into(),
+            MetaPattern::Bind(_, name) => name.clone(),
+            MetaPattern::Literal(_, literal) => self.print_term(literal),
+            MetaPattern::Nil(_) => "nil".into(),
+            MetaPattern::Cons(_, p1, p2) => format!(
+                "(cons {} {})",
+                self.print_pattern(p1),
+                self.print_pattern(p2)
+            ),
+            MetaPattern::List(_, ps) => {
+                let mut vec = vec!["list".to_string()];
+                vec.extend(ps.iter().map(|p| self.print_pattern(p)));
+                format!("({})", vec.join(" "))
+            }
+        }
+    }
+
+    fn print_else(&self, else_term: &Option<Box<MetaTerm>>) -> Option<String> {
+        else_term
+            .as_ref()
+            .map(|t| format!("(else {})", self.print_term(t)))
+    }
+
+    fn print_binding_vec(&self, binding_vec: &[(String, MetaTerm)]) -> String {
+        binding_vec
+            .iter()
+            .map(|(name, value)| format!("({} {})", name, self.print_term(value)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
 }
@@ -1,7 +1,10 @@
 use crate::common::fileinfo::{CompileError, FileInfo};
-use crate::corelang::syntax::Term;
-use num_bigint::BigUint;
+use crate::corelang::syntax::{rational_to_term, Term};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 
 // syntax
 #[derive(Clone, Debug)]
@@ -12,8 +15,16 @@ pub enum MetaTerm {
     Lambda(Option<FileInfo>, Vec<String>, Box<MetaTerm>),
 
     Quote(Option<FileInfo>, Box<MetaTerm>),
+    /// quote with unquoted parts filled by their values
+    Quasiquote(Option<FileInfo>, Box<MetaTerm>),
+    Unquote(Option<FileInfo>, Box<MetaTerm>),
+    /// spliced into the args of an application or the elements of a list
+    UnquoteSplicing(Option<FileInfo>, Box<MetaTerm>),
+    /// (index, splicing), an unquoted part taken out of a quasiquote template during the transform
+    Hole(Option<FileInfo>, usize, bool),
     Variable(Option<FileInfo>, String),
     Eq(Option<FileInfo>),
+    BetaEq(Option<FileInfo>),
 
     If(
         Option<FileInfo>,
@@ -27,7 +38,12 @@ pub enum MetaTerm {
     Nil(Option<FileInfo>),
 
     // arith
-    Number(Option<FileInfo>, BigUint),
+    Number(Option<FileInfo>, BigInt),
+    Rational(Option<FileInfo>, BigRational),
+
+    // string
+    String(Option<FileInfo>, String),
+    Char(Option<FileInfo>, char),
 
     // bool
     Bool(Option<FileInfo>, bool),
@@ -41,6 +57,46 @@ pub enum MetaTerm {
     Mul(Option<FileInfo>),
     Div(Option<FileInfo>),
     Rem(Option<FileInfo>),
+    Monus(Option<FileInfo>),
+    Neg(Option<FileInfo>),
+    Abs(Option<FileInfo>),
+    Numerator(Option<FileInfo>),
+    Denominator(Option<FileInfo>),
+    Lt(Option<FileInfo>),
+    Le(Option<FileInfo>),
+    Gt(Option<FileInfo>),
+    Ge(Option<FileInfo>),
+    Min(Option<FileInfo>),
+    Max(Option<FileInfo>),
+    Pow(Option<FileInfo>),
+    ModPow(Option<FileInfo>),
+    Gcd(Option<FileInfo>),
+    Isqrt(Option<FileInfo>),
+    Shl(Option<FileInfo>),
+    Shr(Option<FileInfo>),
+    BitAnd(Option<FileInfo>),
+    BitOr(Option<FileInfo>),
+    BitXor(Option<FileInfo>),
+    StringLength(Option<FileInfo>),
+    StringAppend(Option<FileInfo>),
+    Substring(Option<FileInfo>),
+    CharToInteger(Option<FileInfo>),
+    IntegerToChar(Option<FileInfo>),
+    CharToString(Option<FileInfo>),
+    StringToList(Option<FileInfo>),
+    ListToString(Option<FileInfo>),
+    IsSymbol(Option<FileInfo>),
+    SymbolToString(Option<FileInfo>),
+    StringToSymbol(Option<FileInfo>),
+    IsNumber(Option<FileInfo>),
+    IsBool(Option<FileInfo>),
+    IsString(Option<FileInfo>),
+    IsChar(Option<FileInfo>),
+    IsCons(Option<FileInfo>),
+    IsNil(Option<FileInfo>),
+    IsLambda(Option<FileInfo>),
+    IsQuote(Option<FileInfo>),
+    TypeOf(Option<FileInfo>),
 
     // structure op
     Car(Option<FileInfo>),
@@ -48,7 +104,36 @@ pub enum MetaTerm {
 
     // meta specific
     List(Option<FileInfo>, Vec<MetaTerm>),
-    // TODO: let be in
+    /// (bindings of (name, value), body), values are bound in parallel
+    Let(Option<FileInfo>, Vec<(String, MetaTerm)>, Box<MetaTerm>),
+    /// (bindings of (name, value), body), each value sees the names bound before it
+    LetStar(Option<FileInfo>, Vec<(String, MetaTerm)>, Box<MetaTerm>),
+    /// (mutually recursive functions, body)
+    LetRec(Option<FileInfo>, Vec<DefRecFun>, Box<MetaTerm>),
+    /// (clauses of (condition, value), else value), nil without else
+    Cond(
+        Option<FileInfo>,
+        Vec<(MetaTerm, MetaTerm)>,
+        Option<Box<MetaTerm>>,
+    ),
+    /// (key, clauses of (datums, value), else value), nil without else.
+    /// Datums are literals, or variables taken as symbols.
+    Case(
+        Option<FileInfo>,
+        Box<MetaTerm>,
+        Vec<(Vec<MetaTerm>, MetaTerm)>,
+        Option<Box<MetaTerm>>,
+    ),
+    /// short circuit, true if empty
+    And(Option<FileInfo>, Vec<MetaTerm>),
+    /// short circuit, false if empty
+    Or(Option<FileInfo>, Vec<MetaTerm>),
+    /// (key, clauses of (pattern, value)), nil if no pattern matches
+    Match(
+        Option<FileInfo>,
+        Box<MetaTerm>,
+        Vec<(MetaPattern, MetaTerm)>,
+    ),
 }
 
 impl MetaTerm {
@@ -57,11 +142,19 @@ impl MetaTerm {
             MetaTerm::Apply(info, ..) => info,
             MetaTerm::Lambda(info, ..) => info,
             MetaTerm::Quote(info, ..) => info,
+            MetaTerm::Quasiquote(info, ..) => info,
+            MetaTerm::Unquote(info, ..) => info,
+            MetaTerm::UnquoteSplicing(info, ..) => info,
+            MetaTerm::Hole(info, ..) => info,
             MetaTerm::Variable(info, ..) => info,
             MetaTerm::Eq(info, ..) => info,
+            MetaTerm::BetaEq(info, ..) => info,
             MetaTerm::Cons(info, ..) => info,
             MetaTerm::Nil(info, ..) => info,
             MetaTerm::Number(info, ..) => info,
+            MetaTerm::Rational(info, ..) => info,
+            MetaTerm::String(info, ..) => info,
+            MetaTerm::Char(info, ..) => info,
             MetaTerm::Bool(info, ..) => info,
             MetaTerm::Eval(info, ..) => info,
             MetaTerm::Add(info, ..) => info,
@@ -69,10 +162,58 @@ impl MetaTerm {
             MetaTerm::Mul(info, ..) => info,
             MetaTerm::Div(info, ..) => info,
             MetaTerm::Rem(info, ..) => info,
+            MetaTerm::Monus(info, ..) => info,
+            MetaTerm::Neg(info, ..) => info,
+            MetaTerm::Abs(info, ..) => info,
+            MetaTerm::Numerator(info, ..) => info,
+            MetaTerm::Denominator(info, ..) => info,
+            MetaTerm::Lt(info, ..) => info,
+            MetaTerm::Le(info, ..) => info,
+            MetaTerm::Gt(info, ..) => info,
+            MetaTerm::Ge(info, ..) => info,
+            MetaTerm::Min(info, ..) => info,
+            MetaTerm::Max(info, ..) => info,
+            MetaTerm::Pow(info, ..) => info,
+            MetaTerm::ModPow(info, ..) => info,
+            MetaTerm::Gcd(info, ..) => info,
+            MetaTerm::Isqrt(info, ..) => info,
+            MetaTerm::Shl(info, ..) => info,
+            MetaTerm::Shr(info, ..) => info,
+            MetaTerm::BitAnd(info, ..) => info,
+            MetaTerm::BitOr(info, ..) => info,
+            MetaTerm::BitXor(info, ..) => info,
+            MetaTerm::StringLength(info, ..) => info,
+            MetaTerm::StringAppend(info, ..) => info,
+            MetaTerm::Substring(info, ..) => info,
+            MetaTerm::CharToInteger(info, ..) => info,
+            MetaTerm::IntegerToChar(info, ..) => info,
+            MetaTerm::CharToString(info, ..) => info,
+            MetaTerm::StringToList(info, ..) => info,
+            MetaTerm::ListToString(info, ..) => info,
+            MetaTerm::IsSymbol(info, ..) => info,
+            MetaTerm::SymbolToString(info, ..) => info,
+            MetaTerm::StringToSymbol(info, ..) => info,
+            MetaTerm::IsNumber(info, ..) => info,
+            MetaTerm::IsBool(info, ..) => info,
+            MetaTerm::IsString(info, ..) => info,
+            MetaTerm::IsChar(info, ..) => info,
+            MetaTerm::IsCons(info, ..) => info,
+            MetaTerm::IsNil(info, ..) => info,
+            MetaTerm::IsLambda(info, ..) => info,
+            MetaTerm::IsQuote(info, ..) => info,
+            MetaTerm::TypeOf(info, ..) => info,
             MetaTerm::If(info, ..) => info,
             MetaTerm::Car(info, ..) => info,
             MetaTerm::Cdr(info, ..) => info,
             MetaTerm::List(info, ..) => info,
+            MetaTerm::Let(info, ..) => info,
+            MetaTerm::LetStar(info, ..) => info,
+            MetaTerm::LetRec(info, ..) => info,
+            MetaTerm::Cond(info, ..) => info,
+            MetaTerm::Case(info, ..) => info,
+            MetaTerm::And(info, ..) => info,
+            MetaTerm::Or(info, ..) => info,
+            MetaTerm::Match(info, ..) => info,
         }
     }
 
@@ -80,36 +221,219 @@ impl MetaTerm {
     where
         F: Fn(Self) -> Self,
     {
-        match self {
+        match self.try_map_subterm(|t| Ok::<_, Infallible>(f(t))) {
+            Ok(t) => t,
+            Err(e) => match e {},
+        }
+    }
+
+    /// `map_subterm` stopping at the first error.
+    pub fn try_map_subterm<F, E>(&self, f: F) -> Result<Self, E>
+    where
+        F: Fn(Self) -> Result<Self, E>,
+    {
+        Ok(match self {
             Self::Apply(info, t1, ts) => Self::Apply(
                 (*info).clone(),
-                f((**t1).clone()).into(),
-                (*ts).clone().into_iter().map(f).collect(),
+                f((**t1).clone())?.into(),
+                ts.iter().cloned().map(&f).collect::<Result<_, _>>()?,
             ),
             Self::Lambda(info, a1, t1) => {
-                Self::Lambda((*info).clone(), a1.clone(), f((**t1).clone()).into())
+                Self::Lambda((*info).clone(), a1.clone(), f((**t1).clone())?.into())
             }
 
             Self::If(info, t1, t2, t3) => Self::If(
                 (*info).clone(),
-                f((**t1).clone()).into(),
-                f((**t2).clone()).into(),
-                f((**t3).clone()).into(),
+                f((**t1).clone())?.into(),
+                f((**t2).clone())?.into(),
+                f((**t3).clone())?.into(),
             ),
 
-            Self::Quote(info, t1) => Self::Quote((*info).clone(), f((**t1).clone()).into()),
+            Self::Quote(info, t1)
+            | Self::Quasiquote(info, t1)
+            | Self::Unquote(info, t1)
+            | Self::UnquoteSplicing(info, t1) => {
+                let wrap = match self {
+                    Self::Quote(..) => Self::Quote,
+                    Self::Quasiquote(..) => Self::Quasiquote,
+                    Self::Unquote(..) => Self::Unquote,
+                    _ => Self::UnquoteSplicing,
+                };
+                wrap((*info).clone(), f((**t1).clone())?.into())
+            }
 
             Self::Cons(info, t1, t2) => Self::Cons(
                 (*info).clone(),
-                f((**t1).clone()).into(),
-                f((**t2).clone()).into(),
+                f((**t1).clone())?.into(),
+                f((**t2).clone())?.into(),
             ),
 
-            Self::List(info, ts) => {
-                Self::List((*info).clone(), (*ts).clone().into_iter().map(f).collect())
-            }
+            Self::List(info, ts) => Self::List(
+                (*info).clone(),
+                ts.iter().cloned().map(&f).collect::<Result<_, _>>()?,
+            ),
+
+            Self::Let(info, bs, t1) => Self::Let(
+                (*info).clone(),
+                bs.iter()
+                    .map(|(name, t)| Ok((name.clone(), f(t.clone())?)))
+                    .collect::<Result<_, _>>()?,
+                f((**t1).clone())?.into(),
+            ),
+            Self::LetStar(info, bs, t1) => Self::LetStar(
+                (*info).clone(),
+                bs.iter()
+                    .map(|(name, t)| Ok((name.clone(), f(t.clone())?)))
+                    .collect::<Result<_, _>>()?,
+                f((**t1).clone())?.into(),
+            ),
+            Self::LetRec(info, fun_vec, t1) => Self::LetRec(
+                (*info).clone(),
+                fun_vec
+                    .iter()
+                    .map(|fun| {
+                        Ok(DefRecFun {
+                            term: f(fun.term.clone())?,
+                            ..fun.clone()
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                f((**t1).clone())?.into(),
+            ),
+            Self::Cond(info, cs, t1) => Self::Cond(
+                (*info).clone(),
+                cs.iter()
+                    .map(|(t2, t3)| Ok((f(t2.clone())?, f(t3.clone())?)))
+                    .collect::<Result<_, _>>()?,
+                match t1 {
+                    Some(t1) => Some(f((**t1).clone())?.into()),
+                    None => None,
+                },
+            ),
+            Self::Case(info, t1, cs, t2) => Self::Case(
+                (*info).clone(),
+                f((**t1).clone())?.into(),
+                cs.iter()
+                    .map(|(datum_vec, t3)| Ok((datum_vec.clone(), f(t3.clone())?)))
+                    .collect::<Result<_, _>>()?,
+                match t2 {
+                    Some(t2) => Some(f((**t2).clone())?.into()),
+                    None => None,
+                },
+            ),
+            Self::And(info, ts) => Self::And(
+                (*info).clone(),
+                ts.iter().cloned().map(&f).collect::<Result<_, _>>()?,
+            ),
+            Self::Or(info, ts) => Self::Or(
+                (*info).clone(),
+                ts.iter().cloned().map(&f).collect::<Result<_, _>>()?,
+            ),
+            Self::Match(info, t1, cs) => Self::Match(
+                (*info).clone(),
+                f((**t1).clone())?.into(),
+                cs.iter()
+                    .map(|(pattern, t2)| Ok((pattern.clone(), f(t2.clone())?)))
+                    .collect::<Result<_, _>>()?,
+            ),
 
             _ => self.clone(),
+        })
+    }
+
+    /// Direct subterms in order.
+    pub fn subterms(&self) -> Vec<&Self> {
+        match self {
+            Self::Apply(_, t1, ts) => {
+                let mut vec = vec![&**t1];
+                vec.extend(ts.iter());
+                vec
+            }
+            Self::Lambda(_, _, t1) => vec![t1],
+            Self::If(_, t1, t2, t3) => vec![t1, t2, t3],
+            Self::Quote(_, t1)
+            | Self::Quasiquote(_, t1)
+            | Self::Unquote(_, t1)
+            | Self::UnquoteSplicing(_, t1) => vec![t1],
+            Self::Cons(_, t1, t2) => vec![t1, t2],
+            Self::List(_, ts) => ts.iter().collect(),
+            Self::Let(_, bs, t1) | Self::LetStar(_, bs, t1) => {
+                let mut vec: Vec<&Self> = bs.iter().map(|(_, t)| t).collect();
+                vec.push(t1);
+                vec
+            }
+            Self::LetRec(_, fun_vec, t1) => {
+                let mut vec: Vec<&Self> = fun_vec.iter().map(|fun| &fun.term).collect();
+                vec.push(t1);
+                vec
+            }
+            Self::Cond(_, cs, t1) => {
+                let mut vec: Vec<&Self> = cs.iter().flat_map(|(t2, t3)| [t2, t3]).collect();
+                vec.extend(t1.as_deref());
+                vec
+            }
+            // datums are not evaluated
+            Self::Case(_, t1, cs, t2) => {
+                let mut vec = vec![&**t1];
+                vec.extend(cs.iter().map(|(_, t3)| t3));
+                vec.extend(t2.as_deref());
+                vec
+            }
+            Self::And(_, ts) | Self::Or(_, ts) => ts.iter().collect(),
+            Self::Match(_, t1, cs) => {
+                let mut vec = vec![&**t1];
+                vec.extend(cs.iter().map(|(_, t2)| t2));
+                vec
+            }
+            _ => vec![],
+        }
+    }
+
+    /// This term with its direct subterms replaced by `subterm_vec`, given in the order of `subterms`.
+    pub fn with_subterms(&self, subterm_vec: Vec<Self>) -> Self {
+        let subterm_iter = RefCell::new(subterm_vec.into_iter());
+        self.map_subterm(|_| subterm_iter.borrow_mut().next().unwrap())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MetaPattern {
+    /// `_`, matches anything
+    Wildcard(Option<FileInfo>),
+    /// matches anything and binds it to the name
+    Bind(Option<FileInfo>, String),
+    /// number, rational, bool, string or char, compared by eq
+    Literal(Option<FileInfo>, MetaTerm),
+    Nil(Option<FileInfo>),
+    /// (head, tail)
+    Cons(Option<FileInfo>, Box<MetaPattern>, Box<MetaPattern>),
+    /// list of exactly the length
+    List(Option<FileInfo>, Vec<MetaPattern>),
+}
+
+impl MetaPattern {
+    pub fn file_info(&self) -> &Option<FileInfo> {
+        match self {
+            MetaPattern::Wildcard(info) => info,
+            MetaPattern::Bind(info, _) => info,
+            MetaPattern::Literal(info, _) => info,
+            MetaPattern::Nil(info) => info,
+            MetaPattern::Cons(info, ..) => info,
+            MetaPattern::List(info, _) => info,
+        }
+    }
+
+    /// Names bound by this pattern, from left to right.
+    pub fn binding_names(&self) -> Vec<&String> {
+        match self {
+            MetaPattern::Bind(_, name) => vec![name],
+            MetaPattern::Cons(_, p1, p2) => {
+                let mut vec = p1.binding_names();
+                vec.extend(p2.binding_names());
+                vec
+            }
+            MetaPattern::List(_, ps) => ps.iter().flat_map(|p| p.binding_names()).collect(),
+            _ => vec![],
         }
     }
 }
@@ -125,6 +449,8 @@ pub enum MetaStatement {
     Import(Option<FileInfo>, Vec<String>),
     /// (name)
     Export(Option<FileInfo>, Vec<MetaExport>),
+    /// (name, arg names, body)
+    DefMacro(Option<FileInfo>, String, Vec<String>, MetaTerm),
 }
 
 impl MetaStatement {
@@ -136,6 +462,7 @@ impl MetaStatement {
             MetaStatement::Assert(info, _) => info,
             MetaStatement::Import(info, _) => info,
             MetaStatement::Export(info, _) => info,
+            MetaStatement::DefMacro(info, ..) => info,
         }
     }
 }
@@ -148,12 +475,143 @@ pub enum MetaExport {
 
 #[derive(Clone, Debug)]
 pub struct DefRecFun {
+    /// range from the name to the end of the body, given to the lambda of the function
+    pub info: Option<FileInfo>,
+    /// range of the name, given to the inner lambda taking all functions first
+    pub name_info: Option<FileInfo>,
     pub name: String,
     pub arg_name_vec: Vec<String>,
     pub term: MetaTerm,
 }
 
-pub type MetaEnv = HashMap<String, Term>;
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommentKind {
+    /// `; ..` till the end of line
+    Line,
+    /// `#| .. |#`, which may be nested
+    Block,
+    /// `#;` followed by a datum to be ignored
+    Datum,
+}
+
+/// Comment in source. Not a part of statements, but kept by the parser for formatters and doc tools.
+#[derive(Clone, Debug)]
+pub struct Comment {
+    pub info: Option<FileInfo>,
+    pub kind: CommentKind,
+    /// source text including delimiters
+    pub text: String,
+}
+
+impl Comment {
+    /// Text without delimiters, e.g. `foo` for `;; foo` and ` foo ` for `#| foo |#`.
+    pub fn body(&self) -> &str {
+        match self.kind {
+            CommentKind::Line => {
+                let body = self.text.trim_start_matches(';');
+                body.strip_prefix(' ').unwrap_or(body)
+            }
+            CommentKind::Block => {
+                let body = &self.text[2..];
+                body.strip_suffix("|#").unwrap_or(body)
+            }
+            CommentKind::Datum => self.text[2..].trim_start(),
+        }
+    }
+}
+
+/// What a name in the environment stands for.
+#[derive(Clone, Debug)]
+pub enum MetaBinding {
+    Value(Term),
+    /// lambda taking gensym and the quoted args of a call, and returning the syntax replacing the call
+    Macro(Term),
+}
+
+pub type MetaEnv = HashMap<String, MetaBinding>;
+
+/// Passes all functions first to calls of them, e.g. `(f x)` to `(f f g x)` for functions `f` and `g`.
+fn substitution_rec(mt: &MetaTerm, name_vec: &Vec<&str>, name_set: &HashSet<&str>) -> MetaTerm {
+    if let MetaTerm::Apply(info, t, ts) = mt {
+        if let MetaTerm::Variable(_, v) = &**t {
+            if name_set.contains(v.as_str()) {
+                let mut new_arg_vec: Vec<MetaTerm> = name_vec
+                    .iter()
+                    .map(|name| MetaTerm::Variable(None, name.to_string()))
+                    .collect();
+                new_arg_vec.append(&mut ts.clone());
+                return MetaTerm::Apply(info.clone(), t.clone(), new_arg_vec);
+            }
+        }
+    }
+    mt.map_subterm(|st| substitution_rec(&st, name_vec, name_set))
+}
+
+/// Lambdas of mutually recursive functions, in order of `fun_vec`.
+/// Each function is encoded as an inner lambda taking all functions before its own args,
+/// wrapped by a lambda passing all inner lambdas to it, so no fixed point combinator is needed.
+pub fn transform_rec_functions(info: &Option<FileInfo>, fun_vec: &[DefRecFun]) -> Vec<MetaTerm> {
+    let name_vec: Vec<&str> = fun_vec.iter().map(|fun| fun.name.as_str()).collect();
+    let name_set: HashSet<&str> = fun_vec.iter().map(|fun| fun.name.as_str()).collect();
+    let real_lambda_in_vec: Vec<_> = fun_vec
+        .iter()
+        .map(|fun| {
+            let real_body = substitution_rec(&fun.term, &name_vec, &name_set);
+            let real_arg_name_vec = {
+                let mut new_vec: Vec<String> =
+                    name_vec.clone().iter().map(|e| (*e).into()).collect();
+                new_vec.append(&mut fun.arg_name_vec.clone());
+                new_vec
+            };
+            MetaTerm::Lambda(fun.name_info.clone(), real_arg_name_vec, real_body.into())
+        })
+        .collect();
+
+    fun_vec
+        .iter()
+        .zip(real_lambda_in_vec.iter())
+        .map(|(fun, real_lambda_in)| {
+            // (lambda (<orig_arg>) (real_lambda <real_lambda_vec> <orig_arg>))
+
+            // <real_lambda_vec> <orig_arg>
+            let mut real_operand = real_lambda_in_vec.clone();
+            real_operand.append(
+                &mut fun
+                    .arg_name_vec
+                    .iter()
+                    .map(|v| MetaTerm::Variable(info.clone(), v.clone()))
+                    .collect(),
+            );
+
+            MetaTerm::Lambda(
+                fun.info.clone(),
+                fun.arg_name_vec.clone(),
+                MetaTerm::Apply(info.clone(), real_lambda_in.clone().into(), real_operand).into(),
+            )
+        })
+        .collect()
+}
+
+/// Checks that `name_vec` bound by one `form` has no duplicates.
+fn check_unique_names<'a>(
+    info: &Option<FileInfo>,
+    form: &str,
+    name_vec: impl Iterator<Item = &'a String>,
+) -> Result<(), CompileError> {
+    let mut name_set = HashSet::new();
+    for name in name_vec {
+        if !name_set.insert(name) {
+            return Err(CompileError {
+                info: info.clone(),
+                message: Some(format!(
+                    "Variable name \"{}\" is bound twice in one {}.",
+                    name, form
+                )),
+            });
+        }
+    }
+    Ok(())
+}
 
 /// (De Bruijn index, arg index in lambda)
 type ArgNameMap = HashMap<String, (usize, usize)>;
@@ -164,10 +622,12 @@ fn shift_arg_map(arg_map: ArgNameMap) -> ArgNameMap {
         .collect()
 }
 
+/// quoted: whether `mt` is in quote, where unbound variables are symbols
 fn transform_to_core_internal(
     env: &MetaEnv,
     mt: &MetaTerm,
     arg_map: &ArgNameMap,
+    quoted: bool,
 ) -> Result<Term, CompileError> {
     match mt {
         // Lambda and Variable are most important parts.
@@ -186,7 +646,7 @@ fn transform_to_core_internal(
             Ok(Term::Lambda(
                 info.clone(),
                 args.len(),
-                transform_to_core_internal(env, body, &sub_arg_map)?.into(),
+                transform_to_core_internal(env, body, &sub_arg_map, quoted)?.into(),
             ))
         }
         MetaTerm::Variable(info, var) => {
@@ -195,7 +655,15 @@ fn transform_to_core_internal(
                 None => {
                     // Env var which is not shadowed by local bound variables.
                     match env.get(var) {
-                        Some(mt2) => Ok(mt2.clone()),
+                        Some(MetaBinding::Value(mt2)) => Ok(mt2.clone()),
+                        Some(MetaBinding::Macro(_)) if quoted => {
+                            Ok(Term::Symbol(info.clone(), var.clone()))
+                        }
+                        Some(MetaBinding::Macro(_)) => Err(CompileError {
+                            info: info.clone(),
+                            message: Some(format!("Macro \"{}\" cannot be used as a value.", var)),
+                        }),
+                        None if quoted => Ok(Term::Symbol(info.clone(), var.clone())),
                         None => Err(CompileError {
                             info: info.clone(),
                             message: Some(format!(
@@ -210,11 +678,11 @@ fn transform_to_core_internal(
 
         MetaTerm::Apply(info, t1, ts) => Ok(Term::Apply(
             info.clone(),
-            transform_to_core_internal(env, t1, arg_map)?.into(),
+            transform_to_core_internal(env, t1, arg_map, quoted)?.into(),
             {
                 let cs = ts
                     .iter()
-                    .map(|e| transform_to_core_internal(env, e, arg_map))
+                    .map(|e| transform_to_core_internal(env, e, arg_map, quoted))
                     .collect::<Vec<_>>();
 
                 if let Some(Err(err)) = cs.iter().find(|c| matches!(c, Err(..))) {
@@ -225,29 +693,45 @@ fn transform_to_core_internal(
             }?,
         )),
 
-        MetaTerm::Quote(info, t) => Ok(Term::Quote(
-            info.clone(),
-            transform_to_core_internal(env, t, arg_map)?.into(),
-        )),
+        MetaTerm::Quote(info, t) => match transform_to_core_internal(env, t, arg_map, true)? {
+            // Symbol evaluates to itself, so the quote of it is the symbol.
+            symbol @ Term::Symbol(..) => Ok(symbol),
+            t => Ok(Term::Quote(info.clone(), t.into())),
+        },
+        MetaTerm::Quasiquote(info, t) => transform_quasiquote(env, info, t, arg_map, quoted),
+        MetaTerm::Unquote(info, _) => Err(CompileError {
+            info: info.clone(),
+            message: Some("unquote should be in quasiquote.".into()),
+        }),
+        MetaTerm::UnquoteSplicing(info, _) => Err(CompileError {
+            info: info.clone(),
+            message: Some("unquote-splicing should be in quasiquote.".into()),
+        }),
+        MetaTerm::Hole(info, index, false) => Ok(Term::Unquote(info.clone(), *index)),
+        MetaTerm::Hole(info, index, true) => Ok(Term::UnquoteSplicing(info.clone(), *index)),
         MetaTerm::Eq(info) => Ok(Term::Eq(info.clone())),
+        MetaTerm::BetaEq(info) => Ok(Term::BetaEq(info.clone())),
 
         MetaTerm::If(info, t1, t2, t3) => Ok(Term::If(
             info.clone(),
-            transform_to_core_internal(env, t1, arg_map)?.into(),
-            transform_to_core_internal(env, t2, arg_map)?.into(),
-            transform_to_core_internal(env, t3, arg_map)?.into(),
+            transform_to_core_internal(env, t1, arg_map, quoted)?.into(),
+            transform_to_core_internal(env, t2, arg_map, quoted)?.into(),
+            transform_to_core_internal(env, t3, arg_map, quoted)?.into(),
         )),
 
         // structure
         MetaTerm::Cons(info, t1, t2) => Ok(Term::Cons(
             info.clone(),
-            transform_to_core_internal(env, t1, arg_map)?.into(),
-            transform_to_core_internal(env, t2, arg_map)?.into(),
+            transform_to_core_internal(env, t1, arg_map, quoted)?.into(),
+            transform_to_core_internal(env, t2, arg_map, quoted)?.into(),
         )),
         MetaTerm::Nil(info) => Ok(Term::Nil(info.clone())),
 
         // arith
         MetaTerm::Number(info, n) => Ok(Term::Number(info.clone(), (*n).clone())),
+        MetaTerm::Rational(info, r) => Ok(rational_to_term(info.clone(), (*r).clone())),
+        MetaTerm::String(info, s) => Ok(Term::String(info.clone(), s.clone())),
+        MetaTerm::Char(info, c) => Ok(Term::Char(info.clone(), *c)),
 
         // bool
         MetaTerm::Bool(info, b) => Ok(Term::Bool(info.clone(), *b)),
@@ -261,6 +745,46 @@ fn transform_to_core_internal(
         MetaTerm::Mul(info) => Ok(Term::Mul(info.clone())),
         MetaTerm::Div(info) => Ok(Term::Div(info.clone())),
         MetaTerm::Rem(info) => Ok(Term::Rem(info.clone())),
+        MetaTerm::Monus(info) => Ok(Term::Monus(info.clone())),
+        MetaTerm::Neg(info) => Ok(Term::Neg(info.clone())),
+        MetaTerm::Abs(info) => Ok(Term::Abs(info.clone())),
+        MetaTerm::Numerator(info) => Ok(Term::Numerator(info.clone())),
+        MetaTerm::Denominator(info) => Ok(Term::Denominator(info.clone())),
+        MetaTerm::Lt(info) => Ok(Term::Lt(info.clone())),
+        MetaTerm::Le(info) => Ok(Term::Le(info.clone())),
+        MetaTerm::Gt(info) => Ok(Term::Gt(info.clone())),
+        MetaTerm::Ge(info) => Ok(Term::Ge(info.clone())),
+        MetaTerm::Min(info) => Ok(Term::Min(info.clone())),
+        MetaTerm::Max(info) => Ok(Term::Max(info.clone())),
+        MetaTerm::Pow(info) => Ok(Term::Pow(info.clone())),
+        MetaTerm::ModPow(info) => Ok(Term::ModPow(info.clone())),
+        MetaTerm::Gcd(info) => Ok(Term::Gcd(info.clone())),
+        MetaTerm::Isqrt(info) => Ok(Term::Isqrt(info.clone())),
+        MetaTerm::Shl(info) => Ok(Term::Shl(info.clone())),
+        MetaTerm::Shr(info) => Ok(Term::Shr(info.clone())),
+        MetaTerm::BitAnd(info) => Ok(Term::BitAnd(info.clone())),
+        MetaTerm::BitOr(info) => Ok(Term::BitOr(info.clone())),
+        MetaTerm::BitXor(info) => Ok(Term::BitXor(info.clone())),
+        MetaTerm::StringLength(info) => Ok(Term::StringLength(info.clone())),
+        MetaTerm::StringAppend(info) => Ok(Term::StringAppend(info.clone())),
+        MetaTerm::Substring(info) => Ok(Term::Substring(info.clone())),
+        MetaTerm::CharToInteger(info) => Ok(Term::CharToInteger(info.clone())),
+        MetaTerm::IntegerToChar(info) => Ok(Term::IntegerToChar(info.clone())),
+        MetaTerm::CharToString(info) => Ok(Term::CharToString(info.clone())),
+        MetaTerm::StringToList(info) => Ok(Term::StringToList(info.clone())),
+        MetaTerm::ListToString(info) => Ok(Term::ListToString(info.clone())),
+        MetaTerm::IsSymbol(info) => Ok(Term::IsSymbol(info.clone())),
+        MetaTerm::SymbolToString(info) => Ok(Term::SymbolToString(info.clone())),
+        MetaTerm::StringToSymbol(info) => Ok(Term::StringToSymbol(info.clone())),
+        MetaTerm::IsNumber(info) => Ok(Term::IsNumber(info.clone())),
+        MetaTerm::IsBool(info) => Ok(Term::IsBool(info.clone())),
+        MetaTerm::IsString(info) => Ok(Term::IsString(info.clone())),
+        MetaTerm::IsChar(info) => Ok(Term::IsChar(info.clone())),
+        MetaTerm::IsCons(info) => Ok(Term::IsCons(info.clone())),
+        MetaTerm::IsNil(info) => Ok(Term::IsNil(info.clone())),
+        MetaTerm::IsLambda(info) => Ok(Term::IsLambda(info.clone())),
+        MetaTerm::IsQuote(info) => Ok(Term::IsQuote(info.clone())),
+        MetaTerm::TypeOf(info) => Ok(Term::TypeOf(info.clone())),
 
         // structure op
         MetaTerm::Car(info) => Ok(Term::Car(info.clone())),
@@ -270,27 +794,326 @@ fn transform_to_core_internal(
         MetaTerm::List(info, vec) => Ok(transform_list_construction_to_core_list_internal(
             info.clone(),
             env,
-            &*vec,
+            vec,
             arg_map,
+            quoted,
         )?),
+        // ((lambda (x1 .. xn) body) v1 .. vn)
+        MetaTerm::Let(info, binding_vec, body) => {
+            check_unique_names(info, "let", binding_vec.iter().map(|(name, _)| name))?;
+            let (name_vec, value_vec) = binding_vec.iter().cloned().unzip();
+            let lambda = MetaTerm::Lambda(info.clone(), name_vec, body.clone());
+            transform_to_core_internal(
+                env,
+                &MetaTerm::Apply(info.clone(), lambda.into(), value_vec),
+                arg_map,
+                quoted,
+            )
+        }
+        // (let (b1) (let* (b2 .. bn) body))
+        MetaTerm::LetStar(info, binding_vec, body) => match binding_vec.split_first() {
+            Some((first, rest)) => {
+                let inner = MetaTerm::LetStar(info.clone(), rest.to_vec(), body.clone());
+                let outer = MetaTerm::Let(info.clone(), vec![first.clone()], inner.into());
+                transform_to_core_internal(env, &outer, arg_map, quoted)
+            }
+            None => transform_to_core_internal(env, body, arg_map, quoted),
+        },
+        // ((lambda (f1 .. fn) body) <lambda of f1> .. <lambda of fn>)
+        MetaTerm::LetRec(info, fun_vec, body) => {
+            check_unique_names(info, "letrec", fun_vec.iter().map(|fun| &fun.name))?;
+            let name_vec = fun_vec.iter().map(|fun| fun.name.clone()).collect();
+            let lambda = MetaTerm::Lambda(info.clone(), name_vec, body.clone());
+            transform_to_core_internal(
+                env,
+                &MetaTerm::Apply(
+                    info.clone(),
+                    lambda.into(),
+                    transform_rec_functions(info, fun_vec),
+                ),
+                arg_map,
+                quoted,
+            )
+        }
+        // (if c1 v1 (if c2 v2 .. else))
+        MetaTerm::Cond(info, clause_vec, else_term) => {
+            let mut term = match else_term {
+                Some(t) => transform_to_core_internal(env, t, arg_map, quoted)?,
+                None => Term::Nil(info.clone()),
+            };
+            for (condition, value) in clause_vec.iter().rev() {
+                term = Term::If(
+                    info.clone(),
+                    transform_to_core_internal(env, condition, arg_map, quoted)?.into(),
+                    transform_to_core_internal(env, value, arg_map, quoted)?.into(),
+                    term.into(),
+                );
+            }
+            Ok(term)
+        }
+        // ((lambda (<key>) (if (eq <key> d1) v1 ..)) key)
+        // <key> has no name so that it is not captured by clauses.
+        MetaTerm::Case(info, key, clause_vec, else_term) => {
+            let sub_arg_map = shift_arg_map(arg_map.clone());
+            let key_var = Term::Variable(info.clone(), 0, 0);
+            let mut term = match else_term {
+                Some(t) => transform_to_core_internal(env, t, &sub_arg_map, quoted)?,
+                None => Term::Nil(info.clone()),
+            };
+            for (datum_vec, value) in clause_vec.iter().rev() {
+                let mut eq_vec = Vec::new();
+                for datum in datum_vec {
+                    let datum = match datum {
+                        MetaTerm::Variable(info, name) => Term::Symbol(info.clone(), name.clone()),
+                        _ => transform_to_core_internal(env, datum, &sub_arg_map, quoted)?,
+                    };
+                    eq_vec.push(Term::Apply(
+                        info.clone(),
+                        Term::Eq(info.clone()).into(),
+                        vec![key_var.clone(), datum],
+                    ));
+                }
+                // (if (eq <key> d1) true (eq <key> d2))
+                let condition = match eq_vec.pop() {
+                    Some(last) => eq_vec.into_iter().rev().fold(last, |acc, eq| {
+                        Term::If(
+                            info.clone(),
+                            eq.into(),
+                            Term::Bool(info.clone(), true).into(),
+                            acc.into(),
+                        )
+                    }),
+                    None => continue,
+                };
+                term = Term::If(
+                    info.clone(),
+                    condition.into(),
+                    transform_to_core_internal(env, value, &sub_arg_map, quoted)?.into(),
+                    term.into(),
+                );
+            }
+            Ok(Term::Apply(
+                info.clone(),
+                Term::Lambda(info.clone(), 1, term.into()).into(),
+                vec![transform_to_core_internal(env, key, arg_map, quoted)?],
+            ))
+        }
+        // ((lambda (<key>) (if <p1 matches> ((lambda (<names in p1>) v1) <parts of key>) ..)) key)
+        MetaTerm::Match(info, key, clause_vec) => {
+            let sub_arg_map = shift_arg_map(arg_map.clone());
+            let key_var = Term::Variable(info.clone(), 0, 0);
+            let mut term = Term::Nil(info.clone());
+            for (pattern, value) in clause_vec.iter().rev() {
+                check_unique_names(
+                    pattern.file_info(),
+                    "pattern",
+                    pattern.binding_names().into_iter(),
+                )?;
+                let mut check_vec = Vec::new();
+                let mut binding_vec = Vec::new();
+                transform_pattern(pattern, key_var.clone(), &mut check_vec, &mut binding_vec)?;
+                let value = if binding_vec.is_empty() {
+                    transform_to_core_internal(env, value, &sub_arg_map, quoted)?
+                } else {
+                    let mut value_arg_map = shift_arg_map(sub_arg_map.clone());
+                    for (arg_num, (name, _)) in binding_vec.iter().enumerate() {
+                        value_arg_map.insert((*name).clone(), (0, arg_num));
+                    }
+                    let lambda = Term::Lambda(
+                        pattern.file_info().clone(),
+                        binding_vec.len(),
+                        transform_to_core_internal(env, value, &value_arg_map, quoted)?.into(),
+                    );
+                    let part_vec = binding_vec.into_iter().map(|(_, part)| part).collect();
+                    Term::Apply(info.clone(), lambda.into(), part_vec)
+                };
+                // (if c1 (if c2 .. false) false)
+                let condition = check_vec.into_iter().rev().reduce(|acc, check| {
+                    Term::If(
+                        info.clone(),
+                        check.into(),
+                        acc.into(),
+                        Term::Bool(info.clone(), false).into(),
+                    )
+                });
+                term = match condition {
+                    Some(condition) => {
+                        Term::If(info.clone(), condition.into(), value.into(), term.into())
+                    }
+                    // matches anything
+                    None => value,
+                };
+            }
+            Ok(Term::Apply(
+                info.clone(),
+                Term::Lambda(info.clone(), 1, term.into()).into(),
+                vec![transform_to_core_internal(env, key, arg_map, quoted)?],
+            ))
+        }
+        // (if t1 (if t2 .. false) false)
+        MetaTerm::And(info, ts) => transform_short_circuit(env, info, ts, arg_map, quoted, true),
+        // (if t1 true (if t2 true ..))
+        MetaTerm::Or(info, ts) => transform_short_circuit(env, info, ts, arg_map, quoted, false),
+    }
+}
+
+/// (fill (fill (quote <template>) e0) e1) ..
+fn transform_quasiquote(
+    env: &MetaEnv,
+    info: &Option<FileInfo>,
+    template: &MetaTerm,
+    arg_map: &ArgNameMap,
+    quoted: bool,
+) -> Result<Term, CompileError> {
+    let part_vec = RefCell::new(Vec::new());
+    let template = take_unquoted_parts(template, &part_vec)?;
+    let quote = MetaTerm::Quote(info.clone(), template.into());
+    let mut term = transform_to_core_internal(env, &quote, arg_map, quoted)?;
+    for part in part_vec.into_inner() {
+        term = Term::Apply(
+            info.clone(),
+            Term::Fill(info.clone()).into(),
+            vec![
+                term,
+                transform_to_core_internal(env, &part, arg_map, quoted)?,
+            ],
+        );
+    }
+    Ok(term)
+}
+
+/// Replaces unquoted parts in a quasiquote template by holes indexed in order of `part_vec`.
+fn take_unquoted_parts(
+    mt: &MetaTerm,
+    part_vec: &RefCell<Vec<MetaTerm>>,
+) -> Result<MetaTerm, CompileError> {
+    // unquote-splicing is allowed only here
+    let take_element = |mt: &MetaTerm| match mt {
+        MetaTerm::UnquoteSplicing(info, t) => {
+            let mut part_vec = part_vec.borrow_mut();
+            part_vec.push((**t).clone());
+            Ok(MetaTerm::Hole(info.clone(), part_vec.len() - 1, true))
+        }
+        mt => take_unquoted_parts(mt, part_vec),
+    };
+    match mt {
+        MetaTerm::Unquote(info, t) => {
+            let mut part_vec = part_vec.borrow_mut();
+            part_vec.push((**t).clone());
+            Ok(MetaTerm::Hole(info.clone(), part_vec.len() - 1, false))
+        }
+        MetaTerm::UnquoteSplicing(info, _) => Err(CompileError {
+            info: info.clone(),
+            message: Some(
+                "unquote-splicing should be an arg of an application or an element of a list."
+                    .into(),
+            ),
+        }),
+        MetaTerm::Quasiquote(info, _) => Err(CompileError {
+            info: info.clone(),
+            message: Some("quasiquote cannot be nested.".into()),
+        }),
+        MetaTerm::Apply(info, t1, ts) => Ok(MetaTerm::Apply(
+            info.clone(),
+            take_unquoted_parts(t1, part_vec)?.into(),
+            ts.iter().map(take_element).collect::<Result<_, _>>()?,
+        )),
+        MetaTerm::List(info, ts) => Ok(MetaTerm::List(
+            info.clone(),
+            ts.iter().map(take_element).collect::<Result<_, _>>()?,
+        )),
+        MetaTerm::Cons(info, t1, t2) => Ok(MetaTerm::Cons(
+            info.clone(),
+            take_element(t1)?.into(),
+            take_unquoted_parts(t2, part_vec)?.into(),
+        )),
+        mt => mt.try_map_subterm(|t| take_unquoted_parts(&t, part_vec)),
+    }
+}
+
+/// Collects the conditions for `part` to match `pattern`, in order to be checked,
+/// and the parts bound to names, which are car and cdr of `part`.
+fn transform_pattern<'a>(
+    pattern: &'a MetaPattern,
+    part: Term,
+    check_vec: &mut Vec<Term>,
+    binding_vec: &mut Vec<(&'a String, Term)>,
+) -> Result<(), CompileError> {
+    let info = pattern.file_info();
+    let apply =
+        |operator: Term, arg_vec: Vec<Term>| Term::Apply(info.clone(), operator.into(), arg_vec);
+    match pattern {
+        MetaPattern::Wildcard(_) => {}
+        MetaPattern::Bind(_, name) => binding_vec.push((name, part)),
+        MetaPattern::Literal(_, literal) => {
+            let literal = transform_to_core(&HashMap::new(), literal)?;
+            check_vec.push(apply(Term::Eq(info.clone()), vec![part, literal]));
+        }
+        MetaPattern::Nil(_) => check_vec.push(apply(Term::IsNil(info.clone()), vec![part])),
+        MetaPattern::Cons(_, p1, p2) => {
+            check_vec.push(apply(Term::IsCons(info.clone()), vec![part.clone()]));
+            let car = apply(Term::Car(info.clone()), vec![part.clone()]);
+            transform_pattern(p1, car, check_vec, binding_vec)?;
+            let cdr = apply(Term::Cdr(info.clone()), vec![part]);
+            transform_pattern(p2, cdr, check_vec, binding_vec)?;
+        }
+        MetaPattern::List(_, ps) => {
+            let mut part = part;
+            for p in ps {
+                check_vec.push(apply(Term::IsCons(info.clone()), vec![part.clone()]));
+                let car = apply(Term::Car(info.clone()), vec![part.clone()]);
+                transform_pattern(p, car, check_vec, binding_vec)?;
+                part = apply(Term::Cdr(info.clone()), vec![part]);
+            }
+            check_vec.push(apply(Term::IsNil(info.clone()), vec![part]));
+        }
+    }
+    Ok(())
+}
+
+/// and: whether to transform `and` rather than `or`
+fn transform_short_circuit(
+    env: &MetaEnv,
+    info: &Option<FileInfo>,
+    ts: &[MetaTerm],
+    arg_map: &ArgNameMap,
+    quoted: bool,
+    and: bool,
+) -> Result<Term, CompileError> {
+    let (last, init) = match ts.split_last() {
+        Some(split) => split,
+        None => return Ok(Term::Bool(info.clone(), and)),
+    };
+    let mut term = transform_to_core_internal(env, last, arg_map, quoted)?;
+    for t in init.iter().rev() {
+        let t = transform_to_core_internal(env, t, arg_map, quoted)?.into();
+        let short = Term::Bool(info.clone(), !and).into();
+        term = if and {
+            Term::If(info.clone(), t, term.into(), short)
+        } else {
+            Term::If(info.clone(), t, short, term.into())
+        };
     }
+    Ok(term)
 }
 
 fn transform_list_construction_to_core_list_internal(
     info: Option<FileInfo>,
     env: &MetaEnv,
-    vec: &Vec<MetaTerm>,
+    vec: &[MetaTerm],
     arg_map: &ArgNameMap,
+    quoted: bool,
 ) -> Result<Term, CompileError> {
     Ok(if !vec.is_empty() {
         Term::Cons(
             info.clone(),
-            transform_to_core_internal(env, &vec[0], arg_map)?.into(),
+            transform_to_core_internal(env, &vec[0], arg_map, quoted)?.into(),
             transform_list_construction_to_core_list_internal(
                 info,
                 env,
-                &vec.clone().into_iter().skip(1).collect(),
+                &vec[1..],
                 arg_map,
+                quoted,
             )?
             .into(),
         )
@@ -300,5 +1123,5 @@ fn transform_list_construction_to_core_list_internal(
 }
 
 pub fn transform_to_core(env: &MetaEnv, mt: &MetaTerm) -> Result<Term, CompileError> {
-    transform_to_core_internal(env, mt, &HashMap::new())
+    transform_to_core_internal(env, mt, &HashMap::new(), false)
 }
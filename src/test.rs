@@ -1,16 +1,27 @@
-use crate::common::fileinfo::CompileError;
+use crate::action::debug::debug_source;
+use crate::action::expand::expand_source;
+use crate::action::repl::{is_incomplete, Repl};
+use crate::corelang::eval::{EvalError, EvalLimit, EvalStrategy, Limit};
+use crate::corelang::normalize::normalize;
 use crate::corelang::printer::simple::SimplePrinter;
+use crate::corelang::syntax::Term;
+use crate::corelang::trace::{PrintTracer, Rule, TraceFormat, TraceOption, TraceStep, Tracer};
 use crate::metalang::eval::MetaEvaluator;
 use crate::metalang::parser::MetaParser;
-use crate::metalang::syntax::MetaStatement;
+use crate::metalang::printer::simple::SimplePrinter as MetaPrinter;
+use crate::metalang::syntax::{transform_to_core, CommentKind, MetaStatement};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
 
-fn parse_string(str: String) -> Result<Vec<MetaStatement>, CompileError> {
+fn parse_string(str: String) -> Result<Vec<MetaStatement>, EvalError> {
     let mut p = MetaParser::new("<test>".into(), str.chars().collect());
-    p.parse_stmt_vec()
+    p.parse_stmt_vec().map_err(EvalError::Parse)
 }
 
-fn parse_eval_print(str: String) -> Result<String, CompileError> {
+fn parse_eval_print(str: String, strategy: EvalStrategy) -> Result<String, EvalError> {
     let mut evaluator = MetaEvaluator::default();
+    evaluator.strategy = strategy;
     let vs = evaluator.eval_vec(&parse_string(str)?)?;
     Ok(vs
         .into_iter()
@@ -22,17 +33,21 @@ fn parse_eval_print(str: String) -> Result<String, CompileError> {
         .join("\n"))
 }
 
-fn test_success(source: &str, want: &str) {
-    let got = match parse_eval_print(source.to_string()) {
+fn test_success_with(strategy: EvalStrategy, source: &str, want: &str) {
+    let got = match parse_eval_print(source.to_string(), strategy) {
         Ok(out) => out,
         Err(err) => format!("ERROR: {}", err),
     };
-    assert_eq!(want, got, "\nsource:{}", source);
+    assert_eq!(want, got, "\nstrategy: {:?}\nsource:{}", strategy, source);
+}
+
+fn test_success(source: &str, want: &str) {
+    test_success_with(EvalStrategy::default(), source, want);
 }
 
 #[test]
 fn test() {
-    test_success("(- 2 4)", "0");
+    test_success("(- 2 4)", "-2");
     test_success("(- 4 2)", "2");
     test_success("(+ 2 4)", "6");
     test_success("(* 2 4)", "8");
@@ -47,6 +62,16 @@ fn test() {
     test_success("((lambda (n) (+ 1 n)) 4)", "5");
     test_success("((lambda (n) (quote (+ 1 n))) 4)", "(quote (+ 1 4))");
     test_success("(eval ((lambda (n) (quote (+ 1 n))) 4))", "5");
+    test_success(
+        "((lambda (n) (quote (+ 1 n))) (+ 2 2))",
+        "(quote (+ 1 (+ 2 2)))",
+    );
+    test_success("((lambda (x) (lambda (y) x)) 5)", "(lambda<1-ary> 5)");
+    test_success(
+        "((lambda (x) (lambda (y) (x y))) (lambda (z) z))",
+        "(lambda<1-ary> ((lambda<1-ary> ARG<0-up 0-th>) ARG<0-up 0-th>))",
+    );
+    test_success("(eq ((lambda (x) (lambda (y) x)) 1) (lambda (y) 1))", "true");
     test_success("((lambda (n) (+ ((lambda (n) n) 10) n)) 4)", "14");
     test_success("(list)", "nil");
     test_success(
@@ -85,6 +110,25 @@ fn test() {
     test_success("(import \"std\") (and true false)", "false");
     test_success("(import \"std\") (and false true)", "false");
     test_success("(import \"std\") (and true true)", "true");
+    // the calls above are taken by the special form, and the functions of std are named and2, or2
+    test_success("(import \"std\") (and2 false false)", "false");
+    test_success("(import \"std\") (and2 true false)", "false");
+    test_success("(import \"std\") (and2 false true)", "false");
+    test_success("(import \"std\") (and2 true true)", "true");
+    // the special forms are not shadowed by std, and cannot be redefined
+    test_success("(import \"std\") (and true true false)", "false");
+    test_success(
+        "(import \"std\") ((lambda (f) (f true false)) and2)",
+        "false",
+    );
+    test_success(
+        "(def and 1)",
+        "ERROR: <test>: (line 1 col 6-line 1 col 8): reserved name \"and\" cannot be bound",
+    );
+    test_success(
+        "(lambda (or) or)",
+        "ERROR: <test>: (line 1 col 10-line 1 col 11): reserved name \"or\" cannot be bound",
+    );
 
     test_success("(import \"std\") (nand false false)", "true");
     test_success("(import \"std\") (nand true false)", "true");
@@ -95,6 +139,10 @@ fn test() {
     test_success("(import \"std\") (or true false)", "true");
     test_success("(import \"std\") (or false true)", "true");
     test_success("(import \"std\") (or true true)", "true");
+    test_success("(import \"std\") (or2 false false)", "false");
+    test_success("(import \"std\") (or2 true false)", "true");
+    test_success("(import \"std\") (or2 false true)", "true");
+    test_success("(import \"std\") (or2 true true)", "true");
 
     test_success("(import \"std\") (nor false false)", "true");
     test_success("(import \"std\") (nor true false)", "false");
@@ -111,3 +159,1277 @@ fn test() {
     test_success("(import \"std\") (nxor false true)", "false");
     test_success("(import \"std\") (nxor true true)", "true");
 }
+
+fn test_limit_exceeded(source: &str, limit: EvalLimit, want: Limit) {
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.limit = limit;
+    let got = parse_string(source.to_string()).and_then(|stmt_vec| evaluator.eval_vec(&stmt_vec));
+    match got {
+        Err(EvalError::FuelExhausted(_, got)) => assert_eq!(want, got, "\nsource:{}", source),
+        _ => panic!("expected fuel exhausted: {:?}\nsource:{}", got, source),
+    }
+}
+
+#[test]
+fn test_signed() {
+    test_success("-5", "-5");
+    test_success("(+ -5 3)", "-2");
+    test_success("(- 3 -5)", "8");
+    test_success("(neg 7)", "-7");
+    test_success("(neg -7)", "7");
+    test_success("(abs -7)", "7");
+    test_success("(abs 7)", "7");
+    // quotient is rounded down, and remainder has the sign of the divisor
+    test_success("(/ 7 2)", "3");
+    test_success("(/ -7 2)", "-4");
+    test_success("(/ 7 -2)", "-4");
+    test_success("(/ -7 -2)", "3");
+    test_success("(% 7 2)", "1");
+    test_success("(% -7 2)", "1");
+    test_success("(% 7 -2)", "-1");
+    test_success("(% -7 -2)", "-1");
+    test_success("(monus 4 2)", "2");
+    test_success("(monus 2 4)", "0");
+    test_success("((lambda (f x) (f x)) neg 3)", "-3");
+    test_success(
+        "(neg 1 2)",
+        "ERROR: <test>: (line 1 col 2-line 1 col 4): neg operator only accepts 1 number.",
+    );
+    test_success(
+        "(import \"std/arith\") (< -3 -2) (<= -2 -2) (> -3 2)",
+        "true\ntrue\nfalse",
+    );
+}
+
+#[test]
+fn test_rational() {
+    test_success("3/4", "3/4");
+    test_success("-6/8", "-3/4");
+    test_success("4/2", "2");
+    test_success("(+ 1/2 1/3)", "5/6");
+    test_success("(+ 1/2 1/2)", "1");
+    test_success("(- 1 1/3)", "2/3");
+    test_success("(* 2/3 3)", "2");
+    test_success("(/ 1/2 3)", "1/6");
+    test_success("(/ 7 2)", "3");
+    test_success("(monus 1/3 1/2)", "0");
+    test_success("(neg 1/2)", "-1/2");
+    test_success("(abs -1/2)", "1/2");
+    test_success("(numerator -6/8)", "-3");
+    test_success("(denominator -6/8)", "4");
+    test_success("(denominator 5)", "1");
+    test_success("(eq 2/4 1/2)", "true");
+    test_success("(eq (* 1/2 2) 1)", "true");
+    test_success("(import \"std/arith\") (< 1/3 1/2)", "true");
+    test_success(
+        "(/ 1/2 0)",
+        "ERROR: <test>: (line 1 col 8): division operator got 0 for divisor.",
+    );
+    test_success(
+        "(% 1/2 1)",
+        "ERROR: <test>: (line 1 col 2): remainder operator only accepts 2 integers.",
+    );
+    test_success(
+        "1/0",
+        "ERROR: <test>: (line 1 col 3): denominator of rational literal is 0",
+    );
+}
+
+#[test]
+fn test_comparison() {
+    test_success("(< 1 2)", "true");
+    test_success("(< 2 2)", "false");
+    test_success("(<= 2 2)", "true");
+    test_success("(> -1 -2)", "true");
+    test_success("(>= -2 -1)", "false");
+    test_success("(< 1/3 1/2)", "true");
+    test_success("(>= 1/2 1)", "false");
+    test_success("(min 3 -4)", "-4");
+    test_success("(max 3 -4)", "3");
+    test_success("(max 1/2 1/3)", "1/2");
+    test_success("(min 1 3/2)", "1");
+    test_success("((lambda (op) (op 1 2)) <)", "true");
+    test_success(
+        "(< 1 true)",
+        "ERROR: <test>: (line 1 col 2): comparison operator only accepts 2 numbers.",
+    );
+}
+
+#[test]
+fn test_integer() {
+    test_success("(pow 2 100)", "1267650600228229401496703205376");
+    test_success("(pow -3 3)", "-27");
+    test_success("(pow 0 0)", "1");
+    test_success("(modpow 3 200 7)", "2");
+    test_success("(modpow -2 3 5)", "2");
+    test_success("(gcd 12 -18)", "6");
+    test_success("(gcd 0 0)", "0");
+    test_success("(isqrt 99)", "9");
+    test_success("(isqrt 100000000000000000000)", "10000000000");
+    test_success("(shl 3 4)", "48");
+    test_success("(shr -7 1)", "-4");
+    test_success("(bit-and 12 -4)", "12");
+    test_success("(bit-or 12 3)", "15");
+    test_success("(bit-xor -1 5)", "-6");
+    test_success(
+        "(pow 2 -1)",
+        "ERROR: <test>: (line 1 col 8-line 1 col 9): pow operator got exponent out of 0..2^32.",
+    );
+    // results too large to allocate are rejected, unless the base is -1, 0 or 1
+    test_success(
+        "(pow 2 4000000000)",
+        "ERROR: <test>: (line 1 col 8-line 1 col 17): pow operator got exponent making too large integer.",
+    );
+    test_success("(pow -1 4000000001)", "-1");
+    test_success(
+        "(shl 1 1000000000000)",
+        "ERROR: <test>: (line 1 col 8-line 1 col 20): shift operator got shift making too large integer.",
+    );
+    test_success("(shl 0 1000000000000)", "0");
+    test_success("(shr (shl 1 1000000) 1000000)", "1");
+    test_success(
+        "(modpow 2 3 0)",
+        "ERROR: <test>: (line 1 col 13): modpow operator got 0 for modulus.",
+    );
+    test_success(
+        "(isqrt -4)",
+        "ERROR: <test>: (line 1 col 8-line 1 col 9): isqrt operator got negative number.",
+    );
+    test_success(
+        "(modpow 2 3)",
+        "ERROR: <test>: (line 1 col 2-line 1 col 7): modpow operator only accepts 3 integers.",
+    );
+    test_success(
+        "(gcd 1/2 2)",
+        "ERROR: <test>: (line 1 col 2-line 1 col 4): gcd operator only accepts 2 integers.",
+    );
+}
+
+#[test]
+fn test_number_literal() {
+    test_success(
+        "(list 0xff 0o17 0b1010 -0x10)",
+        "(cons 255 (cons 15 (cons 10 (cons -16 nil))))",
+    );
+    test_success("(+ 1_000_000 0xdead_BEEF)", "3736928559");
+    test_success("1_0/2_0", "1/2");
+    test_success(
+        "(+ 0x 1)",
+        "ERROR: <test>: (line 1 col 4-line 1 col 5): hexadecimal literal has no digits: found ' '",
+    );
+    test_success(
+        "(+ 0b102 1)",
+        "ERROR: <test>: (line 1 col 8): invalid digit '2' in binary literal",
+    );
+    test_success(
+        "(+ 12abc 1)",
+        "ERROR: <test>: (line 1 col 6): invalid digit 'a' in decimal literal",
+    );
+    test_success(
+        "1__000",
+        "ERROR: <test>: (line 1 col 2): separator '_' must be between digits",
+    );
+    test_success(
+        "0x_ff",
+        "ERROR: <test>: (line 1 col 3): separator '_' must be between digits",
+    );
+    test_success(
+        "(+ 1_ 2)",
+        "ERROR: <test>: (line 1 col 5): separator '_' must be between digits",
+    );
+    // only a whole token is a number, and the rest are names
+    test_success("(def -1x 3) (def 1+ (lambda (n) (+ n 1))) (1+ -1x)", "4");
+    test_success(
+        "(+ 1/2+ 1)",
+        "ERROR: <test>: (line 1 col 4-line 1 col 7): Variable name \"1/2+\" is not defined variable.",
+    );
+    test_success(
+        "(lambda (1) 1)",
+        "ERROR: <test>: (line 1 col 10): identifier expected: found \"'1'\"",
+    );
+}
+
+#[test]
+fn test_let() {
+    test_success("(let ((x 1) (y 2)) (+ x y))", "3");
+    // values of let cannot see the names bound by the same let, but let* can
+    test_success("(let ((x 1)) (let ((x 2) (y x)) y))", "1");
+    test_success("(let ((x 1)) (let* ((x 2) (y x)) y))", "2");
+    test_success("(let* () 1)", "1");
+    test_success(
+        "(let ((n 10)) (letrec ((f (x) (if (eq x 0) n (f (- x 1))))) (f 3)))",
+        "10",
+    );
+    let even_odd = "(letrec ((even? (n) (if (eq n 0) true (odd? (- n 1))))
+                         (odd? (n) (if (eq n 0) false (even? (- n 1)))))";
+    test_success(&format!("{} (even? 10))", even_odd), "true");
+    test_success(&format!("{} (odd? 7))", even_odd), "true");
+    test_success(&format!("{} (even? 3))", even_odd), "false");
+    test_success(
+        "(let ((x 1) (x 2)) x)",
+        "ERROR: <test>: (line 1 col 1-line 1 col 21): Variable name \"x\" is bound twice in one let.",
+    );
+    test_success(
+        "(letrec ((f () 1) (f () 2)) (f))",
+        "ERROR: <test>: (line 1 col 1-line 1 col 32): Variable name \"f\" is bound twice in one letrec.",
+    );
+    test_success(
+        "(let (x 1) x)",
+        "ERROR: <test>: (line 1 col 7): let binding: expect start parenthesis: found 'x'",
+    );
+    let source = "(let ((a 1)) (let* ((b a) (c b)) (letrec ((f (x) (f x)) (g () 0)) c)))";
+    let stmt = &parse_string(source.into()).unwrap()[0];
+    assert_eq!(MetaPrinter::default().print_stmt(stmt), source);
+}
+
+#[test]
+fn test_cond() {
+    let sign = "(def sign (lambda (n) (cond ((< n 0) -1) ((eq n 0) 0) (else 1))))";
+    test_success(&format!("{} (sign -5) (sign 0) (sign 7)", sign), "-1\n0\n1");
+    test_success("(cond ((eq 1 2) 3))", "nil");
+    test_success("(cond (else 3))", "3");
+    test_success(
+        "(cond (else 1) (true 2))",
+        "ERROR: <test>: (line 1 col 16): else clause of cond should be the last",
+    );
+    let kind = "(def kind (lambda (x)
+        (case x ((0 1) (quote bit)) ((true false) (quote bool)) ((foo bar) 2) (else 3))))";
+    test_success(
+        &format!("{} (kind 1) (kind false) (kind (quote bar)) (kind 5)", kind),
+        "bit\nbool\n2\n3",
+    );
+    // the key is not captured by clauses
+    test_success("((lambda (x) (case (+ x 1) ((2) x))) 1)", "1");
+    test_success("(case 3 ((1) 2))", "nil");
+    test_success(
+        "(case 1 ((car) 2))",
+        "ERROR: <test>: (line 1 col 11-line 1 col 13): case datum should be a literal or symbol",
+    );
+    // short circuit, the other sides would be type errors
+    test_success("(and true false (+ 1 nil))", "false");
+    test_success("(or false true (+ 1 nil))", "true");
+    test_success(
+        "(list (and) (or) (and true) (or false))",
+        "(cons true (cons false (cons true (cons false nil))))",
+    );
+    test_success("(and true (eq 1 1) (< 1 2))", "true");
+    test_success("(or false (eq 1 2) (< 2 1))", "false");
+    // recursive calls in the new forms are passed the functions by defrec
+    test_success(
+        "(defrec collatz (n steps) (cond ((eq n 1) steps) ((eq (% n 2) 0) (collatz (/ n 2) (+ steps 1))) (else (collatz (+ (* 3 n) 1) (+ steps 1))))) (collatz 27 0)",
+        "111",
+    );
+    let source = "(cond ((and a (or b c)) (case d ((1 x) 2) (else 3))) (else (and)))";
+    let stmt = &parse_string(source.into()).unwrap()[0];
+    assert_eq!(MetaPrinter::default().print_stmt(stmt), source);
+}
+
+#[test]
+fn test_match() {
+    let len = "(defrec len (xs) (match xs (nil 0) ((cons _ t) (+ 1 (len t)))))";
+    test_success(&format!("{} (len (list 1 2 3))", len), "3");
+    let describe = "(def describe (lambda (x) (match x
+        (0 (quote zero))
+        (true (quote yes))
+        (\"s\" (quote string))
+        ((list) (quote empty))
+        ((list a) a)
+        ((cons a (cons 1 _)) (+ a 100))
+        ((cons (cons a b) c) (+ a (+ b c)))
+        (_ (quote other)))))";
+    test_success(
+        &format!(
+            "{} (describe 0) (describe true) (describe \"s\") (describe nil) (describe (list 7))",
+            describe
+        ),
+        "zero\nyes\nstring\nempty\n7",
+    );
+    test_success(
+        &format!(
+            "{} (describe (list 2 1 0)) (describe (cons (cons 1 2) 3)) (describe (list 1 2)) (describe 1)",
+            describe
+        ),
+        "102\n6\nother\nother",
+    );
+    // nil if no pattern matches
+    test_success("(match 1 (2 3))", "nil");
+    // the key is evaluated once, and not captured by clauses
+    test_success("((lambda (x) (match (+ x 1) (2 x))) 1)", "1");
+    test_success(
+        "(match (list 1 2) ((list x x) x))",
+        "ERROR: <test>: (line 1 col 20-line 1 col 29): Variable name \"x\" is bound twice in one pattern.",
+    );
+    test_success(
+        "(match 1 ((foo x) x))",
+        "ERROR: <test>: (line 1 col 11-line 1 col 15): pattern should be cons, list, nil, a literal, _ or a variable name",
+    );
+    let source = "(match xs (nil 0) ((cons _ (list x 1 true)) x) (y y))";
+    let stmt = &parse_string(source.into()).unwrap()[0];
+    assert_eq!(MetaPrinter::default().print_stmt(stmt), source);
+}
+
+#[test]
+fn test_macro() {
+    let swap = "(defmacro swap (a b) (list (quote cons) b a))";
+    test_success(&format!("{} (car (swap 1 (+ 1 1)))", swap), "(+ 1 1)");
+    // local names shadow macros, and quoted calls are not expanded
+    test_success(&format!("{} ((lambda (swap) (swap 1 2)) -)", swap), "-1");
+    test_success(&format!("{} (let ((swap +)) (swap 1 2))", swap), "3");
+    test_success(
+        &format!("{} (quote (swap 1 2))", swap),
+        "(quote (swap 1 2))",
+    );
+    // macros made by macros, and calls in the syntax made
+    test_success(
+        "(defmacro unless (c t) (list (quote if) c nil t))
+        (defmacro unless-zero (n t) (list (quote unless) (list (quote eq) n 0) t))
+        (unless-zero 1 (unless false 5))",
+        "5",
+    );
+    // helpers defined before the macro run at transform time
+    test_success(
+        "(def rev (lambda (xs) (cons (car (cdr xs)) (cons (car xs) nil))))
+        (defmacro flip (call) (cons (car call) (rev (cdr call))))
+        (flip (- 1 10))",
+        "9",
+    );
+    // gensym keeps the name made by the macro from capturing the name of args
+    test_success(
+        "(defmacro my-or (a b) (let ((tmp (gensym (quote tmp))))
+            (list (quote let) (list (list tmp a)) (list (quote if) tmp tmp b))))
+        (def tmp 5)
+        (my-or false tmp)",
+        "5",
+    );
+    test_success(
+        "(import \"std\") (when (< 1 2) 3) (unless (< 1 2) 3)",
+        "3\nnil",
+    );
+    // names made by gensym cannot be written in source, and differ in every expansion
+    let sym = "(defmacro sym (x) (list (quote quote) (gensym x)))";
+    test_success(&format!("{} (sym a) (sym a)", sym), "a'1\na'2");
+    test_success(
+        &format!("{} (eq (sym a) (quote a'1))", sym),
+        "ERROR: <test>: (line 1 col 64-line 1 col 74): quote only accepts 1 term",
+    );
+    test_success(
+        r#"(string->symbol "a'1")"#,
+        "ERROR: <test>: (line 1 col 17-line 1 col 21): string->symbol operator got a string not of a name.",
+    );
+    // counted over the expansions in imported files, and kept by reset
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.do_assert = true;
+    for (source, want) in [
+        (format!("(import \"std/control\") {} (sym a)", sym), "a'5"),
+        (format!("{} (sym a)", sym), "a'6"),
+    ] {
+        let value_vec = evaluator.eval_vec(&parse_string(source).unwrap()).unwrap();
+        assert_eq!(SimplePrinter::default().print(&value_vec[0]), want);
+        evaluator.reset();
+    }
+    test_success(
+        "(defmacro m (a gensym) a)",
+        "ERROR: <test>: (line 1 col 13-line 1 col 22): defmacro args cannot have \"gensym\", which is bound for the macro",
+    );
+    test_success(
+        &format!("{} (swap 1)", swap),
+        "ERROR: <test>: (line 1 col 47-line 1 col 54): Macro \"swap\" needs 2 args but found 1.",
+    );
+    test_success(
+        &format!("{} swap", swap),
+        "ERROR: <test>: (line 1 col 47-line 1 col 50): Macro \"swap\" cannot be used as a value.",
+    );
+    test_success("(defmacro f (x) (list (quote /) 3/2 x)) (f 3)", "1/2");
+    test_success(
+        "(defmacro f (x) (list (quote if) true (list (quote f) x) 1)) (f 1)",
+        "ERROR: <test>: (line 1 col 62-line 1 col 66): Macro \"f\" is expanded more than 64 times in a row.",
+    );
+    test_success(
+        "(defmacro f (x) (list (quote f) x)) (f 1)",
+        "ERROR: <test>: (line 1 col 37-line 1 col 41): Macro \"f\" is expanded more than 64 times in a row.",
+    );
+    test_success(
+        "(defmacro f (x) (lambda (y) y)) (f 1)",
+        "ERROR: <test>: (line 1 col 33-line 1 col 37): Macro \"f\" should return syntax made of lists, symbols and literals: found (lambda<1-ary> ARG<0-up 0-th>)",
+    );
+    // errors in the syntax made point at the call
+    test_success(
+        "(defmacro f (x) (list (quote if) x)) (f 1)",
+        "ERROR: <test>: (line 1 col 38-line 1 col 42): if only accepts 3 terms",
+    );
+    let source = "(defmacro when (c t) (list (quote if) c t nil))";
+    let stmt = &parse_string(source.into()).unwrap()[0];
+    assert_eq!(MetaPrinter::default().print_stmt(stmt), source);
+    assert_eq!(
+        expand_source(
+            "<test>".into(),
+            &format!("{} (def x (swap 1 (swap 2 3))) (quote (swap 1 2))", swap),
+            EvalLimit::default(),
+            EvalStrategy::default()
+        )
+        .unwrap(),
+        vec![swap, "(def x (cons (cons 3 2) 1))", "(quote (swap 1 2))"]
+    );
+}
+
+#[test]
+fn test_quasiquote() {
+    test_success("(def x 3) (eval `(+ 1 ,x))", "4");
+    test_success("(eval `(+ ,@(list 1 2)))", "3");
+    test_success("'(a b)", "(quote (a b))");
+    test_success("`(a ,@(list 1 2) ,@nil b)", "(quote (a 1 2 b))");
+    test_success("`(list ,@(list 1 2))", "(quote (cons 1 (cons 2 nil)))");
+    // a quote fills the hole with its code, and unquoted parts see names outside the template
+    test_success(
+        "(def code '(+ 1 2)) `((lambda (y) (* y ,code)) 2)",
+        "(quote ((lambda<1-ary> (* ARG<0-up 0-th> (+ 1 2))) 2))",
+    );
+    test_success(
+        "(def add (lambda (n) `(lambda (y) (+ y ,n)))) ((eval (add 5)) 1)",
+        "6",
+    );
+    test_success("(let ((x 1) (y 2)) (eval `(let ((y ,y)) (- ,x y))))", "-1");
+    test_success(
+        "(quasiquote (a (unquote (+ 1 1)) (unquote-splicing (list 3))))",
+        "(quote (a 2 3))",
+    );
+    // syntax in the template is not expanded, but unquoted parts are
+    test_success(
+        "(defmacro swap (a b) (list (quote cons) b a)) `(swap ,(car (swap 1 2)))",
+        "(quote (swap 2))",
+    );
+    test_success(
+        "(def x 1) '(a ,x)",
+        "ERROR: <test>: (line 1 col 15-line 1 col 16): unquote should be in quasiquote.",
+    );
+    test_success(
+        "`(if ,@(list true) 1 2)",
+        "ERROR: <test>: (line 1 col 6-line 1 col 19): unquote-splicing should be an arg of an application or an element of a list.",
+    );
+    test_success(
+        "`(a `(b ,c))",
+        "ERROR: <test>: (line 1 col 5-line 1 col 11): quasiquote cannot be nested.",
+    );
+    test_success(
+        "`(a ,@3)",
+        "ERROR: <test>: (line 1 col 1-line 1 col 8): unquote-splicing expects a list",
+    );
+    test_normalize_success("`(+ ,@(list 1 2) ,(+ 1 2))", false, "(quote (+ 1 2 3))");
+    // the argument may be put under lambdas of the template
+    test_normalize_success(
+        "(lambda (x) `(lambda (y) ,x))",
+        false,
+        "(lambda<1-ary> (fill (quote (lambda<1-ary> UNQUOTE<0>)) ARG<0-up 0-th>))",
+    );
+
+    let source = "(def t `(a ,b ,@c 'd))";
+    let stmt = &parse_string(source.into()).unwrap()[0];
+    assert_eq!(
+        MetaPrinter::default().print_stmt(stmt),
+        "(def t (quasiquote (a (unquote b) (unquote-splicing c) (quote d))))"
+    );
+}
+
+#[test]
+fn test_string() {
+    test_success(r#""hello""#, r#""hello""#);
+    test_success(r#""a\"b\\c\n\t\u{3bb}\u{7f}""#, r#""a\"b\\c\n\tλ\u{7f}""#);
+    test_success(
+        r"(list #\a #\space #\newline #\( #\u{3bb})",
+        r"(cons #\a (cons #\space (cons #\newline (cons #\( (cons #\λ nil)))))",
+    );
+    test_success(r#"(string-length "λx.x")"#, "4");
+    test_success(r#"(string-append "foo" "bar")"#, r#""foobar""#);
+    test_success(r#"(substring "hello" 1 3)"#, r#""el""#);
+    test_success(r"(char->integer #\A)", "65");
+    test_success("(integer->char 955)", r"#\λ");
+    test_success(r"(char->string #\a)", r#""a""#);
+    test_success(r#"(string->list "ab")"#, r"(cons #\a (cons #\b nil))");
+    test_success(r#"(eq (string->list "ab") (list #\a #\b))"#, "true");
+    test_success(r#"(car (cdr (string->list "ab")))"#, r"#\b");
+    test_success(r#"(string->list "")"#, "nil");
+    let long = "a".repeat(50000);
+    test_success(&format!(r#"(nil? (string->list "{}"))"#, long), "false");
+    test_success_with(
+        EvalStrategy::CallByValue,
+        &format!(
+            r#"(defrec len (xs n) (if (nil? xs) n (len (cdr xs) (+ n 1)))) (len (string->list "{}") 0)"#,
+            long
+        ),
+        "50000",
+    );
+    test_success(r#"(list->string (string->list "λ\"x"))"#, r#""λ\"x""#);
+    test_success(r"(list->string (list #\a (car (cons #\b nil))))", r#""ab""#);
+    test_success(r#"(eq "ab" (string-append "a" "b"))"#, "true");
+    test_success(
+        r#"(substring "abc" 2 1)"#,
+        "ERROR: <test>: (line 1 col 20): substring operator got index out of range.",
+    );
+    test_success(
+        "(integer->char 55296)",
+        "ERROR: <test>: (line 1 col 16-line 1 col 20): integer->char operator got invalid code point.",
+    );
+    test_success(
+        r#"(string-length 1)"#,
+        "ERROR: <test>: (line 1 col 2-line 1 col 14): string-length operator only accepts 1 string.",
+    );
+    test_success(
+        r#""\q""#,
+        r#"ERROR: <test>: (line 1 col 4): unknown string escape sequence "\q""#,
+    );
+    test_success(
+        r#""abc"#,
+        "ERROR: <test>: (line 1 col 5): expected string ending double quotation: found <EOF>",
+    );
+}
+
+#[test]
+fn test_symbol() {
+    test_success("(quote foo)", "foo");
+    test_success("(symbol? (quote foo))", "true");
+    test_success(r#"(symbol? "foo")"#, "false");
+    test_success("(symbol? (lambda (x) x))", "false");
+    test_success("(eq (quote foo) (quote foo))", "true");
+    test_success("(eq (quote foo) (quote bar))", "false");
+    test_success("(symbol->string (quote foo))", r#""foo""#);
+    test_success(r#"(eq (string->symbol "foo") (quote foo))"#, "true");
+    // only names, so that symbols read back
+    test_success(
+        r#"(string->symbol "a b")"#,
+        "ERROR: <test>: (line 1 col 17-line 1 col 21): string->symbol operator got a string not of a name.",
+    );
+    test_success(
+        r#"(string->symbol "12")"#,
+        "ERROR: <test>: (line 1 col 17-line 1 col 20): string->symbol operator got a string not of a name.",
+    );
+    // bound variables in quote are not symbols
+    test_success("(def x 1) (quote (cons x y))", "(quote (cons 1 y))");
+    test_success("(car (eval (quote (list + a))))", "+");
+    test_success("(cdr (eval (quote (cons + a))))", "a");
+    test_success(
+        "foo",
+        r#"ERROR: <test>: (line 1 col 1-line 1 col 3): Variable name "foo" is not defined variable."#,
+    );
+    // symbolic differentiation of sums and products by x, forcing the parts taken from lists
+    test_success_with(
+        EvalStrategy::CallByNeed,
+        r#"
+(defrec d (e)
+  (if (symbol? e)
+    (if (eq e (quote x)) 1 0)
+    (if (eq (car e) (quote add))
+      (list (quote add) (d (car (cdr e))) (d (car (cdr (cdr e)))))
+      (if (eq (car e) (quote mul))
+        (list (quote add)
+          (list (quote mul) (d (car (cdr e))) (car (cdr (cdr e))))
+          (list (quote mul) (car (cdr e)) (d (car (cdr (cdr e))))))
+        0))))
+(def e (d (eval (quote (list mul x y)))))
+(car e)
+(car (car (cdr e)))
+(car (cdr (car (cdr e))))
+(car (cdr (cdr (car (cdr e)))))
+"#,
+        "add\nmul\n1\ny",
+    );
+}
+
+#[test]
+fn test_type() {
+    test_success(
+        "(number? 1)\n(number? 1/2)\n(number? true)",
+        "true\ntrue\nfalse",
+    );
+    test_success("(bool? false)\n(bool? nil)", "true\nfalse");
+    test_success(
+        r#"(string? "a") (char? #\a) (string? #\a)"#,
+        "true\ntrue\nfalse",
+    );
+    test_success("(cons? (list 1))\n(cons? nil)", "true\nfalse");
+    test_success("(nil? nil)\n(nil? (list 1))", "true\nfalse");
+    test_success("(lambda? (lambda (x) x))\n(lambda? +)", "true\nfalse");
+    test_success("(quote? (quote (+ 1 2)))\n(quote? 1)", "true\nfalse");
+    // operands are evaluated
+    test_success("(number? (+ 1 2))", "true");
+    test_success(
+        r#"(type-of 1)
+(type-of 1/2)
+(type-of true)
+(type-of "a")
+(type-of #\a)
+(type-of (quote a))
+(type-of (cons 1 2))
+(type-of nil)
+(type-of (lambda (x) x))
+(type-of (quote (+ 1 2)))
+(type-of car)"#,
+        "number\nnumber\nbool\nstring\nchar\nsymbol\ncons\nnil\nlambda\nquote\noperator",
+    );
+    test_success("(eq (type-of 1) (quote number))", "true");
+    test_success(
+        "(nil? 1 2)",
+        "ERROR: <test>: (line 1 col 2-line 1 col 5): type predicate only accepts 1 term.",
+    );
+    test_success(
+        r#"(import "std/list") (len 1)"#,
+        "ERROR: std/list: (line 5 col 14-line 5 col 16): cdr operator only 1 cons.",
+    );
+}
+
+fn eval_error(source: &str) -> EvalError {
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.do_assert = true;
+    evaluator.limit.max_steps = Some(1000);
+    match parse_string(source.to_string()).and_then(|stmt_vec| evaluator.eval_vec(&stmt_vec)) {
+        Err(e) => e,
+        Ok(_) => panic!("expected error\nsource:{}", source),
+    }
+}
+
+#[test]
+fn test_error_kind() {
+    let printer = SimplePrinter::default();
+    let code_term = |source: &str| {
+        let e = eval_error(source);
+        (e.code(), e.term().map(|term| printer.print(term)))
+    };
+    assert_eq!(("E0001", None), code_term("(+ 1 2"));
+    assert_eq!(("E0002", None), code_term(r#"(import "std/nothing")"#));
+    assert_eq!(("E0003", None), code_term("(+ x 1)"));
+    assert_eq!(("E0004", Some("0".into())), code_term("(/ 1 (- 1 1))"));
+    assert_eq!(
+        ("E0004", Some("(+ 1 true)".into())),
+        code_term("(+ 1 true)")
+    );
+    assert_eq!(("E0004", Some("1".into())), code_term("(if 1 2 3)"));
+    assert_eq!(("E0005", Some("(neg 1 2)".into())), code_term("(neg 1 2)"));
+    assert_eq!(
+        ("E0005", Some("((lambda<1-ary> ARG<0-up 0-th>) 1 2)".into())),
+        code_term("((lambda (x) x) 1 2)")
+    );
+    assert_eq!(
+        ("E0006", Some("(eq 1 2)".into())),
+        code_term("(assert (eq 1 2))")
+    );
+    assert_eq!(("E0007", None), code_term("(defrec f (x) (f x)) (f 1)"));
+    assert!(matches!(eval_error("(/ 1 0)"), EvalError::Type(..)));
+    assert_eq!("type error", eval_error("(/ 1 0)").kind());
+}
+
+#[test]
+fn test_parse_recovery() {
+    let source = r#"(def a 1)
+(def b (+ a 1)
+(def c "(" 2)
+)
+(lambda x)
+(+ a #\) b)
+"#;
+    let mut p = MetaParser::new("<test>".into(), source.chars().collect());
+    let (stmt_vec, error_vec) = p.parse_stmt_vec_recovering();
+    assert_eq!(
+        error_vec
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<String>>(),
+        vec![
+            "<test>: (line 3 col 1): expect def statement end parenthesis: found '('",
+            "<test>: (line 3 col 12): expect def statement end parenthesis: found '2'",
+            "<test>: (line 4 col 1): value expected: found ')'",
+            "<test>: (line 5 col 9): lambda args: expect start parenthesis for args: got 'x'",
+        ]
+    );
+    let mp = MetaPrinter::default();
+    assert_eq!(
+        stmt_vec
+            .iter()
+            .map(|stmt| mp.print_stmt(stmt))
+            .collect::<Vec<String>>(),
+        vec!["(def a 1)", "(+ a #\\) b)"]
+    );
+    assert!(parse_string(source.into()).is_err());
+}
+
+#[test]
+fn test_comment() {
+    let source = r#";; adds one
+(def inc ; the function
+  (lambda (x) #| #| nested |# block |# (+ x 1)))
+#;(inc 0) (inc #;
+  2 1) ; ignored )
+(+ 1 #;(2) 3 #;4)
+"#;
+    assert_eq!(
+        parse_eval_print(source.into(), EvalStrategy::default()).unwrap(),
+        "2\n4"
+    );
+    let mut p = MetaParser::new("<test>".into(), source.chars().collect());
+    assert_eq!(p.parse_stmt_vec().unwrap().len(), 3);
+    assert_eq!(
+        p.comments()
+            .iter()
+            .map(|c| (
+                c.kind.clone(),
+                c.body(),
+                c.info.as_ref().unwrap().to_string()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                CommentKind::Line,
+                "adds one",
+                "<test>: (line 1 col 1-line 1 col 11)".into()
+            ),
+            (
+                CommentKind::Line,
+                "the function",
+                "<test>: (line 2 col 10-line 2 col 23)".into()
+            ),
+            (
+                CommentKind::Block,
+                " #| nested |# block ",
+                "<test>: (line 3 col 15-line 3 col 38)".into()
+            ),
+            (
+                CommentKind::Datum,
+                "(inc 0)",
+                "<test>: (line 4 col 1-line 4 col 9)".into()
+            ),
+            (
+                CommentKind::Datum,
+                "2",
+                "<test>: (line 4 col 16-line 5 col 3)".into()
+            ),
+            (
+                CommentKind::Line,
+                "ignored )",
+                "<test>: (line 5 col 8-line 5 col 18)".into()
+            ),
+            (
+                CommentKind::Datum,
+                "(2)",
+                "<test>: (line 6 col 6-line 6 col 10)".into()
+            ),
+            (
+                CommentKind::Datum,
+                "4",
+                "<test>: (line 6 col 14-line 6 col 16)".into()
+            ),
+        ]
+    );
+    let mut p = MetaParser::new("<test>".into(), "(+ 1 2) #| #| |#".chars().collect());
+    let (stmt_vec, error_vec) = p.parse_stmt_vec_recovering();
+    assert_eq!(stmt_vec.len(), 1);
+    assert_eq!(
+        error_vec[0].to_string(),
+        "<test>: (line 1 col 9): unterminated block comment: found <EOF>"
+    );
+    let mut p = MetaParser::new("<test>".into(), "(def a 1)\n  (foo bar".chars().collect());
+    let (stmt_vec, error_vec) = p.parse_stmt_vec_recovering();
+    assert_eq!(stmt_vec.len(), 1);
+    assert_eq!(
+        error_vec[0].to_string(),
+        "<test>: (line 2 col 3): unclosed parenthesis opened here"
+    );
+    let source = "(foo (bar)\n(def b 2)\n";
+    let mut p = MetaParser::new("<test>".into(), source.chars().collect());
+    let (stmt_vec, error_vec) = p.parse_stmt_vec_recovering();
+    assert_eq!(stmt_vec.len(), 1);
+    assert_eq!(
+        error_vec[0].to_string(),
+        "<test>: (line 1 col 1): unclosed parenthesis opened here"
+    );
+}
+
+fn render_error(source: &str, color: bool) -> String {
+    let source_of = |filepath: &str| (filepath == "<test>").then(|| source.to_string());
+    eval_error(source).diagnostic().render(&source_of, color)
+}
+
+#[test]
+fn test_diagnostic() {
+    assert_eq!(
+        render_error("(def f (lambda (x) x))\n\n\n(f 1 2)", false),
+        "error[E0005]: the lambda function needs 1 args but found 2 arg(s)
+ --> <test>:4:1
+  |
+1 | (def f (lambda (x) x))
+  |        -------------- lambda defined here
+...
+4 | (f 1 2)
+  | ^^^^^^^
+  = note: term: ((lambda<1-ary> ARG<0-up 0-th>) 1 2)
+"
+    );
+    assert_eq!(
+        render_error("(assert\n  (eq 1 2))", false),
+        "error[E0006]: assertion failed
+ --> <test>:1:1
+  |
+1 | (assert
+  | ^^^^^^^
+  = note: term: (eq 1 2)
+"
+    );
+    // a parenthesis left unclosed till the end of file
+    assert_eq!(
+        render_error("(+ 1 2\n", false),
+        "error[E0001]: unclosed parenthesis opened here
+ --> <test>:1:1
+  |
+1 | (+ 1 2
+  | ^
+"
+    );
+    // files without source are shown only by location
+    assert_eq!(
+        render_error(r#"(import "std/nothing")"#, false)
+            .lines()
+            .count(),
+        1
+    );
+    assert_eq!(
+        render_error("(+ x 1)", true),
+        "\x1b[1;31merror[E0003]\x1b[0m\x1b[1m: Variable name \"x\" is not defined variable.\x1b[0m
+ \x1b[1;34m-->\x1b[0m <test>:1:4
+\x1b[1;34m  |\x1b[0m
+\x1b[1;34m1 |\x1b[0m (+ x 1)
+\x1b[1;34m  |\x1b[0m    \x1b[1;31m^\x1b[0m
+"
+    );
+}
+
+#[test]
+fn test_limit() {
+    let spin = "(defrec spin (n) (spin (+ n 1))) (spin 0)";
+    test_limit_exceeded(
+        spin,
+        EvalLimit {
+            max_steps: Some(50),
+            ..Default::default()
+        },
+        Limit::Steps(50),
+    );
+    test_limit_exceeded(
+        "(defrec deep (n) (+ 1 (deep n))) (deep 1000)",
+        EvalLimit {
+            max_depth: Some(50),
+            ..Default::default()
+        },
+        Limit::Depth(50),
+    );
+    // tail calls do not deepen the continuation
+    test_limit_exceeded(
+        "(defrec spin (n) (spin n)) (spin 0)",
+        EvalLimit {
+            max_steps: Some(1000),
+            max_depth: Some(10),
+            ..Default::default()
+        },
+        Limit::Steps(1000),
+    );
+    // arguments passed on as variables share their thunks rather than growing chains,
+    // so each step of a deep loop takes constant time under the default call-by-name
+    test_limit_exceeded(
+        "(defrec spin (n) (spin n)) (spin 0)",
+        EvalLimit {
+            max_steps: Some(20000),
+            timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
+        },
+        Limit::Steps(20000),
+    );
+    test_limit_exceeded(
+        spin,
+        EvalLimit {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        },
+        Limit::Timeout(Duration::ZERO),
+    );
+}
+
+#[test]
+fn test_deep_recursion() {
+    test_success(
+        "(defrec deep (n) (if (eq n 0) 0 (+ 1 (deep (- n 1))))) (deep 1000)",
+        "1000",
+    );
+}
+
+#[test]
+fn test_strategy() {
+    use EvalStrategy::*;
+    for strategy in [CallByName, CallByValue, CallByNeed] {
+        test_success_with(strategy, "((lambda (a b) (+ a (+ b 1))) 10 100)", "111");
+        test_success_with(strategy, "(import \"std\") (factorial 5)", "120");
+        test_success_with(strategy, "(import \"std\") (is_prime 3907)", "true");
+    }
+    // infinite lists need the parts taken from them to be evaluated
+    for strategy in [CallByValue, CallByNeed] {
+        test_success_with(
+            strategy,
+            "(import \"std\") (index 3 (iterate (lambda (n) (* n 2)) 1))",
+            "8",
+        );
+        test_success_with(
+            strategy,
+            "(import \"std\") (index 2 (take 3 (list 1 2 3 4 5)))",
+            "3",
+        );
+        test_success_with(
+            strategy,
+            "(import \"std\") (len (take 4 (iterate (lambda (n) (+ n 1)) 0)))",
+            "4",
+        );
+    }
+
+    let taken = "(car (cons (+ 1 1) 0))";
+    test_success_with(CallByName, taken, "(+ 1 1)");
+    test_success_with(CallByValue, taken, "2");
+    test_success_with(CallByNeed, taken, "2");
+
+    let unevaluated = "((lambda (n) (quote (+ 1 n))) (+ 2 2))";
+    test_success_with(CallByName, unevaluated, "(quote (+ 1 (+ 2 2)))");
+    test_success_with(CallByValue, unevaluated, "(quote (+ 1 4))");
+    test_success_with(CallByNeed, unevaluated, "(quote (+ 1 (+ 2 2)))");
+
+    let forced = "((lambda (n) (if (eq n 4) (quote n) 0)) (+ 2 2))";
+    test_success_with(CallByName, forced, "(quote (+ 2 2))");
+    test_success_with(CallByValue, forced, "(quote 4)");
+    test_success_with(CallByNeed, forced, "(quote 4)");
+
+    let unused = "((lambda (n) 0) (/ 1 0))";
+    test_success_with(CallByName, unused, "0");
+    test_success_with(
+        CallByValue,
+        unused,
+        "ERROR: <test>: (line 1 col 22): division operator got 0 for divisor.",
+    );
+    test_success_with(CallByNeed, unused, "0");
+}
+
+fn normalize_print(source: &str, limit: EvalLimit, eta: bool) -> Result<String, EvalError> {
+    let mut p = MetaParser::new("<test>".into(), source.chars().collect());
+    let term = p.parse_term().map_err(EvalError::Parse)?;
+    let term =
+        transform_to_core(&MetaEvaluator::default().env, &term).map_err(EvalError::Transform)?;
+    let printer = SimplePrinter::default();
+    Ok(printer.print(&normalize(&term, &limit, eta)?))
+}
+
+fn test_normalize_success(source: &str, eta: bool, want: &str) {
+    let got = match normalize_print(source, EvalLimit::default(), eta) {
+        Ok(out) => out,
+        Err(err) => format!("ERROR: {}", err),
+    };
+    assert_eq!(want, got, "\neta: {}\nsource:{}", eta, source);
+}
+
+#[test]
+fn test_normalize() {
+    test_normalize_success(
+        "(lambda (x) ((lambda (y) y) x))",
+        false,
+        "(lambda<1-ary> ARG<0-up 0-th>)",
+    );
+    test_normalize_success(
+        "(lambda (x) (symbol? (quote a)))",
+        false,
+        "(lambda<1-ary> true)",
+    );
+    test_normalize_success("(type-of (lambda (x) x))", false, "lambda");
+    // not known until the argument is given
+    test_normalize_success(
+        "(lambda (x) (symbol? x))",
+        false,
+        "(lambda<1-ary> (symbol? ARG<0-up 0-th>))",
+    );
+    // free variables of the body are shifted down
+    test_normalize_success(
+        "(lambda (x) ((lambda (y) (lambda (z) x)) 1))",
+        false,
+        "(lambda<1-ary> (lambda<1-ary> ARG<1-up 0-th>))",
+    );
+    test_normalize_success(
+        "(lambda (x) (+ x (+ 1 2)))",
+        false,
+        "(lambda<1-ary> (+ ARG<0-up 0-th> 3))",
+    );
+    test_normalize_success(
+        "(lambda (x) (eq x 1))",
+        false,
+        "(lambda<1-ary> (eq ARG<0-up 0-th> 1))",
+    );
+    // operators failing on the operands are left, since the branch may never be taken
+    test_normalize_success(
+        "(lambda (x) (if x 1 (/ 1 0)))",
+        false,
+        "(lambda<1-ary> (if ARG<0-up 0-th> 1 (/ 1 0)))",
+    );
+    test_success(
+        "(beta-eq (lambda (x) (if x 1 (/ 1 0))) (lambda (x) (if x 1 (/ 1 0))))",
+        "true",
+    );
+    // 2 + 2 in church numerals
+    test_normalize_success(
+        "((lambda (m n) (lambda (f x) (m f (n f x)))) (lambda (f x) (f (f x))) (lambda (f x) (f (f x))))",
+        false,
+        "(lambda<2-ary> (ARG<0-up 0-th> (ARG<0-up 0-th> (ARG<0-up 0-th> (ARG<0-up 0-th> ARG<0-up 1-th>)))))",
+    );
+    test_normalize_success(
+        "(lambda (a b) (+ a b))",
+        false,
+        "(lambda<2-ary> (+ ARG<0-up 0-th> ARG<0-up 1-th>))",
+    );
+    test_normalize_success("(lambda (a b) (+ a b))", true, "+");
+    test_normalize_success(
+        "(lambda (f) (lambda (x) (f x)))",
+        true,
+        "(lambda<1-ary> ARG<0-up 0-th>)",
+    );
+    test_normalize_success(
+        "(lambda (x) (quote ((lambda (y) y) x)))",
+        false,
+        "(lambda<1-ary> (quote ((lambda<1-ary> ARG<0-up 0-th>) ARG<0-up 0-th>)))",
+    );
+
+    let omega = "((lambda (x) (x x)) (lambda (x) (x x)))";
+    let limit = EvalLimit {
+        max_steps: Some(100),
+        ..Default::default()
+    };
+    match normalize_print(omega, limit, false) {
+        Err(EvalError::FuelExhausted(_, got)) => assert_eq!(Limit::Steps(100), got),
+        got => panic!("expected fuel exhausted: {:?}", got),
+    }
+
+    test_success("(beta-eq (lambda (x) (+ 1 1)) (lambda (x) 2))", "true");
+    test_success("(eq (lambda (x) (+ 1 1)) (lambda (x) 2))", "false");
+    test_success(
+        "(beta-eq (lambda (f) (lambda (x) (f x))) (lambda (f) f))",
+        "true",
+    );
+    test_success(
+        "(beta-eq (lambda (x) (+ x 1)) (lambda (x) (+ 1 x)))",
+        "false",
+    );
+    test_success(
+        "(beta-eq 1)",
+        "ERROR: <test>: (line 1 col 2-line 1 col 8): beta-eq operator only accepts 2 terms.",
+    );
+    test_limit_exceeded(
+        &format!("(beta-eq (lambda (y) {}) 1)", omega),
+        EvalLimit {
+            max_steps: Some(100),
+            ..Default::default()
+        },
+        Limit::Steps(100),
+    );
+}
+
+struct CollectTracer {
+    max_depth: usize,
+    step_vec: Rc<RefCell<Vec<String>>>,
+}
+
+impl Tracer for CollectTracer {
+    fn accepts(&self, depth: usize) -> bool {
+        depth <= self.max_depth
+    }
+
+    fn trace(&mut self, step: &TraceStep) {
+        let printer = SimplePrinter::default();
+        self.step_vec.borrow_mut().push(format!(
+            "{} {}: {} => {}",
+            step.depth,
+            step.rule,
+            printer.print(&step.redex),
+            printer.print(&step.result)
+        ));
+    }
+}
+
+fn trace_steps(source: &str, strategy: EvalStrategy, max_depth: usize) -> Vec<String> {
+    let step_vec = Rc::new(RefCell::new(Vec::new()));
+    let mut evaluator = MetaEvaluator::default();
+    evaluator.strategy = strategy;
+    evaluator.tracer = Some(Box::new(CollectTracer {
+        max_depth,
+        step_vec: step_vec.clone(),
+    }));
+    evaluator
+        .eval_vec(&parse_string(source.to_string()).unwrap())
+        .unwrap();
+    step_vec.take()
+}
+
+#[test]
+fn test_trace() {
+    let source = "((lambda (n) (if (eq n 0) 1 (* n 2))) (+ 1 2))";
+    assert_eq!(
+        trace_steps(source, EvalStrategy::CallByName, usize::MAX),
+        vec![
+            "0 beta: ((lambda<1-ary> (if (eq ARG<0-up 0-th> 0) 1 (* ARG<0-up 0-th> 2))) (+ 1 2)) => (if (eq (+ 1 2) 0) 1 (* (+ 1 2) 2))",
+            "2 delta-add: (+ 1 2) => 3",
+            "1 delta-eq: (eq 3 0) => false",
+            "0 if-false: (if false 1 (* (+ 1 2) 2)) => (* (+ 1 2) 2)",
+            "1 delta-add: (+ 1 2) => 3",
+            "0 delta-mul: (* 3 2) => 6",
+        ]
+    );
+    assert_eq!(
+        trace_steps(source, EvalStrategy::CallByValue, 0),
+        vec![
+            "0 beta: ((lambda<1-ary> (if (eq ARG<0-up 0-th> 0) 1 (* ARG<0-up 0-th> 2))) 3) => (if (eq 3 0) 1 (* 3 2))",
+            "0 if-false: (if false 1 (* 3 2)) => (* 3 2)",
+            "0 delta-mul: (* 3 2) => 6",
+        ]
+    );
+
+    let tracer = PrintTracer::new(TraceOption {
+        format: TraceFormat::Json,
+        max_depth: None,
+    });
+    assert_eq!(
+        tracer.format(&TraceStep {
+            step: 1,
+            depth: 0,
+            rule: Rule::Delta("car"),
+            redex: Term::Apply(
+                None,
+                Box::new(Term::Car(None)),
+                vec![Term::Cons(
+                    None,
+                    Box::new(Term::Number(None, 1u32.into())),
+                    Box::new(Term::Nil(None))
+                )]
+            ),
+            result: Term::Number(None, 1u32.into()),
+        }),
+        r#"{"step":1,"depth":0,"rule":"delta-car","redex":"(car (cons 1 nil))","result":"1"}"#
+    );
+}
+
+fn debug_output(source: &str, commands: &str) -> String {
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let input = Box::new(std::io::Cursor::new(commands.to_string()));
+    let result = debug_source(
+        "<test>".into(),
+        source,
+        EvalLimit::default(),
+        EvalStrategy::default(),
+        input,
+        output.clone(),
+    );
+    let mut output = String::from_utf8(output.take()).unwrap();
+    if let Err(err) = result {
+        output.push_str(&format!("ERROR: {}\n", err));
+    }
+    output
+}
+
+#[test]
+fn test_debug() {
+    let source = "(def double (lambda (n) (* n 2)))
+(defrec count (n acc)
+  (if (eq n 0) acc (count (- n 1) (+ acc 1))))
+(double (count 2 0))";
+    assert_eq!(
+        debug_output(
+            source,
+            "break <test>:3\ncontinue\nbindings\nnext\nredex\ndelete <test>:3\ncontinue\n"
+        ),
+        "(debug) breakpoint at <test>:3
+(debug) stopped at <test>: (line 3 col 7-line 3 col 14)
+  (eq n 0)
+(debug) #0 lambda at <test>: (line 2 col 9-line 2 col 13)
+  count = <delayed> (lambda (count n acc) (if (eq n 0) acc (count count (- n 1) (+ acc 1))))
+  n = <delayed> 2
+  acc = <delayed> 0
+#1 lambda at <test>: (line 2 col 9-line 3 col 45)
+  n = <delayed> 2
+  acc = <delayed> 0
+(debug) stopped at <test>: (line 3 col 20-line 3 col 44)
+  (count count (- n 1) (+ acc 1))
+(debug) (count count (- n 1) (+ acc 1))
+(debug) (debug) Out[2] = 4
+"
+    );
+    // functions of the same arity in one defrec have their own names
+    let mutual = "(defrec even? (n) (if (eq n 0) true (odd? (- n 1)))
+  odd? (m)
+  (if (eq m 0) false (even? (- m 1))))
+(even? 1)";
+    assert_eq!(
+        debug_output(mutual, "break <test>:3\ncontinue\nbindings\nc\n"),
+        "(debug) breakpoint at <test>:3
+(debug) stopped at <test>: (line 3 col 7-line 3 col 14)
+  (eq m 0)
+(debug) #0 lambda at <test>: (line 2 col 3-line 2 col 6)
+  even? = <delayed> (lambda (even? odd? n) (if (eq n 0) true (odd? even? odd? (- n 1))))
+  odd? = <delayed> (lambda (even? odd? m) (if (eq m 0) false (even? even? odd? (- m 1))))
+  m = <delayed> (- n 1)
+#1 lambda at <test>: (line 1 col 9-line 2 col 2)
+  n = <delayed> 1
+(debug) Out[1] = false
+"
+    );
+    // call-by-name arguments are shown before evaluated
+    assert_eq!(
+        debug_output(
+            "((lambda (x y) (+ x y)) 1 (+ 1 1))",
+            "step\nstep\nbindings\nfoo\nc\n"
+        ),
+        "(debug) stopped at <test>: (line 1 col 1-line 1 col 34)
+  ((lambda (x y) (+ x y)) 1 (+ 1 1))
+(debug) stopped at <test>: (line 1 col 16-line 1 col 22)
+  (+ x y)
+(debug) #0 lambda at <test>: (line 1 col 2-line 1 col 23)
+  x = <delayed> 1
+  y = <delayed> (+ 1 1)
+(debug) unknown command \"foo\". see help
+(debug) Out[0] = 3
+"
+    );
+    assert!(debug_output(source, "step\nquit\n")
+        .ends_with("ERROR: <test>: (line 4 col 1-line 4 col 20): evaluation quit by debugger\n"));
+}
+
+#[test]
+fn test_repl() {
+    // terms wait for closing parentheses, except those in strings, chars and comments
+    assert!(is_incomplete("(def x\n"));
+    assert!(!is_incomplete("(def x\n  1)\n"));
+    assert!(is_incomplete("(string-length \")\"\n"));
+    assert!(!is_incomplete("(string-length \"(\\\"\")\n"));
+    assert!(!is_incomplete("(char->integer #\\()\n"));
+    assert!(is_incomplete("(+ 1 ; )\n"));
+    assert!(!is_incomplete("(+ 1 #| ( |# 2)\n"));
+    assert!(!is_incomplete("(+ 1 #;(2 3) 4)\n"));
+    // commands are always one line
+    assert!(!is_incomplete(":expand (when true\n"));
+
+    let mut repl = Repl::new(
+        false,
+        EvalLimit::default(),
+        EvalStrategy::default(),
+        None,
+        false,
+    );
+    let defined = |repl: &Repl, name: &str| repl.evaluator().env.contains_key(name);
+    assert!(repl.handle("(def x 1)"));
+    // definitions before an error are kept, and the session goes on after errors
+    assert!(repl.handle("(def y (+ x 1)) (car 1) (def z 3)"));
+    assert!(defined(&repl, "y") && !defined(&repl, "z"));
+    assert!(repl.handle("(def z (+ y"));
+    assert!(repl.handle(":unknown"));
+    assert!(!defined(&repl, "z"));
+    assert!(repl.handle("(def z (+ y 1))"));
+    assert!(defined(&repl, "z"));
+    assert!(repl.handle(":reset"));
+    assert!(!defined(&repl, "x") && !defined(&repl, "z"));
+    assert!(repl.handle("(def x 2)"));
+    assert!(defined(&repl, "x"));
+    assert!(!repl.handle(":quit"));
+}